@@ -0,0 +1,6 @@
+//! The grammar of the RTD EZO chip, built without `std`. Its tests still
+//! need `std`, so they run on the host.
+#![cfg_attr(not(test), no_std)]
+
+#[path = "../../src/grammar.rs"]
+pub mod grammar;
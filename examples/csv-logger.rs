@@ -0,0 +1,46 @@
+//! An example that logs the readings of the RTD EZO chip to daily CSV files,
+//! keeping those of the last 90 days.
+//!
+extern crate ezo_rtd;
+extern crate failure;
+
+use std::path::Path;
+use std::time::Duration;
+
+use ezo_rtd::cancel::CancelToken;
+use ezo_rtd::logger::{CsvLogger, Datalogger, ErrorPolicy, Rotating, Rotation};
+use ezo_rtd::sensor::RtdSensor;
+use failure::{Error, ResultExt};
+
+const I2C_BUS_ID: u8 = 1;
+const EZO_SENSOR_ADDR: u16 = 101; // could be specified as 0x65
+const LOG_PATH: &str = "readings.csv"; // e.g. readings-2018-07-14.csv
+const LOG_DAYS: usize = 90;
+
+fn run() -> Result<(), Error> {
+    let mut sensor = RtdSensor::builder()
+        .bus(I2C_BUS_ID)
+        .address(EZO_SENSOR_ADDR)
+        .build()
+        .context("Could not set up the RTD sensor")?;
+
+    let files = Rotating::new(LOG_PATH, Rotation::Daily, |path: &Path| {
+        CsvLogger::append(path)
+    })
+    .max_files(LOG_DAYS);
+    let mut logger = Datalogger::new(files).on_error(ErrorPolicy::Skip);
+
+    // Runs until the process is stopped; cancel the token from another
+    // thread, e.g. a signal handler, to stop cleanly.
+    let cancel = CancelToken::new();
+    logger.run(&mut sensor, Duration::from_secs(10), &cancel)?;
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(ref e) = run() {
+        println!("error: {}", e);
+        ::std::process::exit(1);
+    }
+}
@@ -2,66 +2,55 @@
 //!
 extern crate ezo_rtd;
 extern crate failure;
-extern crate i2cdev;
 
 use ezo_rtd::command::{
-    CalibrationState, Command, DataloggerInterval, DeviceInformation, Export, ExportInfo, LedState,
-    ReadingWithScale, ScaleCelsius, ScaleFahrenheit, ScaleKelvin, Sleep, Status,
-};
-use ezo_rtd::response::{
-    CalibrationStatus, DataLoggerStorageIntervalSeconds, DeviceInfo, DeviceStatus, Exported,
-    ExportedInfo, LedStatus,
+    DeviceInformation, Export, ExportInfo, ReadingWithScale, ScaleCelsius, ScaleFahrenheit,
+    ScaleKelvin,
 };
+use ezo_rtd::response::{DeviceInfo, Exported, ExportedInfo};
+use ezo_rtd::sensor::RtdSensor;
 use failure::{Error, ResultExt};
-use i2cdev::linux::LinuxI2CDevice;
 
 const I2C_BUS_ID: u8 = 1;
 const EZO_SENSOR_ADDR: u16 = 101; // could be specified as 0x65
 
 fn run() -> Result<(), Error> {
-    let device_path = format!("/dev/i2c-{}", I2C_BUS_ID);
-    let mut dev =
-        LinuxI2CDevice::new(&device_path, EZO_SENSOR_ADDR).context("Could not open I2C device")?;
+    let mut sensor = RtdSensor::builder()
+        .bus(I2C_BUS_ID)
+        .address(EZO_SENSOR_ADDR)
+        .build()
+        .context("Could not open I2C device")?;
 
-    let info: DeviceInfo = DeviceInformation.run(&mut dev)?;
+    let info: DeviceInfo = sensor.run(DeviceInformation)?;
     println!("{:?}", info);
 
-    let status: DeviceStatus = Status.run(&mut dev)?;
-    println!("DeviceStatus: {:?}", status);
-
-    let calibration: CalibrationStatus = CalibrationState.run(&mut dev)?;
-    println!("CalibrationState: {:?}", calibration);
-
-    let datalog_period: DataLoggerStorageIntervalSeconds = DataloggerInterval.run(&mut dev)?;
-    println!("{:?}", datalog_period);
-
-    let led_status: LedStatus = LedState.run(&mut dev)?;
-    println!("LedState: {:?}", led_status);
+    let summary = sensor.summary()?;
+    println!("{:?}", summary);
 
-    let ExportedInfo { lines, total_bytes } = ExportInfo.run(&mut dev)?;
+    let ExportedInfo { lines, total_bytes } = sensor.run(ExportInfo)?;
     println!("ExportInfo: #lines {}, #bytes {}", lines, total_bytes);
 
     for _ in 0..=lines {
-        let exports: Exported = Export.run(&mut dev)?;
+        let exports: Exported = sensor.run(Export)?;
         println!("Exported: {:?}", exports);
     }
 
-    let _kelvin = ScaleKelvin.run(&mut dev)?;
+    let _kelvin = sensor.run(ScaleKelvin)?;
     println!("Scale set to KELVIN");
 
-    let temperature = ReadingWithScale.run(&mut dev)?;
+    let temperature = sensor.run(ReadingWithScale)?;
     println!("{:?}", temperature);
 
-    let _fahrenheit = ScaleFahrenheit.run(&mut dev)?;
+    let _fahrenheit = sensor.run(ScaleFahrenheit)?;
     println!("Scale set to FAHRENHEIT");
 
-    let temperature = ReadingWithScale.run(&mut dev)?;
+    let temperature = sensor.run(ReadingWithScale)?;
     println!("{:?}", temperature);
 
-    let _celsius = ScaleCelsius.run(&mut dev)?;
+    let _celsius = sensor.run(ScaleCelsius)?;
     println!("Scale set to CELSIUS");
 
-    let _reading = match ReadingWithScale.run(&mut dev) {
+    let _reading = match sensor.run(ReadingWithScale) {
         Ok(temperature) => println!("{:?}", temperature),
 
         Err(e) => {
@@ -69,7 +58,7 @@ fn run() -> Result<(), Error> {
         }
     };
 
-    let _sleep = Sleep.run(&mut dev)?;
+    sensor.sleep()?;
     println!("Sleeping....");
 
     Ok(())
@@ -0,0 +1,293 @@
+//! Alarms on temperatures that leave a range.
+//!
+//! An `AlarmMonitor` checks each temperature against the limits of a
+//! `TemperatureRange`, and raises an `AlarmEvent` when a temperature
+//! breaches a limit, and again when it is back within the limits. Events
+//! are returned by `check()`, handed to the callbacks given to
+//! `on_event()`, and sent to the channels given to `notify()`.
+//!
+//! To keep a temperature hovering around a limit from raising a storm of
+//! alarms, the monitor can be given a hysteresis band, which a temperature
+//! must clear to recover, and a debounce time, during which a new state
+//! must hold before its event is raised.
+use std::fmt;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use super::response::{Temperature, TemperatureDelta, TemperatureRange, TemperatureScale};
+
+/// Whether the latest temperature is within the limits of an alarm.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AlarmState {
+    /// Within the limits.
+    Normal,
+    /// Below the low limit.
+    Low,
+    /// Above the high limit.
+    High,
+}
+
+/// A change of the state of an alarm, with the temperature that caused it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AlarmEvent {
+    /// The temperature went below the low limit.
+    Low(Temperature),
+    /// The temperature went above the high limit.
+    High(Temperature),
+    /// The temperature is back within the limits.
+    Recovered(Temperature),
+}
+
+impl AlarmEvent {
+    /// Returns the temperature that caused the event.
+    pub fn temperature(&self) -> Temperature {
+        match *self {
+            AlarmEvent::Low(t) | AlarmEvent::High(t) | AlarmEvent::Recovered(t) => t,
+        }
+    }
+
+    /// Returns the state of the alarm after the event.
+    pub fn state(&self) -> AlarmState {
+        match *self {
+            AlarmEvent::Low(_) => AlarmState::Low,
+            AlarmEvent::High(_) => AlarmState::High,
+            AlarmEvent::Recovered(_) => AlarmState::Normal,
+        }
+    }
+}
+
+type Handler = Box<dyn FnMut(&AlarmEvent) + Send>;
+
+/// Raises alarms on temperatures outside of a range.
+///
+/// The alarm starts in the `Normal` state, so a first temperature outside
+/// of the range raises an alarm, but one within it raises nothing. By
+/// default, there is neither hysteresis nor debounce.
+pub struct AlarmMonitor {
+    range: TemperatureRange,
+    hysteresis: TemperatureDelta,
+    debounce: Duration,
+    state: AlarmState,
+    pending: Option<(AlarmState, Instant)>,
+    handlers: Vec<Handler>,
+}
+
+impl AlarmMonitor {
+    /// Raises alarms on temperatures outside of `range`.
+    pub fn new(range: TemperatureRange) -> AlarmMonitor {
+        AlarmMonitor {
+            range,
+            hysteresis: TemperatureDelta::new(TemperatureScale::Celsius, 0.0),
+            debounce: Duration::from_secs(0),
+            state: AlarmState::Normal,
+            pending: None,
+            handlers: Vec::new(),
+        }
+    }
+
+    /// Recovers from a low alarm only once the temperature is `hysteresis`
+    /// above the low limit, and from a high alarm once it is `hysteresis`
+    /// below the high limit. The band should be narrower than the range.
+    pub fn hysteresis(mut self, hysteresis: TemperatureDelta) -> AlarmMonitor {
+        self.hysteresis = hysteresis.abs();
+        self
+    }
+
+    /// Raises an event only once its state held for at least `debounce`,
+    /// so that brief excursions, and brief recoveries, raise nothing.
+    pub fn debounce(mut self, debounce: Duration) -> AlarmMonitor {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Calls `handler` with every event raised.
+    pub fn on_event<F>(mut self, handler: F) -> AlarmMonitor
+    where
+        F: FnMut(&AlarmEvent) + Send + 'static,
+    {
+        self.handlers.push(Box::new(handler));
+        self
+    }
+
+    /// Sends every event raised to `sender`, for as long as its receiver
+    /// is alive.
+    pub fn notify(self, sender: Sender<AlarmEvent>) -> AlarmMonitor {
+        self.on_event(move |event| {
+            let _ = sender.send(*event);
+        })
+    }
+
+    /// Returns the limits of the alarm.
+    pub fn range(&self) -> TemperatureRange {
+        self.range
+    }
+
+    /// Returns the state of the alarm, after the latest temperature.
+    pub fn state(&self) -> AlarmState {
+        self.state
+    }
+
+    /// Checks `temperature` against the limits, returning the event it
+    /// raised, if any, after handing it to the callbacks.
+    ///
+    /// A temperature that is not a number, e.g. from a failed conversion,
+    /// is ignored.
+    pub fn check(&mut self, temperature: Temperature) -> Option<AlarmEvent> {
+        self.check_at(temperature, Instant::now())
+    }
+
+    /// Checks `temperature`, read at `at`, as `check()` does. Readings are
+    /// expected in order.
+    pub fn check_at(&mut self, temperature: Temperature, at: Instant) -> Option<AlarmEvent> {
+        if temperature.value().is_nan() {
+            return None;
+        }
+        let state = self.classify(temperature);
+        if state == self.state {
+            self.pending = None;
+            return None;
+        }
+        let since = match self.pending {
+            Some((pending, since)) if pending == state => since,
+            _ => {
+                self.pending = Some((state, at));
+                at
+            }
+        };
+        if at.saturating_duration_since(since) < self.debounce {
+            return None;
+        }
+        self.pending = None;
+        self.state = state;
+
+        let event = match state {
+            AlarmState::Low => AlarmEvent::Low(temperature),
+            AlarmState::High => AlarmEvent::High(temperature),
+            AlarmState::Normal => AlarmEvent::Recovered(temperature),
+        };
+        for handler in self.handlers.iter_mut() {
+            handler(&event);
+        }
+        Some(event)
+    }
+
+    // The state `temperature` puts the alarm in, before debouncing. Within
+    // the hysteresis band, an alarm stays as it is.
+    fn classify(&self, temperature: Temperature) -> AlarmState {
+        if temperature < self.range.low {
+            return AlarmState::Low;
+        }
+        if temperature > self.range.high {
+            return AlarmState::High;
+        }
+        match self.state {
+            AlarmState::Low if temperature < self.range.low + self.hysteresis => AlarmState::Low,
+            AlarmState::High if temperature > self.range.high - self.hysteresis => AlarmState::High,
+            _ => AlarmState::Normal,
+        }
+    }
+}
+
+impl fmt::Debug for AlarmMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AlarmMonitor")
+            .field("range", &self.range)
+            .field("hysteresis", &self.hysteresis)
+            .field("debounce", &self.debounce)
+            .field("state", &self.state)
+            .field("handlers", &self.handlers.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::Float;
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+
+    fn range() -> TemperatureRange {
+        TemperatureRange::new(Temperature::Celsius(20.0), Temperature::Celsius(30.0))
+    }
+
+    #[test]
+    fn raises_breaches_and_recoveries() {
+        let mut monitor = AlarmMonitor::new(range());
+        assert_eq!(monitor.check(Temperature::Celsius(25.0)), None);
+        assert_eq!(
+            monitor.check(Temperature::Celsius(31.0)),
+            Some(AlarmEvent::High(Temperature::Celsius(31.0)))
+        );
+        assert_eq!(monitor.check(Temperature::Celsius(35.0)), None);
+        assert_eq!(monitor.state(), AlarmState::High);
+        assert_eq!(
+            monitor.check(Temperature::Celsius(19.0)),
+            Some(AlarmEvent::Low(Temperature::Celsius(19.0)))
+        );
+        assert_eq!(monitor.check(Temperature::Celsius(Float::NAN)), None);
+        assert_eq!(
+            monitor.check(Temperature::Fahrenheit(77.0)),
+            Some(AlarmEvent::Recovered(Temperature::Fahrenheit(77.0)))
+        );
+    }
+
+    #[test]
+    fn hands_events_to_callbacks_and_channels() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (sender, receiver) = mpsc::channel();
+        let mut monitor = AlarmMonitor::new(range())
+            .on_event({
+                let seen = Arc::clone(&seen);
+                move |event| seen.lock().unwrap().push(event.state())
+            })
+            .notify(sender);
+
+        monitor.check(Temperature::Celsius(15.0));
+        monitor.check(Temperature::Celsius(25.0));
+        assert_eq!(*seen.lock().unwrap(), [AlarmState::Low, AlarmState::Normal]);
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            [
+                AlarmEvent::Low(Temperature::Celsius(15.0)),
+                AlarmEvent::Recovered(Temperature::Celsius(25.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn recovers_past_the_hysteresis_band() {
+        let mut monitor = AlarmMonitor::new(range())
+            .hysteresis(TemperatureDelta::new(TemperatureScale::Celsius, 1.0));
+        assert!(monitor.check(Temperature::Celsius(30.5)).is_some());
+        assert_eq!(monitor.check(Temperature::Celsius(29.5)), None);
+        assert_eq!(monitor.check(Temperature::Celsius(30.2)), None);
+        assert_eq!(
+            monitor.check(Temperature::Celsius(28.9)),
+            Some(AlarmEvent::Recovered(Temperature::Celsius(28.9)))
+        );
+
+        assert!(monitor.check(Temperature::Celsius(19.9)).is_some());
+        assert_eq!(monitor.check(Temperature::Celsius(20.5)), None);
+        assert_eq!(monitor.state(), AlarmState::Low);
+    }
+
+    #[test]
+    fn raises_events_that_held_for_the_debounce_time() {
+        let start = Instant::now();
+        let secs = |n| start + Duration::from_secs(n);
+        let mut monitor = AlarmMonitor::new(range()).debounce(Duration::from_secs(10));
+
+        // A brief excursion raises nothing.
+        assert_eq!(monitor.check_at(Temperature::Celsius(31.0), secs(0)), None);
+        assert_eq!(monitor.check_at(Temperature::Celsius(25.0), secs(5)), None);
+        assert_eq!(monitor.check_at(Temperature::Celsius(31.0), secs(8)), None);
+        assert_eq!(monitor.check_at(Temperature::Celsius(32.0), secs(12)), None);
+        assert_eq!(
+            monitor.check_at(Temperature::Celsius(33.0), secs(18)),
+            Some(AlarmEvent::High(Temperature::Celsius(33.0)))
+        );
+        assert_eq!(monitor.check_at(Temperature::Celsius(25.0), secs(20)), None);
+        assert_eq!(monitor.state(), AlarmState::High);
+    }
+}
@@ -0,0 +1,147 @@
+//! In-memory history of the latest readings of a sensor.
+//!
+//! A `ReadingBuffer` keeps a fixed number of timestamped readings, dropping
+//! the oldest one as a new one is pushed. It is a `Publish` destination of
+//! a `Sampler`, and its clones share the same readings, so that e.g. a web
+//! dashboard can serve the latest readings without any file I/O.
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::vec;
+
+use super::response::Temperature;
+use super::sampler::Publish;
+use super::sensor::TimestampedReading;
+
+use chrono::Utc;
+
+/// A fixed-capacity buffer of the latest readings, shared by its clones.
+#[derive(Clone, Debug)]
+pub struct ReadingBuffer {
+    capacity: usize,
+    readings: Arc<Mutex<VecDeque<TimestampedReading>>>,
+}
+
+impl ReadingBuffer {
+    /// Keeps up to `capacity` readings, and at least one.
+    pub fn new(capacity: usize) -> ReadingBuffer {
+        let capacity = capacity.max(1);
+        ReadingBuffer {
+            capacity,
+            readings: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    /// Returns the most readings kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of readings kept so far.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if no reading was pushed yet.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Adds a reading, dropping the oldest one if the buffer is full.
+    pub fn push(&self, reading: TimestampedReading) {
+        let mut readings = self.lock();
+        if readings.len() == self.capacity {
+            readings.pop_front();
+        }
+        readings.push_back(reading);
+    }
+
+    /// Drops every reading.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Returns the latest reading, if any.
+    pub fn latest(&self) -> Option<TimestampedReading> {
+        self.lock().back().copied()
+    }
+
+    /// Returns a copy of the readings, from the oldest to the latest.
+    pub fn snapshot(&self) -> Vec<TimestampedReading> {
+        self.lock().iter().copied().collect()
+    }
+
+    /// Returns a copy of the latest `n` readings, from the oldest to the
+    /// latest.
+    pub fn recent(&self, n: usize) -> Vec<TimestampedReading> {
+        let readings = self.lock();
+        let skip = readings.len().saturating_sub(n);
+        readings.iter().skip(skip).copied().collect()
+    }
+
+    /// Iterates over a snapshot of the readings, from the oldest to the
+    /// latest, so that the buffer is not locked while iterating.
+    pub fn iter(&self) -> vec::IntoIter<TimestampedReading> {
+        self.snapshot().into_iter()
+    }
+
+    // A sampler that panicked while pushing leaves whole readings behind,
+    // so the buffer is still usable.
+    fn lock(&self) -> MutexGuard<'_, VecDeque<TimestampedReading>> {
+        self.readings.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<'a> IntoIterator for &'a ReadingBuffer {
+    type Item = TimestampedReading;
+    type IntoIter = vec::IntoIter<TimestampedReading>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Pushes every temperature, timestamped as it is published. The sampler
+/// keeps running for as long as it is not shut down.
+impl Publish for ReadingBuffer {
+    fn publish(&mut self, temperature: Temperature) -> bool {
+        self.push(TimestampedReading {
+            timestamp: Utc::now(),
+            temperature,
+        });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::{Float, TemperatureScale};
+
+    fn values(readings: &[TimestampedReading]) -> Vec<Float> {
+        readings
+            .iter()
+            .map(|reading| reading.temperature.value())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_latest_readings() {
+        let buffer = ReadingBuffer::new(3);
+        let mut publisher = buffer.clone();
+        for value in 0..5 {
+            let temperature = Temperature::new(TemperatureScale::Celsius, value as Float);
+            assert!(publisher.publish(temperature));
+        }
+
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(values(&buffer.snapshot()), [2.0, 3.0, 4.0]);
+        assert_eq!(values(&buffer.recent(2)), [3.0, 4.0]);
+        assert_eq!(values(&buffer.recent(10)), [2.0, 3.0, 4.0]);
+        assert_eq!(buffer.latest().unwrap().temperature.value(), 4.0);
+        assert_eq!(buffer.iter().count(), 3);
+
+        buffer.clear();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.latest(), None);
+    }
+}
@@ -0,0 +1,56 @@
+//! Cooperative cancellation of long command sequences.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::errors::{RtdError, RtdErrorKind};
+
+/// Token used to abort a sequence of commands from another thread.
+///
+/// The token is checked between commands, never during one, so that a
+/// cancelled sequence does not leave an I2C transaction half done. Clones
+/// share the same state.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Creates a token that is not cancelled.
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Requests the cancellation of the sequences using this token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns `true` once `cancel()` has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns an `RtdErrorKind::Cancelled` error once `cancel()` has been
+    /// called.
+    pub fn check(&self) -> Result<(), RtdError> {
+        if self.is_cancelled() {
+            return Err(RtdErrorKind::Cancelled.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_cancellation() {
+        let token = CancelToken::new();
+        let other = token.clone();
+        assert!(token.check().is_ok());
+        other.cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(token.check().err().unwrap().kind(), RtdErrorKind::Cancelled);
+    }
+}
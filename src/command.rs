@@ -1,12 +1,17 @@
 //! I2C commands for the RTD EZO Chip.
+use std::convert::TryFrom;
+use std::fmt;
 use std::str::FromStr;
 use std::thread;
 use std::time::Duration;
 
+use super::grammar;
+use super::protocol::{name_rejected, read_reply, write_encoded, Reply, RunOn, WriteCommand};
 use super::response::{
-    CalibrationStatus, DataLoggerStorageIntervalSeconds, MemoryReading, SensorReading, Temperature,
-    TemperatureScale,
+    CalibrationStatus, DataLoggerStorageIntervalSeconds, DeviceName, InvalidInterval,
+    MemoryReading, RtdResponse, SensorReading, Temperature, TemperatureScale,
 };
+use super::transport::Transport;
 use super::{ErrorKind, EzoError};
 
 use ezo_common::response::ResponseStatus;
@@ -20,187 +25,228 @@ use i2cdev::linux::LinuxI2CDevice;
 /// Maximum ascii-character response size + 2
 pub const MAX_DATA: usize = 16;
 
+/// Implements `FromStr` for a command, ignoring case.
+///
+/// A command without argument parses from its command string; one with an
+/// argument parses from the prefix of its command string followed by the
+/// argument, which may be checked with a predicate.
+macro_rules! command_from_str {
+    (@arg $s:ident, $prefix:expr, $arg:ty) => {
+        match $s.get(..$prefix.len()) {
+            Some(head) if head.eq_ignore_ascii_case($prefix) => $s[$prefix.len()..]
+                .parse::<$arg>()
+                .context(ErrorKind::CommandParse)?,
+            _ => Err(ErrorKind::CommandParse)?,
+        }
+    };
+    ($name:ident, $cmd_str:expr) => {
+        impl FromStr for $name {
+            type Err = EzoError;
+
+            fn from_str(s: &str) -> Result<Self, EzoError> {
+                if s.eq_ignore_ascii_case($cmd_str) {
+                    Ok($name)
+                } else {
+                    Err(ErrorKind::CommandParse)?
+                }
+            }
+        }
+    };
+    ($name:ident, $prefix:expr, $arg:ty) => {
+        impl FromStr for $name {
+            type Err = EzoError;
+
+            fn from_str(s: &str) -> Result<Self, EzoError> {
+                Ok($name(command_from_str!(@arg s, $prefix, $arg)))
+            }
+        }
+    };
+    ($name:ident, $prefix:expr, $arg:ty, |$value:ident| $valid:expr) => {
+        impl FromStr for $name {
+            type Err = EzoError;
+
+            fn from_str(s: &str) -> Result<Self, EzoError> {
+                let $value = command_from_str!(@arg s, $prefix, $arg);
+                if $valid {
+                    Ok($name($value))
+                } else {
+                    Err(ErrorKind::CommandParse)?
+                }
+            }
+        }
+    };
+}
+
 pub use ezo_common::command::*;
 /// I2C command for the EZO chip.
 pub use ezo_common::Command;
 
 define_command! {
     doc: "`CAL,t` command, where `t` is of type `f64`.",
-    arg: CalibrationTemperature(f64), { format!("CAL,{:.*}", 2, arg) }, 1000, Ack
+    arg: CalibrationTemperature(f64), {
+        let mut cmd = String::new();
+        let _ = grammar::write_calibration_temperature(&mut cmd, *arg);
+        cmd
+    }, 1000, Ack
 }
 
-impl FromStr for CalibrationTemperature {
-    type Err = EzoError;
-
-    fn from_str(s: &str) -> Result<Self, EzoError> {
-        let supper = s.to_uppercase();
-        if supper.starts_with("CAL,") {
-            let rest = supper.get(4..).unwrap();
-            let mut split = rest.split(',');
-            let value = match split.next() {
-                Some(n) => n.parse::<f64>().context(ErrorKind::CommandParse)?,
-                _ => return Err(ErrorKind::CommandParse)?,
-            };
-            match split.next() {
-                None => return Ok(CalibrationTemperature(value)),
-                _ => return Err(ErrorKind::CommandParse)?,
-            }
-        } else {
-            return Err(ErrorKind::CommandParse)?;
-        }
-    }
-}
+command_from_str!(CalibrationTemperature, "CAL,", f64);
 
 define_command! {
     doc: "`CAL,?` command. Returns a `CalibrationStatus` response.",
-    CalibrationState, { "CAL,?".to_string() }, 300,
+    CalibrationState, { grammar::CALIBRATION_STATE.to_string() }, 300,
     resp: CalibrationStatus, { CalibrationStatus::parse(&resp) }
 }
 
-impl FromStr for CalibrationState {
-    type Err = EzoError;
-
-    fn from_str(s: &str) -> Result<Self, EzoError> {
-        let supper = s.to_uppercase();
-        match supper.as_ref() {
-            "CAL,?" => Ok(CalibrationState),
-            _ => Err(ErrorKind::CommandParse)?,
-        }
-    }
-}
+command_from_str!(CalibrationState, grammar::CALIBRATION_STATE);
 
 define_command! {
     doc: "`D,n` command, where `n` is of type `u32`, greater than 0.",
-    arg: DataloggerPeriod(u32), { format!("D,{}", arg) }, 300, Ack
+    arg: DataloggerPeriod(u32), {
+        let mut cmd = String::new();
+        let _ = grammar::write_datalogger_period(&mut cmd, *arg);
+        cmd
+    }, 300, Ack
 }
 
-impl FromStr for DataloggerPeriod {
-    type Err = EzoError;
+command_from_str!(DataloggerPeriod, "D,", u32, |n| n > 0);
 
-    fn from_str(s: &str) -> Result<Self, EzoError> {
-        let supper = s.to_uppercase();
-        if supper.starts_with("D,") {
-            let rest = supper.get(2..).unwrap();
-            let mut split = rest.split(',');
-            let value = match split.next() {
-                Some(n) if n != "0" => n.parse::<u32>().context(ErrorKind::CommandParse)?,
-                _ => return Err(ErrorKind::CommandParse)?,
-            };
-            match split.next() {
-                None => return Ok(DataloggerPeriod(value)),
-                _ => return Err(ErrorKind::CommandParse)?,
-            }
-        } else {
-            return Err(ErrorKind::CommandParse)?;
+/// Rounds the duration to the nearest second, which must be between 10 and
+/// 320,000. The data logger is disabled with `DataloggerDisable` instead.
+impl TryFrom<Duration> for DataloggerPeriod {
+    type Error = InvalidInterval;
+
+    fn try_from(duration: Duration) -> Result<DataloggerPeriod, InvalidInterval> {
+        match DataLoggerStorageIntervalSeconds::try_from(duration)? {
+            DataLoggerStorageIntervalSeconds(0) => Err(InvalidInterval(0)),
+            DataLoggerStorageIntervalSeconds(seconds) => Ok(DataloggerPeriod(seconds)),
         }
     }
 }
 
 define_command! {
     doc: "`D,0` command.",
-    DataloggerDisable, { "D,0".to_string() }, 300, Ack
+    DataloggerDisable, { grammar::DATALOGGER_DISABLE.to_string() }, 300, Ack
 }
 
-impl FromStr for DataloggerDisable {
-    type Err = EzoError;
-
-    fn from_str(s: &str) -> Result<Self, EzoError> {
-        let supper = s.to_uppercase();
-        match supper.as_ref() {
-            "D,0" => Ok(DataloggerDisable),
-            _ => Err(ErrorKind::CommandParse)?,
-        }
-    }
-}
+command_from_str!(DataloggerDisable, grammar::DATALOGGER_DISABLE);
 
 define_command! {
     doc: "`D,?` command. Returns a `DataLoggerStorageIntervalSeconds` response.",
-    DataloggerInterval, { "D,?".to_string() }, 300,
+    DataloggerInterval, { grammar::DATALOGGER_INTERVAL.to_string() }, 300,
     resp: DataLoggerStorageIntervalSeconds, { DataLoggerStorageIntervalSeconds::parse(&resp) }
 }
-impl FromStr for DataloggerInterval {
-    type Err = EzoError;
-
-    fn from_str(s: &str) -> Result<Self, EzoError> {
-        let supper = s.to_uppercase();
-        match supper.as_ref() {
-            "D,?" => Ok(DataloggerInterval),
-            _ => Err(ErrorKind::CommandParse)?,
-        }
-    }
-}
+command_from_str!(DataloggerInterval, grammar::DATALOGGER_INTERVAL);
 
 define_command! {
     doc: "`M,CLEAR` command.",
-    MemoryClear, { "M,CLEAR".to_string() }, 300, Ack
+    MemoryClear, { grammar::MEMORY_CLEAR.to_string() }, 300, Ack
 }
 
-impl FromStr for MemoryClear {
-    type Err = EzoError;
-
-    fn from_str(s: &str) -> Result<Self, EzoError> {
-        let supper = s.to_uppercase();
-        match supper.as_ref() {
-            "M,CLEAR" => Ok(MemoryClear),
-            _ => Err(ErrorKind::CommandParse)?,
-        }
-    }
-}
+command_from_str!(MemoryClear, grammar::MEMORY_CLEAR);
 
 define_command! {
     doc: "`M` command. Returns a `MemoryReading` response.",
-    MemoryRecall, { "M".to_string() }, 300,
+    MemoryRecall, { grammar::MEMORY_RECALL.to_string() }, 300,
     resp: MemoryReading, { MemoryReading::parse(&resp) }
 }
 
-impl FromStr for MemoryRecall {
-    type Err = EzoError;
+command_from_str!(MemoryRecall, grammar::MEMORY_RECALL);
 
-    fn from_str(s: &str) -> Result<Self, EzoError> {
-        let supper = s.to_uppercase();
-        match supper.as_ref() {
-            "M" => Ok(MemoryRecall),
-            _ => Err(ErrorKind::CommandParse)?,
-        }
+/// Recalls a reading from memory, with the current scale.
+///
+/// It first calls ScaleState::run(..), then returns MemoryRecall::run(..)
+/// as a location and a temperature. Readings logged before the scale was
+/// last changed are in the old scale, which the device does not keep.
+pub struct MemoryRecallWithScale;
+
+impl Command for MemoryRecallWithScale {
+    type Error = EzoError;
+    type Response = (u32, Temperature);
+
+    fn get_command_string(&self) -> String {
+        MemoryRecall.get_command_string()
+    }
+
+    fn get_delay(&self) -> u64 {
+        ScaleState.get_delay() + MemoryRecall.get_delay()
+    }
+
+    fn run(&self, dev: &mut LinuxI2CDevice) -> Result<(u32, Temperature), EzoError> {
+        self.run_on(dev)
+    }
+}
+
+impl RunOn for MemoryRecallWithScale {
+    fn run_on<D>(&self, dev: &mut D) -> Result<(u32, Temperature), EzoError>
+    where
+        D: I2CDevice,
+        D::Error: Send + Sync + 'static,
+    {
+        let scale = ScaleState.run_on(dev)?;
+        let reading = MemoryRecall.run_on(dev)?;
+        Ok(reading.with_scale(scale))
+    }
+
+    fn run_dyn(&self, dev: &mut dyn Transport) -> Result<(u32, Temperature), EzoError> {
+        let scale = ScaleState.run_dyn(dev)?;
+        let reading = MemoryRecall.run_dyn(dev)?;
+        Ok(reading.with_scale(scale))
     }
 }
 
 define_command! {
     doc: "`M,?` command. Returns a `MemoryReading` response.",
-    MemoryRecallLast, { "M,?".to_string() }, 300,
+    MemoryRecallLast, { grammar::MEMORY_RECALL_LAST.to_string() }, 300,
     resp: MemoryReading, { MemoryReading::parse(&resp) }
 }
 
-impl FromStr for MemoryRecallLast {
+command_from_str!(MemoryRecallLast, grammar::MEMORY_RECALL_LAST);
+
+define_command! {
+    doc: "`NAME,n` command, where `n` is of type `String`, with up to 16 characters.",
+    arg: Name(String), {
+        let mut cmd = String::new();
+        let _ = grammar::write_name(&mut cmd, arg);
+        cmd
+    }, 300, Ack
+}
+
+impl FromStr for Name {
     type Err = EzoError;
 
     fn from_str(s: &str) -> Result<Self, EzoError> {
         let supper = s.to_uppercase();
-        match supper.as_ref() {
-            "M,?" => Ok(MemoryRecallLast),
-            _ => Err(ErrorKind::CommandParse)?,
+        if supper.starts_with("NAME,") {
+            // The name itself keeps its case.
+            match s.get(5..) {
+                Some(name) if !name.is_empty() && name != "?" && name.len() <= 16 => {
+                    Ok(Name(name.to_string()))
+                }
+                _ => Err(ErrorKind::CommandParse)?,
+            }
+        } else {
+            Err(ErrorKind::CommandParse)?
         }
     }
 }
 
 define_command! {
-    doc: "`R` command. Returns a `SensorReading` response.",
-    Reading, { "R".to_string() }, 600,
-    resp: SensorReading, { SensorReading::parse(&resp) }
+    doc: "`NAME,?` command. Returns a `DeviceName` response.",
+    NameState, { grammar::NAME_STATE.to_string() }, 300,
+    resp: DeviceName, { DeviceName::parse(&resp) }
 }
 
-impl FromStr for Reading {
-    type Err = EzoError;
+command_from_str!(NameState, grammar::NAME_STATE);
 
-    fn from_str(s: &str) -> Result<Self, EzoError> {
-        let supper = s.to_uppercase();
-        match supper.as_ref() {
-            "R" => Ok(Reading),
-            _ => Err(ErrorKind::CommandParse)?,
-        }
-    }
+define_command! {
+    doc: "`R` command. Returns a `SensorReading` response.",
+    Reading, { grammar::READING.to_string() }, 600,
+    resp: SensorReading, { SensorReading::parse(&resp) }
 }
 
+command_from_str!(Reading, grammar::READING);
+
 /// Obtains a temperature with the current scales.
 ///
 /// It first calls ScaleState::run(..), then returns  Reading::run(..)
@@ -222,111 +268,420 @@ impl Command for ReadingWithScale {
     }
 
     fn run(&self, dev: &mut LinuxI2CDevice) -> Result<Temperature, EzoError> {
-        let scale = ScaleState.run(dev)?;
+        self.run_on(dev)
+    }
+}
 
-        let cmd = Reading.get_command_string();
+impl RunOn for ReadingWithScale {
+    fn run_on<D>(&self, dev: &mut D) -> Result<Temperature, EzoError>
+    where
+        D: I2CDevice,
+        D::Error: Send + Sync + 'static,
+    {
+        let scale = ScaleState.run_on(dev)?;
 
-        let _w = write_to_ezo(dev, &cmd)?;
+        write_encoded(dev, &Reading)?;
 
-        let _wait = thread::sleep(Duration::from_millis(Reading.get_delay()));
+        thread::sleep(Duration::from_millis(Reading.get_delay()));
 
-        let mut data_buffer = [0u8; MAX_DATA];
+        let resp_string =
+            read_reply(dev, Reply::Data).map_err(|err| name_rejected(err, &Reading))?;
 
-        let _r = dev.read(&mut data_buffer).context(ErrorKind::I2CRead)?;
+        Temperature::parse(&resp_string, scale)
+    }
 
-        let resp_string = match response_code(data_buffer[0]) {
-            ResponseCode::Success => match data_buffer.iter().position(|&c| c == 0) {
-                Some(len) => string_from_response_data(&data_buffer[1..=len])
-                    .context(ErrorKind::MalformedResponse),
-                _ => return Err(ErrorKind::MalformedResponse.into()),
-            },
+    fn run_dyn(&self, dev: &mut dyn Transport) -> Result<Temperature, EzoError> {
+        let scale = ScaleState.run_dyn(dev)?;
+        let reading = Reading.run_dyn(dev)?;
+        Ok(reading.with_scale(scale))
+    }
+}
 
-            ResponseCode::Pending => return Err(ErrorKind::PendingResponse.into()),
+define_command! {
+    doc: "`S,C` command.",
+    ScaleCelsius, { grammar::SCALE_CELSIUS.to_string() }, 300, Ack
+}
 
-            ResponseCode::DeviceError => return Err(ErrorKind::DeviceErrorResponse.into()),
+command_from_str!(ScaleCelsius, grammar::SCALE_CELSIUS);
 
-            ResponseCode::NoDataExpected => return Err(ErrorKind::NoDataExpectedResponse.into()),
+define_command! {
+    doc: "`S,K` command.",
+    ScaleKelvin, { grammar::SCALE_KELVIN.to_string() }, 300, Ack
+}
 
-            ResponseCode::UnknownError => return Err(ErrorKind::MalformedResponse.into()),
-        };
+command_from_str!(ScaleKelvin, grammar::SCALE_KELVIN);
 
-        Temperature::parse(&resp_string?, scale)
-    }
+define_command! {
+    doc: "`S,F` command.",
+    ScaleFahrenheit, { grammar::SCALE_FAHRENHEIT.to_string() }, 300, Ack
 }
 
+command_from_str!(ScaleFahrenheit, grammar::SCALE_FAHRENHEIT);
+
 define_command! {
-    doc: "`S,C` command.",
-    ScaleCelsius, { "S,C".to_string() }, 300, Ack
+    doc: "`S,?` command. Returns a `TemperatureScale` response.",
+    ScaleState, { grammar::SCALE_STATE.to_string() }, 300,
+    resp: TemperatureScale, { TemperatureScale::parse(&resp) }
 }
 
-impl FromStr for ScaleCelsius {
-    type Err = EzoError;
+command_from_str!(ScaleState, grammar::SCALE_STATE);
 
-    fn from_str(s: &str) -> Result<Self, EzoError> {
-        let supper = s.to_uppercase();
-        match supper.as_ref() {
-            "S,C" => Ok(ScaleCelsius),
-            _ => Err(ErrorKind::CommandParse)?,
+macro_rules! write_command_str {
+    ($name:ident, $cmd_str:expr) => {
+        impl WriteCommand for $name {
+            fn write_command<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+                w.write_str($cmd_str)
+            }
         }
-    }
+    };
 }
 
-define_command! {
-    doc: "`S,K` command.",
-    ScaleKelvin, { "S,K".to_string() }, 300, Ack
+impl WriteCommand for CalibrationTemperature {
+    fn write_command<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        grammar::write_calibration_temperature(w, self.0)
+    }
 }
 
-impl FromStr for ScaleKelvin {
-    type Err = EzoError;
+impl WriteCommand for DataloggerPeriod {
+    fn write_command<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        grammar::write_datalogger_period(w, self.0)
+    }
+}
 
-    fn from_str(s: &str) -> Result<Self, EzoError> {
-        let supper = s.to_uppercase();
-        match supper.as_ref() {
-            "S,K" => Ok(ScaleKelvin),
-            _ => Err(ErrorKind::CommandParse)?,
-        }
+impl WriteCommand for Name {
+    fn write_command<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        grammar::write_name(w, &self.0)
     }
 }
 
-define_command! {
-    doc: "`S,F` command.",
-    ScaleFahrenheit, { "S,F".to_string() }, 300, Ack
+write_command_str!(CalibrationState, grammar::CALIBRATION_STATE);
+write_command_str!(DataloggerDisable, grammar::DATALOGGER_DISABLE);
+write_command_str!(DataloggerInterval, grammar::DATALOGGER_INTERVAL);
+write_command_str!(MemoryClear, grammar::MEMORY_CLEAR);
+write_command_str!(MemoryRecall, grammar::MEMORY_RECALL);
+write_command_str!(MemoryRecallWithScale, grammar::MEMORY_RECALL);
+write_command_str!(MemoryRecallLast, grammar::MEMORY_RECALL_LAST);
+write_command_str!(NameState, grammar::NAME_STATE);
+write_command_str!(Reading, grammar::READING);
+write_command_str!(ReadingWithScale, grammar::READING);
+write_command_str!(ScaleCelsius, grammar::SCALE_CELSIUS);
+write_command_str!(ScaleKelvin, grammar::SCALE_KELVIN);
+write_command_str!(ScaleFahrenheit, grammar::SCALE_FAHRENHEIT);
+write_command_str!(ScaleState, grammar::SCALE_STATE);
+
+// The shared commands that take an argument, `Baud`, `DeviceAddress`, and
+// `Import`, are written through `get_command_string()`.
+impl WriteCommand for Baud {}
+impl WriteCommand for DeviceAddress {}
+impl WriteCommand for Import {}
+
+write_command_str!(CalibrationClear, grammar::CALIBRATION_CLEAR);
+write_command_str!(DeviceInformation, grammar::DEVICE_INFORMATION);
+write_command_str!(Export, grammar::EXPORT);
+write_command_str!(ExportInfo, grammar::EXPORT_INFO);
+write_command_str!(Factory, grammar::FACTORY);
+write_command_str!(Find, grammar::FIND);
+write_command_str!(LedOff, grammar::LED_OFF);
+write_command_str!(LedOn, grammar::LED_ON);
+write_command_str!(LedState, grammar::LED_STATE);
+write_command_str!(ProtocolLockDisable, grammar::PROTOCOL_LOCK_DISABLE);
+write_command_str!(ProtocolLockEnable, grammar::PROTOCOL_LOCK_ENABLE);
+write_command_str!(ProtocolLockState, grammar::PROTOCOL_LOCK_STATE);
+write_command_str!(Sleep, grammar::SLEEP);
+write_command_str!(Status, grammar::STATUS);
+
+macro_rules! display_command {
+    ($($name:ident),*) => {
+        $(
+            /// Writes the command string, e.g. to log the command.
+            impl fmt::Display for $name {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    self.write_command(f)
+                }
+            }
+        )*
+    };
 }
 
-impl FromStr for ScaleFahrenheit {
-    type Err = EzoError;
+display_command!(
+    CalibrationState,
+    CalibrationTemperature,
+    DataloggerDisable,
+    DataloggerInterval,
+    DataloggerPeriod,
+    MemoryClear,
+    MemoryRecall,
+    MemoryRecallLast,
+    MemoryRecallWithScale,
+    Name,
+    NameState,
+    Reading,
+    ReadingWithScale,
+    ScaleCelsius,
+    ScaleFahrenheit,
+    ScaleKelvin,
+    ScaleState
+);
+
+#[cfg(feature = "serde")]
+macro_rules! serde_command {
+    ($($name:ident),*) => {
+        $(
+            /// Serializes the command as its command string.
+            impl serde::Serialize for $name {
+                fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
 
-    fn from_str(s: &str) -> Result<Self, EzoError> {
-        let supper = s.to_uppercase();
-        match supper.as_ref() {
-            "S,F" => Ok(ScaleFahrenheit),
-            _ => Err(ErrorKind::CommandParse)?,
-        }
-    }
+            /// Deserializes the command from its command string, ignoring
+            /// case.
+            impl<'de> serde::Deserialize<'de> for $name {
+                fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    let cmd_str = <String as serde::Deserialize>::deserialize(deserializer)?;
+                    cmd_str.parse().map_err(serde::de::Error::custom)
+                }
+            }
+        )*
+    };
 }
 
-define_command! {
-    doc: "`S,?` command. Returns a `TemperatureScale` response.",
-    ScaleState, { "S,?".to_string() }, 300,
-    resp: TemperatureScale, { TemperatureScale::parse(&resp) }
-}
+#[cfg(feature = "serde")]
+serde_command!(
+    CalibrationState,
+    CalibrationTemperature,
+    DataloggerDisable,
+    DataloggerInterval,
+    DataloggerPeriod,
+    MemoryClear,
+    MemoryRecall,
+    MemoryRecallLast,
+    Name,
+    NameState,
+    Reading,
+    RtdCommand,
+    ScaleCelsius,
+    ScaleFahrenheit,
+    ScaleKelvin,
+    ScaleState
+);
+
+macro_rules! rtd_command {
+    ($($name:ident),* $(,)*) => {
+        /// Any command of the RTD EZO chip, e.g. parsed from user input.
+        ///
+        /// `ReadingWithScale` and `MemoryRecallWithScale` are not included,
+        /// since they are written as `R` and `M`, which parse as `Reading` and
+        /// `MemoryRecall`.
+        #[derive(Debug, PartialEq)]
+        pub enum RtdCommand {
+            $( $name($name), )*
+        }
 
-impl FromStr for ScaleState {
-    type Err = EzoError;
+        impl RtdCommand {
+            /// Returns the command string, as `Command::get_command_string`
+            /// does.
+            pub fn get_command_string(&self) -> String {
+                match self {
+                    $( RtdCommand::$name(cmd) => cmd.get_command_string(), )*
+                }
+            }
 
-    fn from_str(s: &str) -> Result<Self, EzoError> {
-        let supper = s.to_uppercase();
-        match supper.as_ref() {
-            "S,?" => Ok(ScaleState),
-            _ => Err(ErrorKind::CommandParse)?,
+            /// Returns the delay of the command in milliseconds, as
+            /// `Command::get_delay` does.
+            pub fn get_delay(&self) -> u64 {
+                match self {
+                    $( RtdCommand::$name(cmd) => cmd.get_delay(), )*
+                }
+            }
+
+            /// Runs the command on `dev`, as `RunOn::run_on` does.
+            pub fn run_on<D>(&self, dev: &mut D) -> Result<RtdResponse, EzoError>
+            where
+                D: I2CDevice,
+                D::Error: Send + Sync + 'static,
+            {
+                match self {
+                    $( RtdCommand::$name(cmd) => cmd.run_on(dev).map(RtdResponse::from), )*
+                }
+            }
         }
-    }
+
+        /// Writes the command string of any command, including the ones
+        /// shared by every EZO chip.
+        impl fmt::Display for RtdCommand {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match self {
+                    $( RtdCommand::$name(cmd) => cmd.write_command(f), )*
+                }
+            }
+        }
+
+        impl FromStr for RtdCommand {
+            type Err = EzoError;
+
+            /// Parses any command, ignoring case, as the `FromStr`
+            /// implementation of each command does.
+            fn from_str(s: &str) -> Result<Self, EzoError> {
+                $(
+                    if let Ok(cmd) = s.parse::<$name>() {
+                        return Ok(RtdCommand::$name(cmd));
+                    }
+                )*
+                Err(ErrorKind::CommandParse)?
+            }
+        }
+
+        $(
+            impl From<$name> for RtdCommand {
+                fn from(cmd: $name) -> RtdCommand {
+                    RtdCommand::$name(cmd)
+                }
+            }
+        )*
+    };
+}
+
+/// Parses `cmd_str` into a command, runs it on `dev`, waiting for its delay,
+/// and returns its response.
+///
+/// Commands are parsed as `RtdCommand` does, ignoring case, e.g. `"cal,?"`.
+pub fn execute_str<D>(dev: &mut D, cmd_str: &str) -> Result<RtdResponse, EzoError>
+where
+    D: I2CDevice,
+    D::Error: Send + Sync + 'static,
+{
+    cmd_str.parse::<RtdCommand>()?.run_on(dev)
+}
+
+// `NameState` comes before `Name`, so that `NAME,?` is never a name.
+rtd_command! {
+    Baud,
+    CalibrationClear,
+    CalibrationState,
+    CalibrationTemperature,
+    DataloggerDisable,
+    DataloggerInterval,
+    DataloggerPeriod,
+    DeviceAddress,
+    DeviceInformation,
+    Export,
+    ExportInfo,
+    Factory,
+    Find,
+    Import,
+    LedOff,
+    LedOn,
+    LedState,
+    MemoryClear,
+    MemoryRecall,
+    MemoryRecallLast,
+    NameState,
+    Name,
+    ProtocolLockDisable,
+    ProtocolLockEnable,
+    ProtocolLockState,
+    Reading,
+    ScaleCelsius,
+    ScaleFahrenheit,
+    ScaleKelvin,
+    ScaleState,
+    Sleep,
+    Status,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn parses_any_command() {
+        assert_eq!(
+            "cal,?".parse::<RtdCommand>().unwrap(),
+            RtdCommand::CalibrationState(CalibrationState)
+        );
+        assert_eq!(
+            "d,60".parse::<RtdCommand>().unwrap(),
+            RtdCommand::DataloggerPeriod(DataloggerPeriod(60))
+        );
+        assert_eq!(
+            "d,0".parse::<RtdCommand>().unwrap(),
+            RtdCommand::DataloggerDisable(DataloggerDisable)
+        );
+        assert_eq!(
+            "r".parse::<RtdCommand>().unwrap(),
+            RtdCommand::Reading(Reading)
+        );
+        assert_eq!(
+            "name,?".parse::<RtdCommand>().unwrap(),
+            RtdCommand::NameState(NameState)
+        );
+        assert_eq!(
+            "Sleep".parse::<RtdCommand>().unwrap(),
+            RtdCommand::from(Sleep)
+        );
+
+        let cmd = "NAME,tank".parse::<RtdCommand>().unwrap();
+        assert_eq!(cmd.get_command_string(), "NAME,tank");
+        assert_eq!(cmd.get_delay(), 300);
+
+        assert!("cal,".parse::<RtdCommand>().is_err());
+        assert!("".parse::<RtdCommand>().is_err());
+    }
+
+    #[test]
+    fn commands_display_their_command_string() {
+        assert_eq!(format!("{}", DataloggerPeriod(60)), "D,60");
+        assert_eq!(format!("{}", CalibrationTemperature(25.0)), "CAL,25.00");
+        assert_eq!(format!("{}", ScaleState), "S,?");
+        assert_eq!(
+            format!("{}", DataloggerPeriod(60))
+                .parse::<DataloggerPeriod>()
+                .unwrap(),
+            DataloggerPeriod(60)
+        );
+        assert_eq!(format!("{}", RtdCommand::from(Sleep)), "SLEEP");
+        assert_eq!(
+            format!("{:?}", DataloggerPeriod(60)),
+            "DataloggerPeriod(60)"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn commands_serialize_as_command_strings() {
+        let job = vec![
+            RtdCommand::from(CalibrationTemperature(25.0)),
+            RtdCommand::from(DataloggerPeriod(60)),
+            RtdCommand::from(Sleep),
+        ];
+        let json = serde_json::to_string(&job).unwrap();
+        assert_eq!(json, r#"["CAL,25.00","D,60","SLEEP"]"#);
+        assert_eq!(serde_json::from_str::<Vec<RtdCommand>>(&json).unwrap(), job);
+
+        let cmd: DataloggerPeriod = serde_json::from_str(r#""d,60""#).unwrap();
+        assert_eq!(cmd, DataloggerPeriod(60));
+        assert!(serde_json::from_str::<DataloggerPeriod>(r#""D,0""#).is_err());
+    }
+
+    #[test]
+    fn executes_command_strings() {
+        use i2cdev::mock::MockI2CDevice;
+
+        // The mock takes the first byte written as a register offset, and
+        // the rest as data; reads continue right after it.
+        let mut dev = MockI2CDevice::new();
+        dev.regmap
+            .write_regs(usize::from(b'C') + 5, b"\x01?CAL,1\0");
+        assert_eq!(
+            execute_str(&mut dev, "cal,?").unwrap(),
+            RtdResponse::CalibrationStatus(CalibrationStatus::Calibrated)
+        );
+
+        let err = execute_str(&mut dev, "cal,").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::CommandParse);
+    }
+
     #[test]
     fn build_command_calibration_temperature() {
         let cmd = CalibrationTemperature(35.2459);
@@ -393,6 +748,29 @@ mod tests {
         assert!(cmd.is_err());
     }
 
+    #[test]
+    fn data_logger_period_from_duration() {
+        let period = DataloggerPeriod::try_from;
+        assert_eq!(period(Duration::from_secs(60)), Ok(DataloggerPeriod(60)));
+        assert_eq!(
+            period(Duration::from_millis(9_500)),
+            Ok(DataloggerPeriod(10))
+        );
+        assert_eq!(
+            period(Duration::from_secs(320_000)),
+            Ok(DataloggerPeriod(320_000))
+        );
+        assert_eq!(
+            period(Duration::from_millis(9_499)),
+            Err(InvalidInterval(9))
+        );
+        assert_eq!(period(Duration::from_millis(499)), Err(InvalidInterval(0)));
+        assert_eq!(
+            period(Duration::from_secs(320_001)),
+            Err(InvalidInterval(320_001))
+        );
+    }
+
     #[test]
     fn build_command_data_logger_disable() {
         let cmd = DataloggerDisable;
@@ -473,6 +851,50 @@ mod tests {
         assert_eq!(cmd, MemoryRecallLast);
     }
 
+    #[test]
+    fn build_command_name() {
+        let cmd = Name("probe-1".to_string());
+        assert_eq!(cmd.get_command_string(), "NAME,probe-1");
+        assert_eq!(cmd.get_delay(), 300);
+    }
+
+    #[test]
+    fn parse_case_insensitive_command_name_keeps_name_case() {
+        let cmd = "name,Tank-A".parse::<Name>().unwrap();
+        assert_eq!(cmd, Name("Tank-A".to_string()));
+
+        let cmd = "NAME,probe".parse::<Name>().unwrap();
+        assert_eq!(cmd, Name("probe".to_string()));
+    }
+
+    #[test]
+    fn parse_invalid_command_name_yields_err() {
+        let cmd = "name,".parse::<Name>();
+        assert!(cmd.is_err());
+
+        let cmd = "name,?".parse::<Name>();
+        assert!(cmd.is_err());
+
+        let cmd = "name,abcdefghijklmnopq".parse::<Name>();
+        assert!(cmd.is_err());
+    }
+
+    #[test]
+    fn build_command_name_state() {
+        let cmd = NameState;
+        assert_eq!(cmd.get_command_string(), "NAME,?");
+        assert_eq!(cmd.get_delay(), 300);
+    }
+
+    #[test]
+    fn parse_case_insensitive_command_name_state() {
+        let cmd = "name,?".parse::<NameState>().unwrap();
+        assert_eq!(cmd, NameState);
+
+        let cmd = "NAME,?".parse::<NameState>().unwrap();
+        assert_eq!(cmd, NameState);
+    }
+
     #[test]
     fn build_command_reading() {
         let cmd = Reading;
@@ -496,6 +918,13 @@ mod tests {
         assert_eq!(cmd.get_delay(), 900);
     }
 
+    #[test]
+    fn build_command_memory_recall_with_scale() {
+        let cmd = MemoryRecallWithScale;
+        assert_eq!(cmd.get_command_string(), "M");
+        assert_eq!(cmd.get_delay(), 600);
+    }
+
     #[test]
     fn build_command_scale_celsius() {
         let cmd = ScaleCelsius;
@@ -559,4 +988,45 @@ mod tests {
         let cmd = "S,?".parse::<ScaleState>().unwrap();
         assert_eq!(cmd, ScaleState);
     }
+
+    #[test]
+    fn written_command_strings_match_built_ones() {
+        fn written<C: WriteCommand>(cmd: &C) -> String {
+            let mut cmd_str = String::new();
+            cmd.write_command(&mut cmd_str).unwrap();
+            assert_eq!(cmd_str, cmd.get_command_string());
+            cmd_str
+        }
+
+        assert_eq!(written(&CalibrationTemperature(-12.345)), "CAL,-12.35");
+        assert_eq!(written(&DataloggerPeriod(60)), "D,60");
+        assert_eq!(written(&Name("tank".to_string())), "NAME,tank");
+        assert_eq!(written(&CalibrationState), "CAL,?");
+        assert_eq!(written(&DataloggerDisable), "D,0");
+        assert_eq!(written(&DataloggerInterval), "D,?");
+        assert_eq!(written(&MemoryClear), "M,CLEAR");
+        assert_eq!(written(&MemoryRecall), "M");
+        assert_eq!(written(&MemoryRecallLast), "M,?");
+        assert_eq!(written(&NameState), "NAME,?");
+        assert_eq!(written(&Reading), "R");
+        assert_eq!(written(&ReadingWithScale), "R");
+        assert_eq!(written(&ScaleCelsius), "S,C");
+        assert_eq!(written(&ScaleKelvin), "S,K");
+        assert_eq!(written(&ScaleFahrenheit), "S,F");
+        assert_eq!(written(&ScaleState), "S,?");
+        assert_eq!(written(&CalibrationClear), "CAL,CLEAR");
+        assert_eq!(written(&DeviceInformation), "I");
+        assert_eq!(written(&Export), "EXPORT");
+        assert_eq!(written(&ExportInfo), "EXPORT,?");
+        assert_eq!(written(&Factory), "FACTORY");
+        assert_eq!(written(&Find), "F");
+        assert_eq!(written(&LedOff), "L,0");
+        assert_eq!(written(&LedOn), "L,1");
+        assert_eq!(written(&LedState), "L,?");
+        assert_eq!(written(&ProtocolLockDisable), "PLOCK,0");
+        assert_eq!(written(&ProtocolLockEnable), "PLOCK,1");
+        assert_eq!(written(&ProtocolLockState), "PLOCK,?");
+        assert_eq!(written(&Sleep), "SLEEP");
+        assert_eq!(written(&Status), "STATUS");
+    }
 }
@@ -0,0 +1,170 @@
+//! Configuration settings of the RTD EZO chip.
+use std::fmt;
+
+use super::response::{
+    DataLoggerStorageIntervalSeconds, DeviceName, LedStatus, ProtocolLockStatus, TemperatureScale,
+};
+
+/// Snapshot of the settings of a device, taken by `RtdSensor::snapshot()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceConfig {
+    pub scale: TemperatureScale,
+    pub datalogger: DataLoggerStorageIntervalSeconds,
+    pub led: LedStatus,
+    pub protocol_lock: ProtocolLockStatus,
+    pub name: DeviceName,
+}
+
+impl fmt::Display for DeviceConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "name: {}", self.name)?;
+        writeln!(f, "scale: {}", self.scale)?;
+        writeln!(f, "datalogger-interval: {}", self.datalogger)?;
+        writeln!(f, "led: {}", self.led)?;
+        write!(f, "protocol-lock: {}", self.protocol_lock)
+    }
+}
+
+impl DeviceConfig {
+    /// Lists the settings of `other` that differ from this configuration,
+    /// which is taken to be the expected one.
+    pub fn diff(&self, other: &DeviceConfig) -> Vec<ConfigDiff> {
+        let mut diffs = Vec::new();
+        if self.scale != other.scale {
+            diffs.push(ConfigDiff::Scale {
+                expected: self.scale,
+                actual: other.scale,
+            });
+        }
+        if self.datalogger != other.datalogger {
+            diffs.push(ConfigDiff::Datalogger {
+                expected: self.datalogger,
+                actual: other.datalogger,
+            });
+        }
+        if self.led != other.led {
+            diffs.push(ConfigDiff::Led {
+                expected: self.led,
+                actual: other.led,
+            });
+        }
+        if self.protocol_lock != other.protocol_lock {
+            diffs.push(ConfigDiff::ProtocolLock {
+                expected: self.protocol_lock,
+                actual: other.protocol_lock,
+            });
+        }
+        if self.name != other.name {
+            diffs.push(ConfigDiff::Name {
+                expected: self.name.clone(),
+                actual: other.name.clone(),
+            });
+        }
+        diffs
+    }
+}
+
+/// A setting that differs between an expected and an actual `DeviceConfig`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigDiff {
+    Scale {
+        expected: TemperatureScale,
+        actual: TemperatureScale,
+    },
+    Datalogger {
+        expected: DataLoggerStorageIntervalSeconds,
+        actual: DataLoggerStorageIntervalSeconds,
+    },
+    Led {
+        expected: LedStatus,
+        actual: LedStatus,
+    },
+    ProtocolLock {
+        expected: ProtocolLockStatus,
+        actual: ProtocolLockStatus,
+    },
+    Name {
+        expected: DeviceName,
+        actual: DeviceName,
+    },
+}
+
+impl fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigDiff::Scale { expected, actual } => {
+                write!(f, "scale: expected {}, found {}", expected, actual)
+            }
+            ConfigDiff::Datalogger { expected, actual } => write!(
+                f,
+                "datalogger-interval: expected {}, found {}",
+                expected, actual
+            ),
+            ConfigDiff::Led { expected, actual } => {
+                write!(f, "led: expected {}, found {}", expected, actual)
+            }
+            ConfigDiff::ProtocolLock { expected, actual } => {
+                write!(f, "protocol-lock: expected {}, found {}", expected, actual)
+            }
+            ConfigDiff::Name {
+                ref expected,
+                ref actual,
+            } => write!(f, "name: expected {}, found {}", expected, actual),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> DeviceConfig {
+        DeviceConfig {
+            scale: TemperatureScale::Fahrenheit,
+            datalogger: DataLoggerStorageIntervalSeconds(10),
+            led: LedStatus::Off,
+            protocol_lock: ProtocolLockStatus::On,
+            name: DeviceName("Tank-A".to_string()),
+        }
+    }
+
+    #[test]
+    fn formats_device_config_as_one_setting_per_line() {
+        let config = config();
+        assert_eq!(
+            format!("{}", config),
+            "name: Tank-A\nscale: fahrenheit\ndatalogger-interval: 10\nled: off\nprotocol-lock: on"
+        );
+    }
+
+    #[test]
+    fn diff_of_equal_configs_is_empty() {
+        assert!(config().diff(&config()).is_empty());
+    }
+
+    #[test]
+    fn diff_lists_changed_settings() {
+        let mut actual = config();
+        actual.scale = TemperatureScale::Celsius;
+        actual.datalogger = DataLoggerStorageIntervalSeconds(60);
+
+        let diffs = config().diff(&actual);
+        assert_eq!(
+            diffs,
+            vec![
+                ConfigDiff::Scale {
+                    expected: TemperatureScale::Fahrenheit,
+                    actual: TemperatureScale::Celsius,
+                },
+                ConfigDiff::Datalogger {
+                    expected: DataLoggerStorageIntervalSeconds(10),
+                    actual: DataLoggerStorageIntervalSeconds(60),
+                },
+            ]
+        );
+        assert_eq!(
+            format!("{}", diffs[0]),
+            "scale: expected fahrenheit, found celsius"
+        );
+    }
+}
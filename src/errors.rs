@@ -0,0 +1,381 @@
+//! Errors for the high-level `RtdSensor` API.
+//!
+//! The crate has two layers of errors:
+//!
+//! - `EzoError`, from `ezo_common`, is returned by commands, responses, and
+//!   the other low-level modules. Its `ErrorKind` tells which step of the
+//!   I2C transaction failed.
+//! - `RtdError` is returned by `RtdSensor` and the helpers built on it. Its
+//!   `RtdErrorKind` tells which step of the sensor operation failed.
+//!
+//! Every `EzoError` converts into an `RtdError` of kind
+//! `RtdErrorKind::Command`, or a kind telling the condition of the device,
+//! such as `RtdErrorKind::Timeout` for a reply that is still pending, so `?`
+//! works on both in code that returns `RtdError`, and `RtdError::ezo_kind()`
+//! gives back the kind of the underlying `EzoError`.
+//!
+//! The low-level layer cannot return `RtdError`: commands implement the
+//! `Command` trait of `ezo_common`, whose macros fix its error type to
+//! `EzoError`, and the parsers, middleware, retry, and non-blocking runners
+//! are built on that trait. So, as a rule, anything that takes an
+//! `RtdSensor` returns `RtdError`, and anything that takes a bare I2C device
+//! returns `EzoError`.
+//!
+//! `RtdError` is a standard `Error`, so it can be used with `?` in
+//! applications built on e.g. `anyhow` or `thiserror`. Its `source()` is the
+//! error that made the step fail, if any.
+use std::error::Error as StdError;
+use std::fmt;
+
+use super::middleware::SkippedCommand;
+use super::protocol::{i2c_fault, I2cFault};
+use super::{ErrorKind, EzoError};
+
+use failure::{Compat, Fail};
+
+use thiserror::Error;
+
+/// Error returned by the operations of an `RtdSensor`.
+#[derive(Debug)]
+pub struct RtdError {
+    kind: RtdErrorKind,
+    command: Option<String>,
+    hint: Option<&'static str>,
+    source: Option<Box<dyn StdError + Send + Sync>>,
+}
+
+/// The step of a sensor operation that failed, or the condition of the
+/// device that made it fail.
+///
+/// More kinds may be added as the crate learns to tell apart more
+/// conditions, so matches on it need a wildcard arm.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Error)]
+#[non_exhaustive]
+pub enum RtdErrorKind {
+    #[error("no I2C bus was specified")]
+    MissingBus,
+    #[error("the I2C device node does not exist")]
+    DeviceNotFound,
+    #[error("could not open the I2C device")]
+    DeviceOpen,
+    #[error("the device did not respond")]
+    NoResponse,
+    #[error("the device is not an RTD EZO chip")]
+    WrongDevice,
+    #[error("could not set the temperature scale")]
+    SetScale,
+    #[error("could not set the data logger interval")]
+    SetDatalogger,
+    #[error("could not set the LED state")]
+    SetLed,
+    #[error("could not set the protocol lock")]
+    SetProtocolLock,
+    #[error("could not set the device name")]
+    SetName,
+    #[error("the device settings do not match the applied configuration")]
+    ConfigMismatch,
+    #[error("the configuration contains an invalid import line")]
+    InvalidImport,
+    #[error("the exported lines do not match the export info")]
+    ExportMismatch,
+    #[error("could not read the configuration file")]
+    ConfigFile,
+    #[error("invalid sensor setting")]
+    InvalidSetting,
+    #[error("the command could not be completed")]
+    Command,
+    #[error("the sensor is not calibrated")]
+    NotCalibrated,
+    #[error("the calibration temperature is out of the range of the probe")]
+    CalibrationOutOfRange,
+    #[error("the I2C address is out of range")]
+    InvalidAddress,
+    #[error("the device did not respond after changing its I2C address")]
+    AddressVerify,
+    #[error("the operation was cancelled")]
+    Cancelled,
+    #[error("the sampling thread panicked")]
+    SamplerPanic,
+    #[error("no probe is connected, or its circuit is open")]
+    ProbeOpenCircuit,
+    #[error("the device is asleep, and did not wake up")]
+    Asleep,
+    #[error("the firmware of the device is too old")]
+    UnsupportedFirmware,
+    #[error("the device did not reply in time")]
+    Timeout,
+    #[error("the device rejected the command")]
+    Rejected,
+    #[error("could not write the log")]
+    LogWrite,
+}
+
+impl RtdError {
+    /// Returns the kind of error, i.e. the step that failed.
+    pub fn kind(&self) -> RtdErrorKind {
+        self.kind
+    }
+
+    /// Returns the command string of the command that failed, e.g. `S,C`,
+    /// if the error comes from running one.
+    pub fn command(&self) -> Option<&str> {
+        self.command.as_deref()
+    }
+
+    // Records the command string of the command that failed.
+    pub(crate) fn with_command(mut self, cmd_str: String) -> RtdError {
+        self.command = Some(cmd_str);
+        self
+    }
+
+    /// Returns the likely cause of the error and how to fix it, e.g. for
+    /// a bus that cannot be opened, if it is known.
+    pub fn hint(&self) -> Option<&str> {
+        self.hint
+    }
+
+    // Records the likely cause of the error, and how to fix it.
+    pub(crate) fn with_hint(mut self, hint: &'static str) -> RtdError {
+        self.hint = Some(hint);
+        self
+    }
+
+    /// Returns the kind of the `EzoError` that caused this error, if it was
+    /// caused by one.
+    pub fn ezo_kind(&self) -> Option<ErrorKind> {
+        self.ezo_error().map(EzoError::kind)
+    }
+
+    // The `EzoError` that caused this error, if any.
+    fn ezo_error(&self) -> Option<&EzoError> {
+        let source = self.source.as_ref()?;
+        let ezo = source.downcast_ref::<Compat<EzoError>>()?;
+        Some(ezo.get_ref())
+    }
+}
+
+impl StdError for RtdError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.source
+            .as_deref()
+            .map(|source| source as &(dyn StdError + 'static))
+    }
+}
+
+/// Writes the kind of error, followed by the failed command, if any, e.g.
+/// ``could not set the temperature scale (running `S,C`)``, and by the hint,
+/// if any.
+impl fmt::Display for RtdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.command {
+            Some(ref cmd_str) => write!(f, "{} (running `{}`)", self.kind, cmd_str)?,
+            None => fmt::Display::fmt(&self.kind, f)?,
+        }
+        match self.hint {
+            Some(hint) => write!(f, ": {}", hint),
+            None => Ok(()),
+        }
+    }
+}
+
+impl From<RtdErrorKind> for RtdError {
+    fn from(kind: RtdErrorKind) -> RtdError {
+        RtdError {
+            kind,
+            command: None,
+            hint: None,
+            source: None,
+        }
+    }
+}
+
+/// A reply that is still pending once the command delay is over is a
+/// `Timeout`, and a device error is a `Rejected` command; any other
+/// `EzoError` is a failed `Command`.
+impl From<EzoError> for RtdError {
+    fn from(err: EzoError) -> RtdError {
+        let kind = match err.kind() {
+            ErrorKind::PendingResponse => RtdErrorKind::Timeout,
+            ErrorKind::DeviceErrorResponse => RtdErrorKind::Rejected,
+            _ => RtdErrorKind::Command,
+        };
+        RtdError {
+            kind,
+            command: None,
+            hint: None,
+            source: Some(Box::new(err.compat())),
+        }
+    }
+}
+
+/// Tells retry loops whether an error may go away if the operation is
+/// retried.
+///
+/// A reply that is still pending, or an I2C transfer that failed, e.g.
+/// because another controller took over the bus, is transient. A command
+/// that does not parse, or that the chip refused, fails the same way every
+/// time, as does a transfer whose address no chip acknowledged.
+pub trait Transient {
+    /// Returns `true` if retrying may succeed.
+    fn is_transient(&self) -> bool;
+}
+
+impl Transient for ErrorKind {
+    fn is_transient(&self) -> bool {
+        matches!(
+            *self,
+            ErrorKind::PendingResponse | ErrorKind::I2CRead | ErrorKind::UnwritableCommand
+        )
+    }
+}
+
+/// An `EzoError` is transient if its kind is, unless it was caused by an
+/// `I2cFault::AddressNack`, as the chip is missing or at another address,
+/// or by a `SkippedCommand`, as the middleware would skip it again.
+impl Transient for EzoError {
+    fn is_transient(&self) -> bool {
+        if i2c_fault(self) == Some(I2cFault::AddressNack) {
+            return false;
+        }
+        let err: &dyn Fail = self;
+        let skipped = err
+            .iter_causes()
+            .any(|cause| cause.downcast_ref::<SkippedCommand>().is_some());
+        !skipped && self.kind().is_transient()
+    }
+}
+
+/// An `RtdError` is transient if the `EzoError` that caused it is.
+impl Transient for RtdError {
+    fn is_transient(&self) -> bool {
+        match self.ezo_error() {
+            Some(err) => err.is_transient(),
+            None => false,
+        }
+    }
+}
+
+/// Turns the error of a failed step into an `RtdError` of the given kind.
+///
+/// Errors of the I2C and EZO layers are `failure` errors, so they are kept
+/// as the `source()` through `failure::Compat`.
+pub(crate) trait ResultExt<T> {
+    fn context(self, kind: RtdErrorKind) -> Result<T, RtdError>;
+}
+
+impl<T, E: Fail> ResultExt<T> for Result<T, E> {
+    fn context(self, kind: RtdErrorKind) -> Result<T, RtdError> {
+        self.map_err(|err| RtdError {
+            kind,
+            command: None,
+            hint: None,
+            source: Some(Box::new(err.compat())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn errors_keep_their_kind_and_source() {
+        let err = RtdError::from(RtdErrorKind::Cancelled);
+        assert_eq!(err.kind(), RtdErrorKind::Cancelled);
+        assert_eq!(err.to_string(), "the operation was cancelled");
+        assert!(err.source().is_none());
+
+        let io_err = io::Error::new(io::ErrorKind::NotFound, "no such file");
+        let err = Err::<(), _>(io_err)
+            .context(RtdErrorKind::ConfigFile)
+            .unwrap_err();
+        assert_eq!(err.kind(), RtdErrorKind::ConfigFile);
+        assert_eq!(err.source().unwrap().to_string(), "no such file");
+
+        let boxed: Box<dyn StdError + Send + Sync> = Box::new(err);
+        assert_eq!(boxed.to_string(), "could not read the configuration file");
+    }
+
+    #[test]
+    fn ezo_errors_convert_into_command_errors() {
+        let err = RtdError::from(EzoError::from(ErrorKind::I2CRead));
+        assert_eq!(err.kind(), RtdErrorKind::Command);
+        assert_eq!(err.ezo_kind(), Some(ErrorKind::I2CRead));
+
+        let err = Err::<(), _>(EzoError::from(ErrorKind::ResponseParse))
+            .context(RtdErrorKind::SetScale)
+            .unwrap_err();
+        assert_eq!(err.kind(), RtdErrorKind::SetScale);
+        assert_eq!(err.ezo_kind(), Some(ErrorKind::ResponseParse));
+
+        assert_eq!(RtdError::from(RtdErrorKind::Cancelled).ezo_kind(), None);
+
+        let err = RtdError::from(EzoError::from(ErrorKind::PendingResponse));
+        assert_eq!(err.kind(), RtdErrorKind::Timeout);
+        assert_eq!(err.ezo_kind(), Some(ErrorKind::PendingResponse));
+
+        let err = RtdError::from(EzoError::from(ErrorKind::DeviceErrorResponse));
+        assert_eq!(err.kind(), RtdErrorKind::Rejected);
+    }
+
+    #[test]
+    fn only_transient_errors_may_be_retried() {
+        assert!(ErrorKind::PendingResponse.is_transient());
+        assert!(EzoError::from(ErrorKind::I2CRead).is_transient());
+        assert!(EzoError::from(ErrorKind::UnwritableCommand).is_transient());
+        assert!(!EzoError::from(ErrorKind::DeviceErrorResponse).is_transient());
+        assert!(!EzoError::from(ErrorKind::CommandParse).is_transient());
+        assert!(!EzoError::from(ErrorKind::ResponseParse).is_transient());
+
+        assert!(RtdError::from(EzoError::from(ErrorKind::PendingResponse)).is_transient());
+        assert!(!RtdError::from(EzoError::from(ErrorKind::CommandParse)).is_transient());
+        assert!(!RtdError::from(RtdErrorKind::Cancelled).is_transient());
+    }
+
+    #[test]
+    fn unacknowledged_addresses_are_not_transient() {
+        // `failure::ResultExt`, as the `ResultExt` of this module makes
+        // `RtdError`s.
+        fn caused_by<E: Fail>(cause: E, kind: ErrorKind) -> EzoError {
+            failure::ResultExt::context(Err::<(), _>(cause), kind)
+                .unwrap_err()
+                .into()
+        }
+        let err = caused_by(I2cFault::AddressNack, ErrorKind::UnwritableCommand);
+        assert!(!err.is_transient());
+        assert!(!RtdError::from(err).is_transient());
+        assert!(caused_by(I2cFault::ArbitrationLoss, ErrorKind::I2CRead).is_transient());
+        assert!(caused_by(I2cFault::DataNack, ErrorKind::UnwritableCommand).is_transient());
+        assert!(!caused_by(I2cFault::Bus, ErrorKind::CommandParse).is_transient());
+
+        let skipped = SkippedCommand("R".to_string());
+        assert!(!caused_by(skipped, ErrorKind::UnwritableCommand).is_transient());
+    }
+
+    #[test]
+    fn errors_tell_the_likely_fix() {
+        let err = RtdError::from(RtdErrorKind::DeviceOpen);
+        assert_eq!(err.hint(), None);
+
+        let err = err.with_hint("add the user to the `i2c` group");
+        assert_eq!(err.hint(), Some("add the user to the `i2c` group"));
+        assert_eq!(
+            err.to_string(),
+            "could not open the I2C device: add the user to the `i2c` group"
+        );
+    }
+
+    #[test]
+    fn errors_name_the_failed_command() {
+        let err = RtdError::from(RtdErrorKind::SetScale);
+        assert_eq!(err.command(), None);
+
+        let err = err.with_command("S,C".to_string());
+        assert_eq!(err.command(), Some("S,C"));
+        assert_eq!(
+            err.to_string(),
+            "could not set the temperature scale (running `S,C`)"
+        );
+    }
+}
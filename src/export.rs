@@ -0,0 +1,291 @@
+//! Exported configuration of the RTD EZO chip.
+use std::fmt;
+use std::str::FromStr;
+
+use super::errors::{RtdError, RtdErrorKind};
+use super::response::ExportedInfo;
+
+/// Maximum number of characters in an exported line.
+pub const MAX_LINE_LENGTH: usize = 12;
+
+/// Calibration and settings exported from a chip, that can be imported
+/// into another chip.
+///
+/// Each line is one of the strings returned by the `EXPORT` command, in
+/// the order they were exported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExportedConfig {
+    lines: Vec<String>,
+}
+
+impl ExportedConfig {
+    /// Creates a configuration from exported lines.
+    pub fn new(lines: Vec<String>) -> ExportedConfig {
+        ExportedConfig { lines }
+    }
+
+    /// The exported lines, in order.
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Checks that every line can be sent with the `IMPORT,n` command.
+    ///
+    /// A bad line would leave the chip half-imported, so this is done
+    /// before any line is sent to the device.
+    pub fn validate(&self) -> Result<(), RtdError> {
+        if self.lines.is_empty() {
+            return Err(RtdErrorKind::InvalidImport.into());
+        }
+        for line in &self.lines {
+            validate_line(line)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the lines match the number of lines and bytes reported
+    /// by the `EXPORT,?` command.
+    pub fn check_info(&self, info: &ExportedInfo) -> Result<(), RtdError> {
+        let total_bytes: usize = self.lines.iter().map(|l| l.len()).sum();
+        if self.lines.len() == info.lines as usize && total_bytes == info.total_bytes as usize {
+            Ok(())
+        } else {
+            Err(RtdErrorKind::ExportMismatch.into())
+        }
+    }
+
+    /// Parses every line into an `ExportEntry`.
+    pub fn entries(&self) -> Result<Vec<ExportEntry>, RtdError> {
+        self.lines.iter().map(|l| ExportEntry::parse(l)).collect()
+    }
+
+    /// Creates a configuration from entries, e.g. after modifying the ones
+    /// returned by `entries()`.
+    pub fn from_entries(entries: &[ExportEntry]) -> ExportedConfig {
+        ExportedConfig::new(entries.iter().map(|e| e.to_string()).collect())
+    }
+}
+
+/// One line of an exported configuration.
+///
+/// The datasheet does not document the layout of the exported data, so
+/// entries are told apart by their encoding: hex-encoded bytes, such as
+/// calibration constants, are decoded; anything else, such as the device
+/// name, is kept as text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExportEntry {
+    Data(Vec<u8>),
+    Text(String),
+}
+
+impl ExportEntry {
+    /// Parses one exported line.
+    pub fn parse(line: &str) -> Result<ExportEntry, RtdError> {
+        validate_line(line)?;
+        match decode_hex(line) {
+            Some(bytes) => Ok(ExportEntry::Data(bytes)),
+            None => Ok(ExportEntry::Text(line.to_string())),
+        }
+    }
+}
+
+// Decodes pairs of uppercase hex digits into bytes.
+fn decode_hex(line: &str) -> Option<Vec<u8>> {
+    line.as_bytes()
+        .chunks(2)
+        .map(|pair| match *pair {
+            [hi, lo] => Some(hex_digit(hi)? << 4 | hex_digit(lo)?),
+            _ => None,
+        })
+        .collect()
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+impl fmt::Display for ExportEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExportEntry::Data(ref bytes) => {
+                for b in bytes {
+                    write!(f, "{:02X}", b)?;
+                }
+                Ok(())
+            }
+            ExportEntry::Text(ref text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl FromStr for ExportEntry {
+    type Err = RtdError;
+
+    fn from_str(s: &str) -> Result<Self, RtdError> {
+        ExportEntry::parse(s)
+    }
+}
+
+// Exported lines are short strings of printable ASCII, which cannot contain
+// the `,` separator, nor start with `*` as the `*DONE` marker does.
+fn validate_line(line: &str) -> Result<(), RtdError> {
+    let valid = !line.is_empty()
+        && line.len() <= MAX_LINE_LENGTH
+        && !line.starts_with('*')
+        && line.chars().all(|c| c.is_ascii_graphic() && c != ',');
+    if valid {
+        Ok(())
+    } else {
+        Err(RtdErrorKind::InvalidImport.into())
+    }
+}
+
+/// One exported line per text line, so it can be saved to a file.
+impl fmt::Display for ExportedConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ExportedConfig {
+    type Err = RtdError;
+
+    fn from_str(s: &str) -> Result<Self, RtdError> {
+        let lines = s
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_string())
+            .collect();
+        let config = ExportedConfig::new(lines);
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_exported_config_one_line_each() {
+        let config = ExportedConfig::new(vec!["RTDCAL1".to_string(), "A9F2C0".to_string()]);
+        assert_eq!(format!("{}", config), "RTDCAL1\nA9F2C0\n");
+    }
+
+    #[test]
+    fn parses_exported_config_skipping_blank_lines() {
+        let config = "RTDCAL1\n\nA9F2C0\r\n".parse::<ExportedConfig>().unwrap();
+        assert_eq!(
+            config,
+            ExportedConfig::new(vec!["RTDCAL1".to_string(), "A9F2C0".to_string()])
+        );
+    }
+
+    #[test]
+    fn validates_exported_config() {
+        let config = ExportedConfig::new(vec!["RTDCAL1".to_string(), "A9F2C0".to_string()]);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn validating_invalid_exported_config_yields_error() {
+        let config = ExportedConfig::new(vec![]);
+        assert_eq!(
+            config.validate().err().unwrap().kind(),
+            RtdErrorKind::InvalidImport
+        );
+
+        let config = ExportedConfig::new(vec!["".to_string()]);
+        assert!(config.validate().is_err());
+
+        let config = ExportedConfig::new(vec!["ABCDEFGHIJKLM".to_string()]);
+        assert!(config.validate().is_err());
+
+        let config = ExportedConfig::new(vec!["*DONE".to_string()]);
+        assert!(config.validate().is_err());
+
+        let config = ExportedConfig::new(vec!["A9,F2".to_string()]);
+        assert!(config.validate().is_err());
+
+        let config = ExportedConfig::new(vec!["A9 F2".to_string()]);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn checks_exported_config_against_export_info() {
+        let config = ExportedConfig::new(vec!["RTDCAL1".to_string(), "A9F2C0".to_string()]);
+        let info = ExportedInfo {
+            lines: 2,
+            total_bytes: 13,
+        };
+        assert!(config.check_info(&info).is_ok());
+    }
+
+    #[test]
+    fn checking_truncated_exported_config_yields_error() {
+        let config = ExportedConfig::new(vec!["RTDCAL1".to_string()]);
+        let info = ExportedInfo {
+            lines: 2,
+            total_bytes: 13,
+        };
+        assert_eq!(
+            config.check_info(&info).err().unwrap().kind(),
+            RtdErrorKind::ExportMismatch
+        );
+
+        let config = ExportedConfig::new(vec!["RTDCAL1".to_string(), "A9F2".to_string()]);
+        assert!(config.check_info(&info).is_err());
+    }
+
+    #[test]
+    fn parses_export_entries() {
+        assert_eq!(
+            ExportEntry::parse("A9F2C0").unwrap(),
+            ExportEntry::Data(vec![0xA9, 0xF2, 0xC0])
+        );
+        assert_eq!(
+            ExportEntry::parse("Tank-A").unwrap(),
+            ExportEntry::Text("Tank-A".to_string())
+        );
+        // Odd length, or lowercase hex digits, are kept as text.
+        assert_eq!(
+            ExportEntry::parse("A9F").unwrap(),
+            ExportEntry::Text("A9F".to_string())
+        );
+        assert_eq!(
+            ExportEntry::parse("a9f2").unwrap(),
+            ExportEntry::Text("a9f2".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_export_entries_to_lines() {
+        let config = ExportedConfig::new(vec!["Tank-A".to_string(), "0A9F2C".to_string()]);
+        let entries = config.entries().unwrap();
+        assert_eq!(format!("{}", entries[1]), "0A9F2C");
+        assert_eq!(ExportedConfig::from_entries(&entries), config);
+    }
+
+    #[test]
+    fn parsing_invalid_export_entry_yields_error() {
+        assert!(ExportEntry::parse("").is_err());
+        assert!(ExportEntry::parse("*DONE").is_err());
+        assert!(ExportEntry::parse("A9,F2").is_err());
+    }
+
+    #[test]
+    fn parsing_invalid_exported_config_yields_error() {
+        assert!("".parse::<ExportedConfig>().is_err());
+        assert!("RTDCAL1\nIMPORT,A9F2C0\n"
+            .parse::<ExportedConfig>()
+            .is_err());
+    }
+}
@@ -0,0 +1,468 @@
+//! Smoothing of noisy temperature readings, and rejection of outliers.
+//!
+//! A `Filter` is fed temperatures one at a time, and returns each one
+//! filtered, in the scale it was given. It can be applied to any iterator of
+//! temperatures, or of readings, with `FilterExt::filtered()`, and to any
+//! stream of them by calling `Filter::apply()` as each one arrives, e.g. in
+//! the `map` of a `futures::Stream`.
+//!
+//! The window filters, `MovingAverage`, `Median`, and `Hampel`, combine the
+//! latest temperatures, while `Ewma` keeps a single running average, for
+//! low-latency smoothing.
+//!
+//! Temperatures in different scales are converted to the scale of the
+//! latest one before being combined, so a filter fed a mix of scales still
+//! returns sensible values.
+use std::collections::VecDeque;
+
+use super::response::{Float, Temperature, TemperatureScale};
+#[cfg(feature = "linux-i2c")]
+use super::sensor::TimestampedReading;
+
+/// A filter over a stream of temperatures.
+pub trait Filter {
+    /// Feeds `temperature` to the filter, returning the filtered
+    /// temperature, in the same scale.
+    fn apply(&mut self, temperature: Temperature) -> Temperature;
+
+    /// Forgets the temperatures fed so far, e.g. after the probe was
+    /// moved.
+    fn reset(&mut self);
+}
+
+/// The average of the latest temperatures, over a window of a fixed size.
+///
+/// Until the window is full, the average is over the temperatures fed so
+/// far.
+#[derive(Clone, Debug)]
+pub struct MovingAverage {
+    window: usize,
+    temperatures: VecDeque<Temperature>,
+}
+
+impl MovingAverage {
+    /// Averages over the latest `window` temperatures, and at least one.
+    pub fn new(window: usize) -> MovingAverage {
+        let window = window.max(1);
+        MovingAverage {
+            window,
+            temperatures: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Returns the number of temperatures averaged over.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+}
+
+impl Filter for MovingAverage {
+    fn apply(&mut self, temperature: Temperature) -> Temperature {
+        push_within(&mut self.temperatures, self.window, temperature);
+        let scale = temperature.scale();
+        let sum: Float = values_in(&self.temperatures, scale).iter().sum();
+        Temperature::new(scale, sum / self.temperatures.len() as Float)
+    }
+
+    fn reset(&mut self) {
+        self.temperatures.clear();
+    }
+}
+
+/// The median of the latest temperatures, over a window of a fixed size.
+///
+/// Unlike an average, the median ignores a lone spike, e.g. from a bus
+/// glitch or the bouncing contact of a probe, as long as it fills less than
+/// half of the window.
+#[derive(Clone, Debug)]
+pub struct Median {
+    window: usize,
+    temperatures: VecDeque<Temperature>,
+}
+
+impl Median {
+    /// Takes the median of the latest `window` temperatures, and at least
+    /// one. Odd windows are best, as the median of an even one is the
+    /// average of its two middle values.
+    pub fn new(window: usize) -> Median {
+        let window = window.max(1);
+        Median {
+            window,
+            temperatures: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Returns the number of temperatures the median is taken over.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+}
+
+impl Filter for Median {
+    fn apply(&mut self, temperature: Temperature) -> Temperature {
+        push_within(&mut self.temperatures, self.window, temperature);
+        let scale = temperature.scale();
+        let mut values = values_in(&self.temperatures, scale);
+        Temperature::new(scale, median(&mut values))
+    }
+
+    fn reset(&mut self) {
+        self.temperatures.clear();
+    }
+}
+
+/// Replaces outliers with the median of the latest temperatures, as a
+/// Hampel filter does, and passes every other temperature through as is.
+///
+/// A temperature is an outlier when it is further from the median of the
+/// window than `threshold` times its scaled median absolute deviation,
+/// which estimates the standard deviation of the readings. Readings that
+/// settle at a new level are let through once they fill half the window.
+#[derive(Clone, Debug)]
+pub struct Hampel {
+    window: usize,
+    threshold: Float,
+    temperatures: VecDeque<Temperature>,
+}
+
+impl Hampel {
+    /// Scales the median absolute deviation of normally distributed
+    /// readings to their standard deviation.
+    const MAD_SCALE: Float = 1.4826;
+
+    /// Rejects outliers among the latest `window` temperatures, and at
+    /// least one, that are more than 3 standard deviations off.
+    pub fn new(window: usize) -> Hampel {
+        let window = window.max(1);
+        Hampel {
+            window,
+            threshold: 3.0,
+            temperatures: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Sets how many standard deviations off a temperature is an outlier.
+    pub fn threshold(mut self, threshold: Float) -> Hampel {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Returns the number of temperatures outliers are looked for among.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+}
+
+impl Filter for Hampel {
+    fn apply(&mut self, temperature: Temperature) -> Temperature {
+        push_within(&mut self.temperatures, self.window, temperature);
+        let scale = temperature.scale();
+        let mut values = values_in(&self.temperatures, scale);
+        let center = median(&mut values);
+        let mut deviations: Vec<Float> = values.iter().map(|v| (v - center).abs()).collect();
+        let limit = self.threshold * Hampel::MAD_SCALE * median(&mut deviations);
+        if (temperature.value() - center).abs() > limit {
+            Temperature::new(scale, center)
+        } else {
+            temperature
+        }
+    }
+
+    fn reset(&mut self) {
+        self.temperatures.clear();
+    }
+}
+
+/// The exponentially weighted moving average of the temperatures.
+///
+/// Each temperature moves the average by `alpha` times its distance to it,
+/// so the latest temperatures weigh the most. Unlike a window filter, it
+/// keeps no history, and follows changes with little lag for high `alpha`,
+/// which suits control loops.
+#[derive(Copy, Clone, Debug)]
+pub struct Ewma {
+    alpha: Float,
+    average: Option<Temperature>,
+}
+
+impl Ewma {
+    /// Weighs each new temperature by `alpha`, which is clamped to
+    /// `0.0..=1.0`. An `alpha` of 1 passes every temperature through, and
+    /// lower ones smooth more.
+    pub fn new(alpha: Float) -> Ewma {
+        let alpha = if alpha > 0.0 { alpha.min(1.0) } else { 0.0 };
+        Ewma {
+            alpha,
+            average: None,
+        }
+    }
+
+    /// Returns the weight of each new temperature.
+    pub fn alpha(&self) -> Float {
+        self.alpha
+    }
+
+    /// Returns the current average, if any temperature was fed so far.
+    pub fn average(&self) -> Option<Temperature> {
+        self.average
+    }
+}
+
+impl Filter for Ewma {
+    /// The first temperature starts the average as is.
+    fn apply(&mut self, temperature: Temperature) -> Temperature {
+        let average = match self.average {
+            Some(average) => {
+                let previous = average.to_scale(temperature.scale()).value();
+                let value = previous + self.alpha * (temperature.value() - previous);
+                Temperature::new(temperature.scale(), value)
+            }
+            None => temperature,
+        };
+        self.average = Some(average);
+        average
+    }
+
+    fn reset(&mut self) {
+        self.average = None;
+    }
+}
+
+// Adds `temperature` to the latest ones, dropping the oldest one if there
+// are already `window` of them.
+fn push_within(temperatures: &mut VecDeque<Temperature>, window: usize, temperature: Temperature) {
+    if temperatures.len() == window {
+        temperatures.pop_front();
+    }
+    temperatures.push_back(temperature);
+}
+
+// The values of `temperatures`, converted to `scale`.
+fn values_in(temperatures: &VecDeque<Temperature>, scale: TemperatureScale) -> Vec<Float> {
+    temperatures
+        .iter()
+        .map(|t| t.to_scale(scale).value())
+        .collect()
+}
+
+// The median of `values`, which are sorted in place. There is always at
+// least one value, as the latest temperature is in the window.
+fn median(values: &mut [Float]) -> Float {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 1 {
+        values[mid]
+    } else {
+        (values[mid - 1] + values[mid]) / 2.0
+    }
+}
+
+/// An item of a stream that a `Filter` can be applied to.
+pub trait Filterable: Sized {
+    /// Returns the temperature to filter, if any.
+    fn temperature(&self) -> Option<Temperature>;
+
+    /// Replaces the temperature with the filtered one.
+    fn with_temperature(self, temperature: Temperature) -> Self;
+}
+
+impl Filterable for Temperature {
+    fn temperature(&self) -> Option<Temperature> {
+        Some(*self)
+    }
+
+    fn with_temperature(self, temperature: Temperature) -> Temperature {
+        temperature
+    }
+}
+
+/// Keeps the timestamp of the reading.
+#[cfg(feature = "linux-i2c")]
+impl Filterable for TimestampedReading {
+    fn temperature(&self) -> Option<Temperature> {
+        Some(self.temperature)
+    }
+
+    fn with_temperature(self, temperature: Temperature) -> TimestampedReading {
+        TimestampedReading {
+            temperature,
+            ..self
+        }
+    }
+}
+
+/// Errors are passed through, without being fed to the filter.
+impl<T: Filterable, E> Filterable for Result<T, E> {
+    fn temperature(&self) -> Option<Temperature> {
+        match *self {
+            Ok(ref item) => item.temperature(),
+            Err(_) => None,
+        }
+    }
+
+    fn with_temperature(self, temperature: Temperature) -> Result<T, E> {
+        self.map(|item| item.with_temperature(temperature))
+    }
+}
+
+/// Iterator adapter returned by `FilterExt::filtered()`.
+#[derive(Clone, Debug)]
+pub struct Filtered<I, F> {
+    iter: I,
+    filter: F,
+}
+
+impl<I, F> Filtered<I, F> {
+    /// Returns the filter, e.g. to reset it.
+    pub fn filter_mut(&mut self) -> &mut F {
+        &mut self.filter
+    }
+}
+
+impl<I, F> Iterator for Filtered<I, F>
+where
+    I: Iterator,
+    I::Item: Filterable,
+    F: Filter,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let item = self.iter.next()?;
+        Some(match item.temperature() {
+            Some(temperature) => {
+                let filtered = self.filter.apply(temperature);
+                item.with_temperature(filtered)
+            }
+            None => item,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Applies filters to iterators of temperatures, or of readings, such as
+/// the `Readings` of a sensor.
+pub trait FilterExt: Iterator + Sized
+where
+    Self::Item: Filterable,
+{
+    /// Applies `filter` to every temperature.
+    fn filtered<F: Filter>(self, filter: F) -> Filtered<Self, F> {
+        Filtered { iter: self, filter }
+    }
+}
+
+impl<I> FilterExt for I
+where
+    I: Iterator,
+    I::Item: Filterable,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values<I: Iterator<Item = Temperature>>(temperatures: I) -> Vec<Float> {
+        temperatures.map(|t| t.value()).collect()
+    }
+
+    #[test]
+    fn averages_over_the_window() {
+        let temperatures = [20.0, 22.0, 24.0, 26.0]
+            .iter()
+            .map(|&value| Temperature::Celsius(value));
+        assert_eq!(
+            values(temperatures.filtered(MovingAverage::new(2))),
+            [20.0, 21.0, 23.0, 25.0]
+        );
+
+        let mut average = MovingAverage::new(0);
+        assert_eq!(average.window(), 1);
+        assert_eq!(average.apply(Temperature::Celsius(20.0)).value(), 20.0);
+        assert_eq!(average.apply(Temperature::Celsius(22.0)).value(), 22.0);
+    }
+
+    #[test]
+    fn averages_in_the_scale_of_the_latest_temperature() {
+        let mut average = MovingAverage::new(2);
+        average.apply(Temperature::Celsius(0.0));
+        let averaged = average.apply(Temperature::Kelvin(283.15));
+        assert_eq!(averaged.scale(), TemperatureScale::Kelvin);
+        assert!((averaged.value() - 278.15).abs() < 1e-3);
+
+        average.reset();
+        assert_eq!(average.apply(Temperature::Celsius(30.0)).value(), 30.0);
+    }
+
+    #[test]
+    fn passes_errors_through() {
+        let readings: Vec<Result<Temperature, ()>> = vec![
+            Ok(Temperature::Celsius(20.0)),
+            Err(()),
+            Ok(Temperature::Celsius(22.0)),
+        ];
+        let filtered: Vec<_> = readings
+            .into_iter()
+            .filtered(MovingAverage::new(3))
+            .map(|reading| reading.map(|t| t.value()))
+            .collect();
+        assert_eq!(filtered, [Ok(20.0), Err(()), Ok(21.0)]);
+    }
+
+    #[test]
+    fn median_ignores_spikes() {
+        let temperatures = [20.0, 20.5, 85.0, 21.0, 21.5]
+            .iter()
+            .map(|&value| Temperature::Celsius(value));
+        assert_eq!(
+            values(temperatures.filtered(Median::new(3))),
+            [20.0, 20.25, 20.5, 21.0, 21.5]
+        );
+    }
+
+    #[test]
+    fn hampel_replaces_outliers_only() {
+        let temperatures = [20.0, 20.5, 20.0, 85.0, 20.5, 21.0]
+            .iter()
+            .map(|&value| Temperature::Celsius(value));
+        assert_eq!(
+            values(temperatures.filtered(Hampel::new(5))),
+            [20.0, 20.5, 20.0, 20.25, 20.5, 21.0]
+        );
+
+        // A new level is let through once it fills half the window.
+        let mut hampel = Hampel::new(3);
+        hampel.apply(Temperature::Celsius(20.0));
+        hampel.apply(Temperature::Celsius(20.0));
+        assert_eq!(hampel.apply(Temperature::Celsius(30.0)).value(), 20.0);
+        assert_eq!(hampel.apply(Temperature::Celsius(30.0)).value(), 30.0);
+    }
+
+    #[test]
+    fn ewma_weighs_the_latest_temperatures_most() {
+        let temperatures = [20.0, 24.0, 24.0, 24.0]
+            .iter()
+            .map(|&value| Temperature::Celsius(value));
+        assert_eq!(
+            values(temperatures.filtered(Ewma::new(0.5))),
+            [20.0, 22.0, 23.0, 23.5]
+        );
+
+        let mut ewma = Ewma::new(2.0);
+        assert_eq!(ewma.alpha(), 1.0);
+        ewma.apply(Temperature::Celsius(20.0));
+        assert_eq!(ewma.apply(Temperature::Celsius(25.0)).value(), 25.0);
+
+        let mut ewma = Ewma::new(0.5);
+        ewma.apply(Temperature::Celsius(0.0));
+        let averaged = ewma.apply(Temperature::Kelvin(283.15));
+        assert_eq!(averaged.scale(), TemperatureScale::Kelvin);
+        assert!((averaged.value() - 278.15).abs() < 1e-3);
+        ewma.reset();
+        assert_eq!(ewma.average(), None);
+    }
+}
@@ -0,0 +1,83 @@
+//! Commands over FTDI USB-to-I2C bridges, such as the FT232H.
+//!
+//! The bridge is driven by `ftdi-embedded-hal`, which works on Windows and
+//! macOS as well as on Linux, without a `/dev/i2c` node. Any MPSSE device
+//! it supports can be used, e.g. a `libftd2xx::Ft232h`.
+use std::error::Error;
+
+use super::errors::{ResultExt, RtdError, RtdErrorKind};
+use super::hal::{HalDevice, StdDelay};
+
+use ftdi_embedded_hal::ftdi_mpsse::MpsseCmdExecutor;
+use ftdi_embedded_hal::{FtHal, I2c};
+
+/// Default frequency of the I2C clock, in Hz.
+pub const DEFAULT_FREQUENCY: u32 = 100_000;
+
+/// An EZO RTD chip behind an FTDI bridge.
+pub type FtdiDevice<Device> = HalDevice<I2c<Device>, StdDelay>;
+
+/// Sets the bridge up as an I2C master clocked at `frequency` Hz, and uses
+/// the chip at the given 7-bit address.
+///
+/// An error of the bridge is kept as the `source()` of the returned error.
+pub fn open<Device, E>(
+    device: Device,
+    frequency: u32,
+    address: u8,
+) -> Result<FtdiDevice<Device>, RtdError>
+where
+    Device: MpsseCmdExecutor<Error = E>,
+    E: Error + Send + Sync + 'static,
+    ftdi_embedded_hal::Error<E>: From<E>,
+{
+    let hal = FtHal::init_freq(device, frequency).context(RtdErrorKind::DeviceOpen)?;
+    let i2c = hal.i2c().context(RtdErrorKind::DeviceOpen)?;
+    Ok(HalDevice::new(i2c, StdDelay, address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ftdi_embedded_hal::ftdi_mpsse::MpsseSettings;
+    use std::fmt;
+
+    #[derive(Debug)]
+    struct Unplugged;
+
+    impl fmt::Display for Unplugged {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "the bridge was unplugged")
+        }
+    }
+
+    impl Error for Unplugged {}
+
+    /// A bridge that fails every command.
+    struct UnpluggedBridge;
+
+    impl MpsseCmdExecutor for UnpluggedBridge {
+        type Error = Unplugged;
+
+        fn init(&mut self, _settings: &MpsseSettings) -> Result<(), Unplugged> {
+            Err(Unplugged)
+        }
+
+        fn send(&mut self, _data: &[u8]) -> Result<(), Unplugged> {
+            Err(Unplugged)
+        }
+
+        fn xfer(&mut self, _cmd: &[u8], _buf: &mut [u8]) -> Result<(), Unplugged> {
+            Err(Unplugged)
+        }
+    }
+
+    #[test]
+    fn errors_of_the_bridge_are_kept_as_sources() {
+        let err = open(UnpluggedBridge, DEFAULT_FREQUENCY, 0x66)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), RtdErrorKind::DeviceOpen);
+        assert!(err.source().is_some());
+    }
+}
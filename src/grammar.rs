@@ -0,0 +1,1671 @@
+//! The command and response grammar of the RTD EZO chip.
+//!
+//! Everything here depends on `core` alone: there is no allocation, no
+//! `std`, and no I2C, so microcontroller firmware can build the same
+//! command strings, and parse the same responses, as the rest of the crate.
+//! The crate itself needs `std`, so firmware includes this file as a module
+//! of its own; `ci/grammar-no-std` does so to build it for a `no_std`
+//! target.
+//! The `parse()` functions in `response` are built on these parsers.
+//!
+//! With the `defmt` feature, the response and temperature types implement
+//! `defmt::Format`, so firmware can log them, e.g. over RTT. With the
+//! `approx` feature, temperatures, differences, and readings implement
+//! `approx::AbsDiffEq` and `approx::RelativeEq`, for comparisons with a
+//! tolerance.
+use core::cmp::Ordering;
+use core::convert::TryFrom;
+use core::fmt;
+use core::iter::Sum;
+use core::ops::{Add, Sub};
+use core::str::FromStr;
+use core::time::Duration;
+
+#[cfg(feature = "approx")]
+use approx::{AbsDiffEq, RelativeEq};
+
+/// The floating-point type of readings and temperatures: `f64`, or `f32`
+/// with the `f32` feature, for targets that emulate doubles in software.
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+/// The floating-point type of readings and temperatures: `f64`, or `f32`
+/// with the `f32` feature, for targets that emulate doubles in software.
+#[cfg(feature = "f32")]
+pub type Float = f32;
+
+/// `R` command.
+pub const READING: &str = "R";
+/// `CAL,?` command.
+pub const CALIBRATION_STATE: &str = "CAL,?";
+/// `D,0` command.
+pub const DATALOGGER_DISABLE: &str = "D,0";
+/// `D,?` command.
+pub const DATALOGGER_INTERVAL: &str = "D,?";
+/// `M,CLEAR` command.
+pub const MEMORY_CLEAR: &str = "M,CLEAR";
+/// `M` command.
+pub const MEMORY_RECALL: &str = "M";
+/// `M,?` command.
+pub const MEMORY_RECALL_LAST: &str = "M,?";
+/// `NAME,?` command.
+pub const NAME_STATE: &str = "NAME,?";
+/// `S,C` command.
+pub const SCALE_CELSIUS: &str = "S,C";
+/// `S,K` command.
+pub const SCALE_KELVIN: &str = "S,K";
+/// `S,F` command.
+pub const SCALE_FAHRENHEIT: &str = "S,F";
+/// `S,?` command.
+pub const SCALE_STATE: &str = "S,?";
+
+// The commands shared by every EZO chip, defined in `ezo_common`.
+
+/// `CAL,CLEAR` command.
+pub const CALIBRATION_CLEAR: &str = "CAL,CLEAR";
+/// `I` command.
+pub const DEVICE_INFORMATION: &str = "I";
+/// `EXPORT` command.
+pub const EXPORT: &str = "EXPORT";
+/// `EXPORT,?` command.
+pub const EXPORT_INFO: &str = "EXPORT,?";
+/// `FACTORY` command.
+pub const FACTORY: &str = "FACTORY";
+/// `F` command.
+pub const FIND: &str = "F";
+/// `L,0` command.
+pub const LED_OFF: &str = "L,0";
+/// `L,1` command.
+pub const LED_ON: &str = "L,1";
+/// `L,?` command.
+pub const LED_STATE: &str = "L,?";
+/// `PLOCK,0` command.
+pub const PROTOCOL_LOCK_DISABLE: &str = "PLOCK,0";
+/// `PLOCK,1` command.
+pub const PROTOCOL_LOCK_ENABLE: &str = "PLOCK,1";
+/// `PLOCK,?` command.
+pub const PROTOCOL_LOCK_STATE: &str = "PLOCK,?";
+/// `SLEEP` command.
+pub const SLEEP: &str = "SLEEP";
+/// `STATUS` command.
+pub const STATUS: &str = "STATUS";
+
+/// Writes the `CAL,t` command, with two decimals.
+pub fn write_calibration_temperature<W: fmt::Write>(w: &mut W, temperature: f64) -> fmt::Result {
+    write!(w, "CAL,{:.*}", 2, temperature)
+}
+
+/// Writes the `D,n` command.
+pub fn write_datalogger_period<W: fmt::Write>(w: &mut W, seconds: u32) -> fmt::Result {
+    write!(w, "D,{}", seconds)
+}
+
+/// Writes the `NAME,n` command.
+pub fn write_name<W: fmt::Write>(w: &mut W, name: &str) -> fmt::Result {
+    write!(w, "NAME,{}", name)
+}
+
+/// Error returned when a response does not follow the grammar.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ParseError;
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "could not parse response")
+    }
+}
+
+/// Error returned when a temperature is one that no probe can measure.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InvalidTemperature {
+    /// The temperature is below absolute zero.
+    BelowAbsoluteZero(Temperature),
+    /// The temperature is not a finite number, e.g. NaN.
+    NotFinite(Temperature),
+}
+
+impl fmt::Display for InvalidTemperature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InvalidTemperature::BelowAbsoluteZero(temperature) => {
+                write!(f, "temperature below absolute zero: {:#}", temperature)
+            }
+            InvalidTemperature::NotFinite(temperature) => {
+                write!(f, "temperature is not a finite number: {:#}", temperature)
+            }
+        }
+    }
+}
+
+/// Removes the padding that some transports leave after a response:
+/// trailing NUL bytes, carriage returns, line feeds, and spaces.
+pub fn trim_response(response: &str) -> &str {
+    response.trim_end_matches(['\0', '\r', '\n', ' '])
+}
+
+/// Error returned when a data logger interval is neither 0, nor between 10
+/// and 320,000 seconds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InvalidInterval(pub u32);
+
+impl fmt::Display for InvalidInterval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid data logger interval: {} seconds", self.0)
+    }
+}
+
+/// Error returned when temperatures in different scales are summed or
+/// averaged, without converting them to one scale first.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MixedScales;
+
+impl fmt::Display for MixedScales {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "temperatures are in different scales")
+    }
+}
+
+/// Calibration status of the RTD EZO chip.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CalibrationStatus {
+    Calibrated,
+    NotCalibrated,
+}
+
+/// Parses the response to `CAL,?`, e.g. `?CAL,1`.
+pub fn parse_calibration_status(response: &str) -> Result<CalibrationStatus, ParseError> {
+    let rest = match response.get(..5) {
+        Some("?CAL,") => &response[5..],
+        _ => return Err(ParseError),
+    };
+    let mut split = rest.split(',');
+
+    let calibration = match split.next() {
+        Some("1") => CalibrationStatus::Calibrated,
+        Some("0") => CalibrationStatus::NotCalibrated,
+        _ => return Err(ParseError),
+    };
+
+    match split.next() {
+        None => Ok(calibration),
+        _ => Err(ParseError),
+    }
+}
+
+impl fmt::Debug for CalibrationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CalibrationStatus::Calibrated => write!(f, "?CAL,1"),
+            CalibrationStatus::NotCalibrated => write!(f, "?CAL,0"),
+        }
+    }
+}
+
+impl fmt::Display for CalibrationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CalibrationStatus::Calibrated => write!(f, "calibrated"),
+            CalibrationStatus::NotCalibrated => write!(f, "not-calibrated"),
+        }
+    }
+}
+
+/// Seconds between automatic logging of readings
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DataLoggerStorageIntervalSeconds(pub u32);
+
+/// Parses the response to `D,?`, e.g. `?D,60`. The interval is either 0,
+/// when the data logger is disabled, or between 10 and 320,000 seconds.
+pub fn parse_datalogger_interval(
+    response: &str,
+) -> Result<DataLoggerStorageIntervalSeconds, ParseError> {
+    let num_str = match response.get(..3) {
+        Some("?D,") => &response[3..],
+        _ => return Err(ParseError),
+    };
+    let num = u32::from_str(num_str).map_err(|_| ParseError)?;
+    DataLoggerStorageIntervalSeconds::new(num).map_err(|_| ParseError)
+}
+
+impl DataLoggerStorageIntervalSeconds {
+    /// Creates an interval of `seconds`, which is either 0, to disable the
+    /// data logger, or between 10 and 320,000.
+    pub fn new(seconds: u32) -> Result<DataLoggerStorageIntervalSeconds, InvalidInterval> {
+        match seconds {
+            0 | 10..=320_000 => Ok(DataLoggerStorageIntervalSeconds(seconds)),
+            _ => Err(InvalidInterval(seconds)),
+        }
+    }
+
+    /// Returns the interval as a `Duration`, which is zero when the data
+    /// logger is disabled.
+    pub fn as_duration(&self) -> Duration {
+        Duration::from_secs(u64::from(self.0))
+    }
+}
+
+/// Rounds a duration to the nearest second, or returns `None` if the
+/// number of seconds does not fit in a `u32`.
+pub fn duration_to_seconds(duration: Duration) -> Option<u32> {
+    let round_up = duration.subsec_millis() >= 500;
+    let seconds = duration.as_secs().checked_add(u64::from(round_up))?;
+    u32::try_from(seconds).ok()
+}
+
+/// Rounds the duration to the nearest second, which must make a valid
+/// interval, as with `DataLoggerStorageIntervalSeconds::new()`.
+impl TryFrom<Duration> for DataLoggerStorageIntervalSeconds {
+    type Error = InvalidInterval;
+
+    fn try_from(duration: Duration) -> Result<DataLoggerStorageIntervalSeconds, InvalidInterval> {
+        let seconds = duration_to_seconds(duration).unwrap_or(u32::MAX);
+        DataLoggerStorageIntervalSeconds::new(seconds)
+    }
+}
+
+impl From<DataLoggerStorageIntervalSeconds> for Duration {
+    fn from(interval: DataLoggerStorageIntervalSeconds) -> Duration {
+        interval.as_duration()
+    }
+}
+
+impl fmt::Debug for DataLoggerStorageIntervalSeconds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "?D,{}", self.0)
+    }
+}
+
+impl fmt::Display for DataLoggerStorageIntervalSeconds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A recalled temperature reading from memory.
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MemoryReading {
+    pub location: u32,
+    pub reading: Float,
+}
+
+/// Parses the response to `M` or `M,?`, e.g. `3,21.500`.
+pub fn parse_memory_reading(response: &str) -> Result<MemoryReading, ParseError> {
+    let mut split = response.split(',');
+
+    let location = match split.next() {
+        Some(location_str) => u32::from_str(location_str).map_err(|_| ParseError)?,
+        None => return Err(ParseError),
+    };
+
+    let reading = match split.next() {
+        Some(reading_str) => parse_reading_value(reading_str)?,
+        None => return Err(ParseError),
+    };
+
+    match split.next() {
+        None => Ok(MemoryReading { location, reading }),
+        _ => Err(ParseError),
+    }
+}
+
+impl MemoryReading {
+    /// Attaches the scale the device is configured to use, which the
+    /// recalled reading itself does not carry, returning its location and
+    /// temperature.
+    pub fn with_scale(&self, scale: TemperatureScale) -> (u32, Temperature) {
+        (self.location, Temperature::new(scale, self.reading))
+    }
+}
+
+impl fmt::Debug for MemoryReading {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.location, self.reading)
+    }
+}
+
+impl fmt::Display for MemoryReading {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.location, self.reading)
+    }
+}
+
+/// Temperature scales supported by the RTD EZO sensor.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TemperatureScale {
+    Celsius,
+    Kelvin,
+    Fahrenheit,
+}
+
+/// Parses the response to `S,?`, e.g. `?S,C`.
+pub fn parse_temperature_scale(response: &str) -> Result<TemperatureScale, ParseError> {
+    match response {
+        "?S,C" => Ok(TemperatureScale::Celsius),
+        "?S,K" => Ok(TemperatureScale::Kelvin),
+        "?S,F" => Ok(TemperatureScale::Fahrenheit),
+        _ => Err(ParseError),
+    }
+}
+
+impl TemperatureScale {
+    /// Returns the unit symbol of the scale: `°C`, `K`, or `°F`.
+    pub fn symbol(&self) -> &'static str {
+        match *self {
+            TemperatureScale::Celsius => "°C",
+            TemperatureScale::Kelvin => "K",
+            TemperatureScale::Fahrenheit => "°F",
+        }
+    }
+}
+
+/// Parses a scale written by a person, e.g. in a configuration file or on
+/// the command line, ignoring case and surrounding whitespace: `c`,
+/// `celsius`, or `°C`; `k` or `kelvin`; `f`, `fahrenheit`, or `°F`.
+///
+/// Responses from the device, such as `?S,C`, are parsed by
+/// `parse_temperature_scale()` instead.
+impl<'a> TryFrom<&'a str> for TemperatureScale {
+    type Error = ParseError;
+
+    fn try_from(value: &'a str) -> Result<TemperatureScale, ParseError> {
+        let value = value.trim();
+        let is = |names: &[&str]| names.iter().any(|name| value.eq_ignore_ascii_case(name));
+        if is(&["c", "celsius", "°c"]) {
+            Ok(TemperatureScale::Celsius)
+        } else if is(&["k", "kelvin"]) {
+            Ok(TemperatureScale::Kelvin)
+        } else if is(&["f", "fahrenheit", "°f"]) {
+            Ok(TemperatureScale::Fahrenheit)
+        } else {
+            Err(ParseError)
+        }
+    }
+}
+
+impl fmt::Debug for TemperatureScale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = match *self {
+            TemperatureScale::Celsius => "?S,C",
+            TemperatureScale::Kelvin => "?S,K",
+            TemperatureScale::Fahrenheit => "?S,F",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+impl fmt::Display for TemperatureScale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = match *self {
+            TemperatureScale::Celsius => "celsius",
+            TemperatureScale::Kelvin => "kelvin",
+            TemperatureScale::Fahrenheit => "fahrenheit",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+/// A temperature value from a temperature reading
+///
+/// Temperatures in different scales are compared by converting both to
+/// kelvin, so `Celsius(0.0) == Kelvin(273.15)`. See the `PartialEq`
+/// implementation for the caveats.
+#[derive(Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Temperature {
+    Celsius(Float),
+    Kelvin(Float),
+    Fahrenheit(Float),
+}
+
+impl Temperature {
+    /// Creates a new temperature value from a given temperature
+    /// `scale`.  Note that this function simply copies the `value`
+    /// regardless of the `scale`; it does not validate e.g. that a
+    /// Kelvin value is not negative.
+    pub fn new(scale: TemperatureScale, value: Float) -> Temperature {
+        match scale {
+            TemperatureScale::Celsius => Temperature::Celsius(value),
+            TemperatureScale::Kelvin => Temperature::Kelvin(value),
+            TemperatureScale::Fahrenheit => Temperature::Fahrenheit(value),
+        }
+    }
+
+    /// Creates a new temperature value from a given temperature `scale`,
+    /// unless it is below absolute zero: −273.15 °C, 0 K, or −459.67 °F, or
+    /// it is not a finite number.
+    pub fn new_checked(
+        scale: TemperatureScale,
+        value: Float,
+    ) -> Result<Temperature, InvalidTemperature> {
+        let absolute_zero = match scale {
+            TemperatureScale::Celsius => -273.15,
+            TemperatureScale::Kelvin => 0.0,
+            TemperatureScale::Fahrenheit => -459.67,
+        };
+        let temperature = Temperature::new(scale, value);
+        if !value.is_finite() {
+            Err(InvalidTemperature::NotFinite(temperature))
+        } else if value < absolute_zero {
+            Err(InvalidTemperature::BelowAbsoluteZero(temperature))
+        } else {
+            Ok(temperature)
+        }
+    }
+
+    /// Returns the scale of the temperature.
+    pub fn scale(&self) -> TemperatureScale {
+        match *self {
+            Temperature::Celsius(_) => TemperatureScale::Celsius,
+            Temperature::Kelvin(_) => TemperatureScale::Kelvin,
+            Temperature::Fahrenheit(_) => TemperatureScale::Fahrenheit,
+        }
+    }
+
+    /// Returns the value of the temperature, in its own scale.
+    pub fn value(&self) -> Float {
+        match *self {
+            Temperature::Celsius(t) | Temperature::Kelvin(t) | Temperature::Fahrenheit(t) => t,
+        }
+    }
+
+    /// Converts the temperature to degrees Celsius.
+    pub fn to_celsius(&self) -> Temperature {
+        match *self {
+            Temperature::Celsius(t) => Temperature::Celsius(t),
+            Temperature::Kelvin(t) => Temperature::Celsius(t - 273.15),
+            Temperature::Fahrenheit(t) => Temperature::Celsius((t - 32.0) * 5.0 / 9.0),
+        }
+    }
+
+    /// Converts the temperature to kelvin.
+    pub fn to_kelvin(&self) -> Temperature {
+        match *self {
+            Temperature::Kelvin(t) => Temperature::Kelvin(t),
+            _ => Temperature::Kelvin(self.to_celsius().value() + 273.15),
+        }
+    }
+
+    /// Converts the temperature to degrees Fahrenheit.
+    pub fn to_fahrenheit(&self) -> Temperature {
+        match *self {
+            Temperature::Fahrenheit(t) => Temperature::Fahrenheit(t),
+            _ => Temperature::Fahrenheit(self.to_celsius().value() * 9.0 / 5.0 + 32.0),
+        }
+    }
+
+    /// Converts the temperature to the given scale.
+    pub fn to_scale(&self, scale: TemperatureScale) -> Temperature {
+        match scale {
+            TemperatureScale::Celsius => self.to_celsius(),
+            TemperatureScale::Kelvin => self.to_kelvin(),
+            TemperatureScale::Fahrenheit => self.to_fahrenheit(),
+        }
+    }
+
+    /// Returns the temperature in degrees Rankine.
+    ///
+    /// The device has no Rankine scale, so the value is a plain number,
+    /// for hosts that report in it.
+    pub fn to_rankine(&self) -> Float {
+        match *self {
+            Temperature::Fahrenheit(t) => t + 459.67,
+            _ => self.to_kelvin().value() * 9.0 / 5.0,
+        }
+    }
+
+    /// Creates a temperature in degrees Fahrenheit, which share their size
+    /// with degrees Rankine, from a value in degrees Rankine.
+    pub fn from_rankine(value: Float) -> Temperature {
+        Temperature::Fahrenheit(value - 459.67)
+    }
+}
+
+impl Temperature {
+    /// Adds up temperatures in one scale, returning `None` if there are
+    /// none.
+    ///
+    /// Temperatures in different scales yield an error; they can be
+    /// converted first, e.g. with `.map(|t| t.to_celsius())`.
+    pub fn sum<I>(temperatures: I) -> Result<Option<Temperature>, MixedScales>
+    where
+        I: IntoIterator<Item = Temperature>,
+    {
+        Ok(Temperature::sum_and_count(temperatures)?.map(|(sum, _)| sum))
+    }
+
+    /// Averages temperatures in one scale, returning `None` if there are
+    /// none, e.g. to report the mean of a minute of readings.
+    ///
+    /// Temperatures in different scales yield an error, as with `sum()`.
+    pub fn mean<I>(temperatures: I) -> Result<Option<Temperature>, MixedScales>
+    where
+        I: IntoIterator<Item = Temperature>,
+    {
+        Ok(Temperature::sum_and_count(temperatures)?
+            .map(|(sum, count)| Temperature::new(sum.scale(), sum.value() / count as Float)))
+    }
+
+    fn sum_and_count<I>(temperatures: I) -> Result<Option<(Temperature, usize)>, MixedScales>
+    where
+        I: IntoIterator<Item = Temperature>,
+    {
+        let mut total: Option<(Temperature, usize)> = None;
+        for temperature in temperatures {
+            total = match total {
+                None => Some((temperature, 1)),
+                Some((sum, count)) if sum.scale() == temperature.scale() => Some((
+                    Temperature::new(sum.scale(), sum.value() + temperature.value()),
+                    count + 1,
+                )),
+                Some(_) => return Err(MixedScales),
+            };
+        }
+        Ok(total)
+    }
+}
+
+/// Compares temperatures as `partial_cmp()` does: values in the same scale
+/// directly, and values in different scales after converting both to
+/// kelvin.
+///
+/// Since the conversions round, equality across scales depends on the
+/// values: `Fahrenheit(98.6) == Celsius(37.0)`, but `Fahrenheit(-36.04)`
+/// and `Celsius(-37.8)` differ in the last bit of their kelvin. Nor is it
+/// transitive: two Celsius values closer than a rounding step of 273.15
+/// both equal the same `Kelvin` value, without being equal to each other.
+/// To tell whether two temperatures match, use `is_within()` with a
+/// tolerance instead.
+///
+/// Before, temperatures in different scales were never equal; code that
+/// relied on `==` to tell the scales apart should compare `scale()`.
+impl PartialEq for Temperature {
+    fn eq(&self, other: &Temperature) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Temperature {
+    fn partial_cmp(&self, other: &Temperature) -> Option<Ordering> {
+        if self.scale() == other.scale() {
+            self.value().partial_cmp(&other.value())
+        } else {
+            self.to_kelvin()
+                .value()
+                .partial_cmp(&other.to_kelvin().value())
+        }
+    }
+}
+
+/// A range of temperatures, from `low` to `high`, both included, e.g. the
+/// bounds of an alarm.
+///
+/// The bounds and the temperatures checked against them may be in any
+/// scales.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TemperatureRange {
+    pub low: Temperature,
+    pub high: Temperature,
+}
+
+impl TemperatureRange {
+    /// The range a PT-1000 probe can measure: −126 °C to 1254 °C.
+    pub const PROBE: TemperatureRange = TemperatureRange {
+        low: Temperature::Celsius(-126.0),
+        high: Temperature::Celsius(1254.0),
+    };
+
+    /// Creates the range from `low` to `high`.
+    pub fn new(low: Temperature, high: Temperature) -> TemperatureRange {
+        TemperatureRange { low, high }
+    }
+
+    /// Returns `true` if `temperature` is neither below `low`, nor above
+    /// `high`.
+    pub fn contains(&self, temperature: &Temperature) -> bool {
+        *temperature >= self.low && *temperature <= self.high
+    }
+}
+
+/// The difference between two temperatures, in the scale of the left-hand
+/// side.
+impl Sub for Temperature {
+    type Output = TemperatureDelta;
+
+    fn sub(self, other: Temperature) -> TemperatureDelta {
+        let degrees = self.value() - other.to_scale(self.scale()).value();
+        TemperatureDelta::new(self.scale(), degrees)
+    }
+}
+
+/// Raises the temperature by `delta`, keeping its scale.
+impl Add<TemperatureDelta> for Temperature {
+    type Output = Temperature;
+
+    fn add(self, delta: TemperatureDelta) -> Temperature {
+        Temperature::new(self.scale(), self.value() + delta.in_scale(self.scale()))
+    }
+}
+
+/// Lowers the temperature by `delta`, keeping its scale.
+impl Sub<TemperatureDelta> for Temperature {
+    type Output = Temperature;
+
+    fn sub(self, delta: TemperatureDelta) -> Temperature {
+        Temperature::new(self.scale(), self.value() - delta.in_scale(self.scale()))
+    }
+}
+
+impl Temperature {
+    /// Returns `true` if the temperature differs from `other` by no more
+    /// than `tolerance`, in either direction.
+    pub fn is_within(&self, other: Temperature, tolerance: TemperatureDelta) -> bool {
+        (*self - other).abs() <= tolerance.abs()
+    }
+}
+
+/// A difference between two temperatures, in degrees of a scale.
+///
+/// A degree Celsius is as large as a kelvin, and 9/5 of a degree
+/// Fahrenheit; differences in different scales are compared accordingly.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TemperatureDelta {
+    scale: TemperatureScale,
+    degrees: Float,
+}
+
+impl TemperatureDelta {
+    /// Creates a difference of `degrees` in the given `scale`.
+    pub fn new(scale: TemperatureScale, degrees: Float) -> TemperatureDelta {
+        TemperatureDelta { scale, degrees }
+    }
+
+    /// Returns the scale of the difference.
+    pub fn scale(&self) -> TemperatureScale {
+        self.scale
+    }
+
+    /// Returns the number of degrees, in the scale of the difference.
+    pub fn degrees(&self) -> Float {
+        self.degrees
+    }
+
+    /// Returns the number of degrees in the given scale.
+    pub fn in_scale(&self, scale: TemperatureScale) -> Float {
+        match (self.scale, scale) {
+            (TemperatureScale::Fahrenheit, TemperatureScale::Fahrenheit) => self.degrees,
+            (TemperatureScale::Fahrenheit, _) => self.degrees * 5.0 / 9.0,
+            (_, TemperatureScale::Fahrenheit) => self.degrees * 9.0 / 5.0,
+            _ => self.degrees,
+        }
+    }
+
+    /// Returns the size of the difference, regardless of its sign.
+    pub fn abs(&self) -> TemperatureDelta {
+        // `Float::abs()` needs `std`.
+        let degrees = if self.degrees < 0.0 {
+            -self.degrees
+        } else {
+            self.degrees
+        };
+        TemperatureDelta::new(self.scale, degrees)
+    }
+}
+
+impl PartialEq for TemperatureDelta {
+    fn eq(&self, other: &TemperatureDelta) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for TemperatureDelta {
+    fn partial_cmp(&self, other: &TemperatureDelta) -> Option<Ordering> {
+        self.degrees.partial_cmp(&other.in_scale(self.scale))
+    }
+}
+
+/// Parses the response to `R` into a temperature in the given scale,
+/// which is the one the device is configured to use.
+pub fn parse_temperature(
+    response: &str,
+    scale: TemperatureScale,
+) -> Result<Temperature, ParseError> {
+    let reading = parse_sensor_reading(response)?;
+    Ok(reading.with_scale(scale))
+}
+
+/// Parses the response to `R` as `parse_temperature()` does, but rejects
+/// temperatures below absolute zero, which no probe can measure.
+pub fn parse_temperature_strict(
+    response: &str,
+    scale: TemperatureScale,
+) -> Result<Temperature, ParseError> {
+    let reading = parse_sensor_reading(response)?;
+    Temperature::new_checked(scale, reading.0).map_err(|_| ParseError)
+}
+
+impl fmt::Debug for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (temp, scale) = match *self {
+            Temperature::Celsius(t) => (t, "celsius"),
+            Temperature::Kelvin(t) => (t, "kelvin"),
+            Temperature::Fahrenheit(t) => (t, "fahrenheit"),
+        };
+        write!(f, "{},{}", temp, scale)
+    }
+}
+
+/// Writes e.g. `21.5,celsius`, or with the alternate flag, `{:#}`, the value
+/// followed by the unit symbol, e.g. `21.5 °C`, with the given precision, if
+/// any.
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            let symbol = self.scale().symbol();
+            return match f.precision() {
+                Some(precision) => write!(f, "{:.*} {}", precision, self.value(), symbol),
+                None => write!(f, "{} {}", self.value(), symbol),
+            };
+        }
+        let (temp, scale) = match *self {
+            Temperature::Celsius(t) => (t, "celsius"),
+            Temperature::Kelvin(t) => (t, "kelvin"),
+            Temperature::Fahrenheit(t) => (t, "fahrenheit"),
+        };
+        write!(f, "{},{}", temp, scale)
+    }
+}
+
+/// A temperature in thousandths of a degree, without floating point.
+///
+/// Readings have three decimals, so they are stored without loss, and can
+/// be handled on targets without an FPU. Unlike `Temperature`, it is `Eq`
+/// and `Hash`, so it can be used as a map key.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TemperatureMilli {
+    pub scale: TemperatureScale,
+    pub millidegrees: i32,
+}
+
+impl TemperatureMilli {
+    /// Creates a temperature of `millidegrees` in the given `scale`.
+    pub fn new(scale: TemperatureScale, millidegrees: i32) -> TemperatureMilli {
+        TemperatureMilli {
+            scale,
+            millidegrees,
+        }
+    }
+
+    /// Rounds a temperature to the nearest thousandth of a degree, or
+    /// returns `None` if it does not fit in an `i32`, or is not finite.
+    pub fn from_temperature(temperature: Temperature) -> Option<TemperatureMilli> {
+        let milli = temperature.value() * 1000.0;
+        if !milli.is_finite()
+            || milli <= i32::MIN as Float - 0.5
+            || milli >= i32::MAX as Float + 0.5
+        {
+            return None;
+        }
+        let half = if milli < 0.0 { -0.5 } else { 0.5 };
+        Some(TemperatureMilli::new(
+            temperature.scale(),
+            (milli + half) as i32,
+        ))
+    }
+}
+
+impl From<TemperatureMilli> for Temperature {
+    fn from(temperature: TemperatureMilli) -> Temperature {
+        Temperature::new(
+            temperature.scale,
+            temperature.millidegrees as Float / 1000.0,
+        )
+    }
+}
+
+/// Parses the response to `R` into thousandths of a degree in the given
+/// scale, reading the digits directly, without floating point.
+///
+/// The reading may have a sign, and up to three decimals, e.g. `-21.5` or
+/// `+025.125`; exponent notation is not accepted.
+pub fn parse_temperature_milli(
+    response: &str,
+    scale: TemperatureScale,
+) -> Result<TemperatureMilli, ParseError> {
+    let (negative, digits) = match response.as_bytes().split_first() {
+        Some((b'-', rest)) => (true, rest),
+        Some((b'+', rest)) => (false, rest),
+        _ => (false, response.as_bytes()),
+    };
+    let (int_digits, frac_digits) = match digits.iter().position(|&c| c == b'.') {
+        Some(dot) => (&digits[..dot], &digits[dot + 1..]),
+        None => (digits, &digits[digits.len()..]),
+    };
+    if (int_digits.is_empty() && frac_digits.is_empty()) || frac_digits.len() > 3 {
+        return Err(ParseError);
+    }
+
+    let mut milli: i32 = 0;
+    for &c in int_digits.iter().chain(frac_digits) {
+        if !c.is_ascii_digit() {
+            return Err(ParseError);
+        }
+        let digit = i32::from(c - b'0');
+        milli = milli
+            .checked_mul(10)
+            .and_then(|m| m.checked_add(digit))
+            .ok_or(ParseError)?;
+    }
+    for _ in frac_digits.len()..3 {
+        milli = milli.checked_mul(10).ok_or(ParseError)?;
+    }
+
+    let millidegrees = if negative { -milli } else { milli };
+    Ok(TemperatureMilli::new(scale, millidegrees))
+}
+
+/// Firmware version of the chip, e.g. `2.10`, as `major.minor`.
+///
+/// Versions compare by major, then minor number, so `2.10` is newer than
+/// `2.01`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl Version {
+    /// Creates the version `major.minor`.
+    pub fn new(major: u16, minor: u16) -> Version {
+        Version { major, minor }
+    }
+}
+
+/// Parses the firmware field of the response to `i`, e.g. `2.10`.
+pub fn parse_version(firmware: &str) -> Result<Version, ParseError> {
+    let mut split = firmware.split('.');
+    let mut number = || match split.next() {
+        Some(digits) if !digits.is_empty() && digits.bytes().all(|c| c.is_ascii_digit()) => {
+            u16::from_str(digits).map_err(|_| ParseError)
+        }
+        _ => Err(ParseError),
+    };
+    let version = Version::new(number()?, number()?);
+    match split.next() {
+        None => Ok(version),
+        _ => Err(ParseError),
+    }
+}
+
+/// Writes the minor number with two digits at least, as the chip does,
+/// e.g. `2.01`.
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:02}", self.major, self.minor)
+    }
+}
+
+/// Supply voltage of the chip, in volts, e.g. from the response to
+/// `STATUS`.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Volts(pub Float);
+
+impl Volts {
+    /// Supply voltage below which the chip browns out, and its readings
+    /// can no longer be trusted.
+    pub const BROWN_OUT: Volts = Volts(3.0);
+
+    /// Creates a voltage from millivolts.
+    pub fn from_millivolts(millivolts: u32) -> Volts {
+        Volts(millivolts as Float / 1000.0)
+    }
+
+    /// Returns the voltage in millivolts, rounded to the nearest one, or 0
+    /// for a negative voltage.
+    pub fn as_millivolts(&self) -> u32 {
+        // `round()` needs `std`; the cast truncates, and saturates.
+        (self.0 * 1000.0 + 0.5) as u32
+    }
+
+    /// Returns `true` if the voltage is below `threshold`, such as
+    /// `Volts::BROWN_OUT`, which is the usual cause of flaky readings.
+    pub fn is_undervoltage(&self, threshold: Volts) -> bool {
+        self.0 < threshold.0
+    }
+}
+
+/// Writes the voltage with three decimals, e.g. `3.300 V`.
+impl fmt::Display for Volts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.*} V", 3, self.0)
+    }
+}
+
+/// A temperature reading
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SensorReading(pub Float);
+
+impl SensorReading {
+    /// The reading of a chip with no probe attached, or with a broken probe
+    /// wire.
+    pub const OPEN_CIRCUIT: SensorReading = SensorReading(-1023.0);
+
+    /// Attaches the scale the device is configured to use, which the
+    /// reading itself does not carry.
+    pub fn with_scale(&self, scale: TemperatureScale) -> Temperature {
+        Temperature::new(scale, self.0)
+    }
+
+    /// Returns `true` if the chip reports that the probe circuit is open,
+    /// rather than a temperature.
+    pub fn is_open_circuit(&self) -> bool {
+        self.0 == SensorReading::OPEN_CIRCUIT.0
+    }
+}
+
+/// Parses the response to `R`, e.g. `21.500`. The returned value has no
+/// known units.
+///
+/// Any finite decimal number is accepted: with or without a sign, with
+/// leading or trailing zeros, and in exponent notation, e.g. `+25.000`,
+/// `-010.5`, `2.5e1`, or `2.5E-1`.
+pub fn parse_sensor_reading(response: &str) -> Result<SensorReading, ParseError> {
+    let val = parse_reading_value(response)?;
+    Ok(SensorReading(val))
+}
+
+// Parses a reading, which is always finite: `from_str` would accept
+// `NaN` and `inf`, but the chip never sends them.
+fn parse_reading_value(reading_str: &str) -> Result<Float, ParseError> {
+    match Float::from_str(reading_str) {
+        Ok(val) if val.is_finite() => Ok(val),
+        _ => Err(ParseError),
+    }
+}
+
+impl SensorReading {
+    /// Averages readings, returning `None` if there are none.
+    ///
+    /// Readings carry no scale, so they must all have been read in the
+    /// same one.
+    pub fn mean<I>(readings: I) -> Option<SensorReading>
+    where
+        I: IntoIterator<Item = SensorReading>,
+    {
+        let (sum, count) = readings
+            .into_iter()
+            .fold((0.0, 0usize), |(sum, count), reading| {
+                (sum + reading.0, count + 1)
+            });
+        if count == 0 {
+            None
+        } else {
+            Some(SensorReading(sum / count as Float))
+        }
+    }
+}
+
+/// Adds up readings, which must all have been read in the same scale.
+impl Sum for SensorReading {
+    fn sum<I: Iterator<Item = SensorReading>>(readings: I) -> SensorReading {
+        SensorReading(readings.map(|reading| reading.0).sum())
+    }
+}
+
+impl<'a> Sum<&'a SensorReading> for SensorReading {
+    fn sum<I: Iterator<Item = &'a SensorReading>>(readings: I) -> SensorReading {
+        readings.copied().sum()
+    }
+}
+
+impl fmt::Debug for SensorReading {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.*}", 3, self.0)
+    }
+}
+
+impl fmt::Display for SensorReading {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.*}", 3, self.0)
+    }
+}
+
+/// Compares in the scale of the left-hand side, with the tolerance in
+/// degrees of that scale.
+#[cfg(feature = "approx")]
+impl AbsDiffEq for Temperature {
+    type Epsilon = Float;
+
+    fn default_epsilon() -> Float {
+        Float::default_epsilon()
+    }
+
+    fn abs_diff_eq(&self, other: &Temperature, epsilon: Float) -> bool {
+        let other = other.to_scale(self.scale()).value();
+        self.value().abs_diff_eq(&other, epsilon)
+    }
+}
+
+#[cfg(feature = "approx")]
+impl RelativeEq for Temperature {
+    fn default_max_relative() -> Float {
+        Float::default_max_relative()
+    }
+
+    fn relative_eq(&self, other: &Temperature, epsilon: Float, max_relative: Float) -> bool {
+        let other = other.to_scale(self.scale()).value();
+        self.value().relative_eq(&other, epsilon, max_relative)
+    }
+}
+
+/// Compares in the scale of the left-hand side, with the tolerance in
+/// degrees of that scale.
+#[cfg(feature = "approx")]
+impl AbsDiffEq for TemperatureDelta {
+    type Epsilon = Float;
+
+    fn default_epsilon() -> Float {
+        Float::default_epsilon()
+    }
+
+    fn abs_diff_eq(&self, other: &TemperatureDelta, epsilon: Float) -> bool {
+        let other = other.in_scale(self.scale);
+        self.degrees.abs_diff_eq(&other, epsilon)
+    }
+}
+
+#[cfg(feature = "approx")]
+impl RelativeEq for TemperatureDelta {
+    fn default_max_relative() -> Float {
+        Float::default_max_relative()
+    }
+
+    fn relative_eq(&self, other: &TemperatureDelta, epsilon: Float, max_relative: Float) -> bool {
+        let other = other.in_scale(self.scale);
+        self.degrees.relative_eq(&other, epsilon, max_relative)
+    }
+}
+
+#[cfg(feature = "approx")]
+impl AbsDiffEq for SensorReading {
+    type Epsilon = Float;
+
+    fn default_epsilon() -> Float {
+        Float::default_epsilon()
+    }
+
+    fn abs_diff_eq(&self, other: &SensorReading, epsilon: Float) -> bool {
+        self.0.abs_diff_eq(&other.0, epsilon)
+    }
+}
+
+#[cfg(feature = "approx")]
+impl RelativeEq for SensorReading {
+    fn default_max_relative() -> Float {
+        Float::default_max_relative()
+    }
+
+    fn relative_eq(&self, other: &SensorReading, epsilon: Float, max_relative: Float) -> bool {
+        self.0.relative_eq(&other.0, epsilon, max_relative)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fixed-size `fmt::Write` buffer, as firmware would use.
+    struct Buffer {
+        data: [u8; 16],
+        len: usize,
+    }
+
+    impl Buffer {
+        fn new() -> Buffer {
+            Buffer {
+                data: [0; 16],
+                len: 0,
+            }
+        }
+
+        fn as_str(&self) -> &str {
+            core::str::from_utf8(&self.data[..self.len]).unwrap()
+        }
+    }
+
+    impl fmt::Write for Buffer {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.len + s.len();
+            if end > self.data.len() {
+                return Err(fmt::Error);
+            }
+            self.data[self.len..end].copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_commands_without_allocating() {
+        let mut buf = Buffer::new();
+        write_calibration_temperature(&mut buf, 25.0).unwrap();
+        assert_eq!(buf.as_str(), "CAL,25.00");
+
+        let mut buf = Buffer::new();
+        write_datalogger_period(&mut buf, 60).unwrap();
+        assert_eq!(buf.as_str(), "D,60");
+
+        let mut buf = Buffer::new();
+        write_name(&mut buf, "tank").unwrap();
+        assert_eq!(buf.as_str(), "NAME,tank");
+    }
+
+    // Conversions are exact to within the precision of `Float`.
+    #[cfg(not(feature = "f32"))]
+    const TOLERANCE: Float = 1e-9;
+    #[cfg(feature = "f32")]
+    const TOLERANCE: Float = 1e-3;
+
+    fn assert_close(actual: Temperature, expected: Temperature) {
+        assert!(actual.scale() == expected.scale());
+        assert!((actual.value() - expected.value()).abs() < TOLERANCE);
+    }
+
+    #[test]
+    fn converts_temperatures_at_fixed_points() {
+        // Freezing and boiling points of water, and the point where the
+        // Celsius and Fahrenheit scales meet.
+        let points = [
+            (0.0, 273.15, 32.0),
+            (100.0, 373.15, 212.0),
+            (-40.0, 233.15, -40.0),
+            (-273.15, 0.0, -459.67),
+        ];
+        for &(c, k, f) in points.iter() {
+            let celsius = Temperature::Celsius(c);
+            let kelvin = Temperature::Kelvin(k);
+            let fahrenheit = Temperature::Fahrenheit(f);
+            for t in [celsius, kelvin, fahrenheit].iter() {
+                assert_close(t.to_celsius(), celsius);
+                assert_close(t.to_kelvin(), kelvin);
+                assert_close(t.to_fahrenheit(), fahrenheit);
+                assert_close(t.to_scale(t.scale()), *t);
+            }
+        }
+    }
+
+    #[test]
+    fn converts_temperatures_to_and_from_rankine() {
+        let points = [
+            (Temperature::Kelvin(0.0), 0.0),
+            (Temperature::Celsius(0.0), 491.67),
+            (Temperature::Fahrenheit(212.0), 671.67),
+            (Temperature::Kelvin(373.15), 671.67),
+        ];
+        for &(temperature, rankine) in points.iter() {
+            assert!((temperature.to_rankine() - rankine).abs() < TOLERANCE);
+            assert_close(
+                Temperature::from_rankine(rankine).to_scale(temperature.scale()),
+                temperature,
+            );
+        }
+    }
+
+    #[test]
+    fn compares_temperatures_across_scales() {
+        let limit = Temperature::Celsius(30.0);
+        assert!(Temperature::Fahrenheit(90.0) > limit);
+        assert!(Temperature::Kelvin(300.0) < limit);
+        assert!(Temperature::Celsius(29.5) < limit);
+        assert_eq!(Temperature::Celsius(0.0), Temperature::Kelvin(273.15));
+        assert!(Temperature::Celsius(Float::NAN) != Temperature::Celsius(Float::NAN));
+    }
+
+    #[test]
+    fn equality_across_scales_goes_through_kelvin() {
+        assert_eq!(Temperature::Fahrenheit(98.6), Temperature::Celsius(37.0));
+        assert_eq!(Temperature::Celsius(37.0), Temperature::Fahrenheit(98.6));
+
+        // Both are 0 °C, give or take less than a rounding step of 273.15.
+        let zero = Temperature::Celsius(0.0);
+        let almost_zero = Temperature::Celsius(1e-14);
+        let kelvin = Temperature::Kelvin(273.15);
+        assert!(zero == kelvin && kelvin == almost_zero);
+        assert!(zero != almost_zero);
+
+        // −37.8 °C is −36.04 °F, but the conversions round apart in `f64`.
+        let fahrenheit = Temperature::Fahrenheit(-36.04);
+        let celsius = Temperature::Celsius(-37.8);
+        #[cfg(not(feature = "f32"))]
+        assert!(fahrenheit != celsius);
+        assert!(fahrenheit.is_within(
+            celsius,
+            TemperatureDelta::new(TemperatureScale::Kelvin, 1e-3)
+        ));
+    }
+
+    #[test]
+    fn ranges_contain_temperatures_in_any_scale() {
+        let range = TemperatureRange::new(Temperature::Celsius(20.0), Temperature::Kelvin(303.15));
+        assert!(range.contains(&Temperature::Celsius(20.0)));
+        assert!(range.contains(&Temperature::Fahrenheit(77.0)));
+        assert!(range.contains(&Temperature::Celsius(30.0)));
+        assert!(!range.contains(&Temperature::Fahrenheit(67.0)));
+        assert!(!range.contains(&Temperature::Kelvin(303.2)));
+        assert!(!range.contains(&Temperature::Celsius(Float::NAN)));
+
+        assert!(!TemperatureRange::PROBE.contains(&Temperature::Kelvin(0.0)));
+        assert!(TemperatureRange::PROBE.contains(&Temperature::Fahrenheit(212.0)));
+    }
+
+    #[test]
+    fn subtracts_temperatures_in_scale_of_left_side() {
+        let delta = Temperature::Celsius(30.0) - Temperature::Celsius(20.0);
+        assert!(delta.scale() == TemperatureScale::Celsius);
+        assert_eq!(delta.degrees(), 10.0);
+
+        let delta = Temperature::Fahrenheit(212.0) - Temperature::Celsius(0.0);
+        assert_eq!(delta.degrees(), 180.0);
+        assert_eq!(delta.in_scale(TemperatureScale::Kelvin), 100.0);
+
+        let delta = Temperature::Celsius(0.0) - Temperature::Fahrenheit(212.0);
+        assert_eq!(delta.degrees(), -100.0);
+    }
+
+    #[test]
+    fn compares_temperature_deltas_across_scales() {
+        let one_kelvin = TemperatureDelta::new(TemperatureScale::Kelvin, 1.0);
+        assert_eq!(
+            TemperatureDelta::new(TemperatureScale::Fahrenheit, 1.8),
+            one_kelvin
+        );
+        assert!(TemperatureDelta::new(TemperatureScale::Fahrenheit, 1.0) < one_kelvin);
+        assert!(TemperatureDelta::new(TemperatureScale::Celsius, -2.0).abs() > one_kelvin);
+
+        let reading = Temperature::Celsius(25.4);
+        assert!(reading.is_within(Temperature::Fahrenheit(77.0), one_kelvin));
+        assert!(!reading.is_within(Temperature::Celsius(27.0), one_kelvin));
+        assert_close(reading + one_kelvin, Temperature::Celsius(26.4));
+        assert_close(
+            Temperature::Fahrenheit(32.0) - one_kelvin,
+            Temperature::Fahrenheit(30.2),
+        );
+    }
+
+    #[test]
+    fn displays_temperatures_with_unit_symbol() {
+        assert_eq!(format!("{:#}", Temperature::Celsius(21.5)), "21.5 °C");
+        assert_eq!(format!("{:#}", Temperature::Kelvin(294.65)), "294.65 K");
+        assert_eq!(format!("{:#}", Temperature::Fahrenheit(70.7)), "70.7 °F");
+        assert_eq!(format!("{:#.1}", Temperature::Celsius(21.53)), "21.5 °C");
+        assert_eq!(format!("{}", Temperature::Celsius(21.5)), "21.5,celsius");
+    }
+
+    #[test]
+    fn checked_temperatures_are_not_below_absolute_zero() {
+        let points = [
+            (TemperatureScale::Celsius, -273.15),
+            (TemperatureScale::Kelvin, 0.0),
+            (TemperatureScale::Fahrenheit, -459.67),
+        ];
+        for &(scale, zero) in points.iter() {
+            assert!(Temperature::new_checked(scale, zero).is_ok());
+            assert_eq!(
+                Temperature::new_checked(scale, zero - 0.01),
+                Err(InvalidTemperature::BelowAbsoluteZero(Temperature::new(
+                    scale,
+                    zero - 0.01
+                )))
+            );
+            assert!(matches!(
+                Temperature::new_checked(scale, Float::NAN),
+                Err(InvalidTemperature::NotFinite(_))
+            ));
+            assert_eq!(
+                Temperature::new_checked(scale, Float::NEG_INFINITY),
+                Err(InvalidTemperature::NotFinite(Temperature::new(
+                    scale,
+                    Float::NEG_INFINITY
+                )))
+            );
+        }
+        assert!(parse_temperature_strict("-12.5", TemperatureScale::Kelvin).is_err());
+        assert!(parse_temperature_strict("-12.5", TemperatureScale::Celsius).is_ok());
+    }
+
+    #[test]
+    fn parses_scales_written_by_people() {
+        for name in ["c", "C", "celsius", "Celsius", " °C ", "°c"].iter() {
+            assert!(TemperatureScale::try_from(*name) == Ok(TemperatureScale::Celsius));
+        }
+        for name in ["k", "K", "kelvin", "KELVIN"].iter() {
+            assert!(TemperatureScale::try_from(*name) == Ok(TemperatureScale::Kelvin));
+        }
+        for name in ["f", "F", "fahrenheit", "°F"].iter() {
+            assert!(TemperatureScale::try_from(*name) == Ok(TemperatureScale::Fahrenheit));
+        }
+        for name in ["", "?S,C", "rankine", "cel"].iter() {
+            assert!(TemperatureScale::try_from(*name).is_err());
+        }
+    }
+
+    #[test]
+    fn parses_readings_into_millidegrees() {
+        let forms = [
+            ("21.5", 21_500),
+            ("-21.5", -21_500),
+            ("+025.125", 25_125),
+            ("0.001", 1),
+            ("100", 100_000),
+            (".5", 500),
+            ("7.", 7_000),
+        ];
+        for &(response, millidegrees) in forms.iter() {
+            let milli = parse_temperature_milli(response, TemperatureScale::Celsius).unwrap();
+            assert_eq!(
+                milli,
+                TemperatureMilli::new(TemperatureScale::Celsius, millidegrees)
+            );
+        }
+        for response in ["", "-", ".", "1.2345", "2.5e1", "1,5", "99999999"].iter() {
+            assert_eq!(
+                parse_temperature_milli(response, TemperatureScale::Celsius),
+                Err(ParseError)
+            );
+        }
+    }
+
+    #[test]
+    fn converts_millidegrees_to_and_from_temperatures() {
+        let milli = TemperatureMilli::new(TemperatureScale::Kelvin, 294_650);
+        assert_eq!(Temperature::from(milli), Temperature::Kelvin(294.65));
+        assert_eq!(
+            TemperatureMilli::from_temperature(Temperature::Kelvin(294.65)),
+            Some(milli)
+        );
+        assert_eq!(
+            TemperatureMilli::from_temperature(Temperature::Celsius(-0.0126)),
+            Some(TemperatureMilli::new(TemperatureScale::Celsius, -13))
+        );
+        assert_eq!(
+            TemperatureMilli::from_temperature(Temperature::Celsius(1e7)),
+            None
+        );
+        assert_eq!(
+            TemperatureMilli::from_temperature(Temperature::Celsius(Float::NAN)),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_responses() {
+        assert_eq!(
+            parse_calibration_status("?CAL,1"),
+            Ok(CalibrationStatus::Calibrated)
+        );
+        assert_eq!(
+            parse_datalogger_interval("?D,60"),
+            Ok(DataLoggerStorageIntervalSeconds(60))
+        );
+        assert_eq!(
+            parse_memory_reading("3,21.5"),
+            Ok(MemoryReading {
+                location: 3,
+                reading: 21.5
+            })
+        );
+        assert_eq!(
+            parse_temperature("21.5", TemperatureScale::Kelvin),
+            Ok(Temperature::Kelvin(21.5))
+        );
+    }
+
+    #[test]
+    fn parsing_invalid_responses_yields_error() {
+        assert_eq!(parse_calibration_status("?CAL"), Err(ParseError));
+        assert_eq!(parse_datalogger_interval("?D,5"), Err(ParseError));
+        assert_eq!(parse_memory_reading("3,21.5,"), Err(ParseError));
+        assert_eq!(parse_temperature_scale("?S,X"), Err(ParseError));
+        assert_eq!(parse_sensor_reading(""), Err(ParseError));
+    }
+
+    #[test]
+    fn parsing_truncated_or_multibyte_responses_yields_error() {
+        let responses = [
+            "", "?", "?C", "?CA", "?CAL", "?CAL,", "?CAL,€", "?CA€", "€CAL,1", "?D", "?D,", "?D€",
+            "?D,€", "?S", "?S,", "?S,€", ",", "3,", ",21.5", "€,1", "3,€", "-", ".", "-.", "€",
+        ];
+        for response in responses.iter() {
+            assert_eq!(parse_calibration_status(response), Err(ParseError));
+            assert_eq!(parse_datalogger_interval(response), Err(ParseError));
+            assert_eq!(parse_memory_reading(response), Err(ParseError));
+            assert_eq!(parse_temperature_scale(response), Err(ParseError));
+            assert_eq!(parse_sensor_reading(response), Err(ParseError));
+            assert_eq!(
+                parse_temperature_milli(response, TemperatureScale::Celsius),
+                Err(ParseError)
+            );
+        }
+    }
+
+    #[test]
+    fn parses_readings_in_any_decimal_form() {
+        let forms = [
+            ("25", 25.0),
+            ("+25.000", 25.0),
+            ("025.000", 25.0),
+            ("-010.5", -10.5),
+            ("2.5e1", 25.0),
+            ("2.5E+1", 25.0),
+            ("+2.5e-1", 0.25),
+            ("-0.000", 0.0),
+            (".5", 0.5),
+        ];
+        for &(response, value) in forms.iter() {
+            assert_eq!(parse_sensor_reading(response), Ok(SensorReading(value)));
+        }
+        assert_eq!(
+            parse_memory_reading("3,+2.15e1"),
+            Ok(MemoryReading {
+                location: 3,
+                reading: 21.5,
+            })
+        );
+    }
+
+    #[test]
+    fn parsing_malformed_readings_yields_error() {
+        for response in [
+            "", "+", "e1", "2.5e", "25.0.0", "++25", " 25", "25 ", "0x19",
+        ]
+        .iter()
+        {
+            assert_eq!(parse_sensor_reading(response), Err(ParseError));
+        }
+    }
+
+    #[test]
+    fn readings_take_the_scale_they_are_given() {
+        let temperature = SensorReading(21.5).with_scale(TemperatureScale::Fahrenheit);
+        assert_eq!(temperature, Temperature::Fahrenheit(21.5));
+        assert!(temperature.scale() == TemperatureScale::Fahrenheit);
+        assert_eq!(temperature.value(), 21.5);
+    }
+
+    #[test]
+    fn detects_open_probe_circuit() {
+        let reading = parse_sensor_reading("-1023.000").unwrap();
+        assert!(reading.is_open_circuit());
+        assert!(!SensorReading(-1022.999).is_open_circuit());
+        assert!(!SensorReading(21.5).is_open_circuit());
+    }
+
+    #[test]
+    fn memory_readings_take_the_scale_they_are_given() {
+        let reading = MemoryReading {
+            location: 3,
+            reading: 21.5,
+        };
+        assert_eq!(
+            reading.with_scale(TemperatureScale::Kelvin),
+            (3, Temperature::Kelvin(21.5))
+        );
+    }
+
+    #[test]
+    fn datalogger_intervals_are_checked_and_convert_to_durations() {
+        for &seconds in [0, 10, 60, 320_000].iter() {
+            let interval = DataLoggerStorageIntervalSeconds::new(seconds).unwrap();
+            assert_eq!(
+                interval.as_duration(),
+                Duration::from_secs(u64::from(seconds))
+            );
+        }
+        for &seconds in [1, 9, 320_001, u32::MAX].iter() {
+            assert_eq!(
+                DataLoggerStorageIntervalSeconds::new(seconds),
+                Err(InvalidInterval(seconds))
+            );
+        }
+
+        let interval = DataLoggerStorageIntervalSeconds::try_from;
+        assert_eq!(
+            interval(Duration::from_millis(59_500)),
+            Ok(DataLoggerStorageIntervalSeconds(60))
+        );
+        assert_eq!(
+            interval(Duration::from_millis(60_499)),
+            Ok(DataLoggerStorageIntervalSeconds(60))
+        );
+        assert_eq!(
+            interval(Duration::from_millis(9_499)),
+            Err(InvalidInterval(9))
+        );
+        assert_eq!(
+            interval(Duration::from_secs(u64::MAX)),
+            Err(InvalidInterval(u32::MAX))
+        );
+        assert_eq!(
+            Duration::from(DataLoggerStorageIntervalSeconds(60)),
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn parses_and_compares_firmware_versions() {
+        assert_eq!(parse_version("2.01"), Ok(Version::new(2, 1)));
+        assert_eq!(parse_version("2.10"), Ok(Version::new(2, 10)));
+        assert!(Version::new(2, 10) > Version::new(2, 1));
+        assert!(Version::new(3, 0) > Version::new(2, 10));
+
+        assert_eq!(format!("{}", Version::new(2, 1)), "2.01");
+        assert_eq!(format!("{}", Version::new(2, 10)), "2.10");
+
+        for firmware in ["", "2", "2.", ".1", "2.1.0", "+2.1", "2.x", "2.€"].iter() {
+            assert_eq!(parse_version(firmware), Err(ParseError));
+        }
+    }
+
+    #[test]
+    fn detects_supply_undervoltage() {
+        assert!(Volts(2.95).is_undervoltage(Volts::BROWN_OUT));
+        assert!(!Volts(3.0).is_undervoltage(Volts::BROWN_OUT));
+        assert!(!Volts(5.038).is_undervoltage(Volts::BROWN_OUT));
+        assert!(Volts(3.2).is_undervoltage(Volts(3.3)));
+        assert_eq!(Volts::from_millivolts(3300), Volts(3.3));
+        assert_eq!(Volts(5.038).as_millivolts(), 5038);
+        assert_eq!(format!("{}", Volts(3.3)), "3.300 V");
+    }
+
+    #[test]
+    fn sums_and_averages_temperatures_in_one_scale() {
+        let temperatures = [
+            Temperature::Celsius(20.0),
+            Temperature::Celsius(21.0),
+            Temperature::Celsius(25.0),
+        ];
+        assert_eq!(
+            Temperature::sum(temperatures.iter().copied()),
+            Ok(Some(Temperature::Celsius(66.0)))
+        );
+        assert_eq!(
+            Temperature::mean(temperatures.iter().copied()),
+            Ok(Some(Temperature::Celsius(22.0)))
+        );
+        assert_eq!(Temperature::mean(None), Ok(None));
+
+        let mixed = [Temperature::Celsius(20.0), Temperature::Kelvin(294.15)];
+        assert_eq!(Temperature::mean(mixed.iter().copied()), Err(MixedScales));
+        let mean = Temperature::mean(mixed.iter().map(|t| t.to_celsius()))
+            .unwrap()
+            .unwrap();
+        assert_close(mean, Temperature::Celsius(20.5));
+    }
+
+    #[test]
+    fn sums_and_averages_readings() {
+        let readings = [
+            SensorReading(20.0),
+            SensorReading(21.0),
+            SensorReading(25.0),
+        ];
+        assert_eq!(readings.iter().sum::<SensorReading>(), SensorReading(66.0));
+        assert_eq!(
+            readings.iter().copied().sum::<SensorReading>(),
+            SensorReading(66.0)
+        );
+        assert_eq!(
+            SensorReading::mean(readings.iter().copied()),
+            Some(SensorReading(22.0))
+        );
+        assert_eq!(SensorReading::mean(None), None);
+    }
+
+    #[cfg(feature = "approx")]
+    #[test]
+    fn compares_with_a_tolerance() {
+        use approx::{assert_abs_diff_eq, assert_relative_eq, assert_relative_ne};
+
+        assert_abs_diff_eq!(
+            Temperature::Celsius(21.5),
+            Temperature::Fahrenheit(70.72),
+            epsilon = 0.02
+        );
+        assert_relative_eq!(
+            Temperature::Kelvin(273.15),
+            Temperature::Celsius(0.0),
+            max_relative = 1e-12
+        );
+        assert_relative_ne!(Temperature::Celsius(21.5), Temperature::Celsius(21.6));
+        assert_abs_diff_eq!(
+            TemperatureDelta::new(TemperatureScale::Celsius, 1.0),
+            TemperatureDelta::new(TemperatureScale::Fahrenheit, 1.8),
+            epsilon = 1e-9
+        );
+        assert_abs_diff_eq!(SensorReading(25.104), SensorReading(25.1), epsilon = 0.01);
+    }
+
+    #[test]
+    fn parsing_non_finite_readings_yields_error() {
+        for response in ["NaN", "nan", "inf", "-inf", "infinity", "1e999"].iter() {
+            assert_eq!(parse_sensor_reading(response), Err(ParseError));
+            assert_eq!(
+                parse_temperature(response, TemperatureScale::Celsius),
+                Err(ParseError)
+            );
+        }
+        assert_eq!(parse_memory_reading("3,NaN"), Err(ParseError));
+    }
+}
@@ -0,0 +1,285 @@
+//! Commands over blocking `embedded-hal` I2C buses.
+//!
+//! The bus and the delay are the `I2c` and `DelayNs` traits of
+//! `embedded-hal`, so the crate can be used from `linux-embedded-hal`,
+//! microcontroller HALs, and other platforms that do not use `i2cdev`.
+//!
+//! Chips that share a bus, such as RTD, pH, and EC chips on one EZO stack,
+//! each get a clone of a `SharedI2c`, which any `embedded-hal` driver can
+//! use. On Linux, each chip can instead open its own `LinuxI2CDevice` on the
+//! same `/dev/i2c-*` node, since the kernel serializes their transfers.
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use super::command::{Reading, ScaleState};
+use super::protocol::{
+    decode_reply, encode_command, name_rejected, CommandReply, I2cFault, Reply, MAX_COMMAND,
+    MAX_REPLY,
+};
+use super::response::Temperature;
+use super::{ErrorKind, EzoError};
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{Error, ErrorType, I2c, Operation};
+
+use failure::ResultExt;
+
+/// Delay that puts the current thread to sleep, for hosts with `std`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StdDelay;
+
+impl DelayNs for StdDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        thread::sleep(Duration::from_nanos(u64::from(ns)));
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        thread::sleep(Duration::from_millis(u64::from(ms)));
+    }
+}
+
+/// An EZO RTD chip on an `embedded-hal` I2C bus.
+#[derive(Debug)]
+pub struct HalDevice<I, D> {
+    i2c: I,
+    delay: D,
+    address: u8,
+}
+
+impl<I: I2c, D: DelayNs> HalDevice<I, D> {
+    /// Uses the chip at the given 7-bit address, waiting on `delay` between
+    /// writing a command and reading its reply.
+    pub fn new(i2c: I, delay: D, address: u8) -> HalDevice<I, D> {
+        HalDevice {
+            i2c,
+            delay,
+            address,
+        }
+    }
+
+    /// Returns the I2C address of the chip.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Gives back the bus and the delay.
+    pub fn release(self) -> (I, D) {
+        (self.i2c, self.delay)
+    }
+
+    /// Writes the command, waits for its delay, then reads and parses the
+    /// reply.
+    pub fn run<C: CommandReply>(&mut self, cmd: &C) -> Result<C::Response, EzoError> {
+        let mut cmd_buffer = [0u8; MAX_COMMAND];
+        let len = encode_command(cmd, &mut cmd_buffer)?;
+
+        self.i2c
+            .write(self.address, &cmd_buffer[..len])
+            .map_err(|err| I2cFault::from(err.kind()))
+            .context(ErrorKind::UnwritableCommand)?;
+
+        let reply = cmd.reply();
+        if reply == Reply::None {
+            return cmd.parse_reply("");
+        }
+
+        let delay = cmd.get_delay();
+        self.delay.delay_ms(delay as u32);
+
+        let mut data_buffer = [0u8; MAX_REPLY];
+
+        self.i2c
+            .read(self.address, &mut data_buffer)
+            .map_err(|err| I2cFault::from(err.kind()))
+            .context(ErrorKind::I2CRead)?;
+
+        let data = decode_reply(&data_buffer, reply).map_err(|err| name_rejected(err, cmd))?;
+
+        cmd.parse_reply(&data)
+    }
+
+    /// Obtains a temperature in the current scale of the chip, as
+    /// `ReadingWithScale` does.
+    pub fn read_temperature(&mut self) -> Result<Temperature, EzoError> {
+        let scale = self.run(&ScaleState)?;
+        let reading = self.run(&Reading)?;
+        Ok(reading.with_scale(scale))
+    }
+}
+
+/// An I2C bus shared by several chips, each on its own thread.
+///
+/// Every transaction locks the bus, so a command holds it only while its
+/// command is written and while its reply is read, not during the delay in
+/// between, which lets the commands of other chips go through meanwhile.
+/// Clones share the same bus.
+#[derive(Debug)]
+pub struct SharedI2c<I> {
+    bus: Arc<Mutex<I>>,
+}
+
+impl<I> SharedI2c<I> {
+    /// Shares the given bus.
+    pub fn new(bus: I) -> SharedI2c<I> {
+        SharedI2c {
+            bus: Arc::new(Mutex::new(bus)),
+        }
+    }
+}
+
+impl<I> Clone for SharedI2c<I> {
+    fn clone(&self) -> SharedI2c<I> {
+        SharedI2c {
+            bus: Arc::clone(&self.bus),
+        }
+    }
+}
+
+impl<I: I2c> ErrorType for SharedI2c<I> {
+    type Error = I::Error;
+}
+
+impl<I: I2c> I2c for SharedI2c<I> {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), I::Error> {
+        // A panic on another thread leaves no transaction half-done, since
+        // each one runs while the lock is held, so the bus is still usable.
+        let mut bus = self.bus.lock().unwrap_or_else(PoisonError::into_inner);
+        bus.transaction(address, operations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{Command, LedOn, Sleep};
+    use crate::protocol::{i2c_fault, rejected_command};
+    use crate::response::{ResponseStatus, TemperatureScale};
+    use embedded_hal::i2c::{ErrorKind as HalErrorKind, NoAcknowledgeSource};
+    use std::convert::Infallible;
+
+    /// Records writes, and replies with the queued buffers in order.
+    struct MockBus {
+        replies: Vec<Vec<u8>>,
+        written: Vec<(u8, Vec<u8>)>,
+    }
+
+    impl ErrorType for MockBus {
+        type Error = Infallible;
+    }
+
+    impl I2c for MockBus {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Infallible> {
+            for op in operations {
+                match op {
+                    Operation::Write(bytes) => self.written.push((address, bytes.to_vec())),
+                    Operation::Read(buf) => {
+                        let reply = self.replies.remove(0);
+                        buf[..reply.len()].copy_from_slice(&reply);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// A bus on which no chip answers.
+    struct EmptyBus;
+
+    impl ErrorType for EmptyBus {
+        type Error = HalErrorKind;
+    }
+
+    impl I2c for EmptyBus {
+        fn transaction(
+            &mut self,
+            _address: u8,
+            _operations: &mut [Operation<'_>],
+        ) -> Result<(), HalErrorKind> {
+            Err(HalErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+        }
+    }
+
+    #[derive(Default)]
+    struct MockDelay(u64);
+
+    impl DelayNs for MockDelay {
+        fn delay_ns(&mut self, ns: u32) {
+            self.0 += u64::from(ns);
+        }
+    }
+
+    fn mock_device(replies: &[&[u8]]) -> HalDevice<MockBus, MockDelay> {
+        let bus = MockBus {
+            replies: replies.iter().map(|r| r.to_vec()).collect(),
+            written: Vec::new(),
+        };
+        HalDevice::new(bus, MockDelay::default(), 0x66)
+    }
+
+    #[test]
+    fn runs_commands() {
+        let mut dev = mock_device(&[&[1, 0]]);
+        assert_eq!(dev.run(&LedOn).unwrap(), ResponseStatus::Ack);
+        dev.run(&Sleep).unwrap();
+        let (bus, delay) = dev.release();
+        assert_eq!(
+            bus.written,
+            vec![(0x66, b"L,1\0".to_vec()), (0x66, b"SLEEP\0".to_vec())]
+        );
+        assert_eq!(delay.0, LedOn.get_delay() * 1_000_000);
+    }
+
+    #[test]
+    fn reads_temperature_in_current_scale() {
+        let mut dev = mock_device(&[b"\x01?S,F\0", b"\x0198.6\0"]);
+        let temperature = dev.read_temperature().unwrap();
+        assert_eq!(
+            temperature,
+            Temperature::new(TemperatureScale::Fahrenheit, 98.6)
+        );
+    }
+
+    #[test]
+    fn device_error_yields_error() {
+        let mut dev = mock_device(&[&[2, 0]]);
+        let err = dev.run(&LedOn).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::DeviceErrorResponse);
+        assert_eq!(rejected_command(&err), Some("L,1"));
+    }
+
+    #[test]
+    fn bus_faults_are_kept_as_causes() {
+        let mut dev = HalDevice::new(EmptyBus, MockDelay::default(), 0x66);
+        let err = dev.run(&LedOn).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::UnwritableCommand);
+        assert_eq!(i2c_fault(&err), Some(I2cFault::AddressNack));
+    }
+
+    #[test]
+    fn shared_bus_runs_commands_of_each_chip() {
+        let bus = SharedI2c::new(mock_device(&[&[1, 0], &[1, 0]]).release().0);
+        let mut first = HalDevice::new(bus.clone(), MockDelay::default(), 0x66);
+        let mut second = HalDevice::new(bus.clone(), MockDelay::default(), 0x67);
+
+        let handle = thread::spawn(move || first.run(&LedOn).unwrap());
+        assert_eq!(second.run(&LedOn).unwrap(), ResponseStatus::Ack);
+        assert_eq!(handle.join().unwrap(), ResponseStatus::Ack);
+
+        let bus = bus.bus.lock().unwrap();
+        let mut written = bus.written.clone();
+        written.sort();
+        assert_eq!(
+            written,
+            vec![(0x66, b"L,1\0".to_vec()), (0x67, b"L,1\0".to_vec())]
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! Async commands over `embedded-hal-async`.
+//!
+//! The bus and the delay are the `I2c` and `DelayNs` traits of
+//! `embedded-hal-async`, so the same command definitions run on any HAL that
+//! implements them. Commands are written and replies read through
+//! fixed-size buffers, the futures are not required to be `Send`, and the
+//! delay comes from the caller's timer, so no particular executor is needed.
+//!
+//! This is not enough for `no_std` firmware, e.g. on Embassy or RTIC 2: the
+//! crate needs `std`, and `i2cdev` through `ezo_common`, and errors are
+//! `EzoError`, which allocates to keep its causes, e.g. the command string
+//! of a rejected command. Firmware can build on `grammar` instead.
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
+
+use super::protocol::{
+    decode_reply, encode_command, name_rejected, CommandReply, I2cFault, Reply, MAX_COMMAND,
+    MAX_REPLY,
+};
+use super::{ErrorKind, EzoError};
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::{Error, I2c};
+
+#[cfg(feature = "tokio")]
+use embedded_hal_async::i2c::{ErrorType, Operation};
+use failure::ResultExt;
+
+/// An EZO RTD chip on an `embedded-hal-async` I2C bus.
+#[derive(Debug)]
+pub struct AsyncHalDevice<I, D> {
+    i2c: I,
+    delay: D,
+    address: u8,
+}
+
+impl<I: I2c, D: DelayNs> AsyncHalDevice<I, D> {
+    /// Uses the chip at the given 7-bit address, waiting on `delay` between
+    /// writing a command and reading its reply.
+    pub fn new(i2c: I, delay: D, address: u8) -> AsyncHalDevice<I, D> {
+        AsyncHalDevice {
+            i2c,
+            delay,
+            address,
+        }
+    }
+
+    /// Returns the I2C address of the chip.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Gives back the bus and the delay.
+    pub fn release(self) -> (I, D) {
+        (self.i2c, self.delay)
+    }
+
+    /// Writes the command, awaits its delay, then reads and parses the reply.
+    pub async fn run<C: CommandReply>(&mut self, cmd: &C) -> Result<C::Response, EzoError> {
+        let mut cmd_buffer = [0u8; MAX_COMMAND];
+        let len = encode_command(cmd, &mut cmd_buffer)?;
+
+        self.i2c
+            .write(self.address, &cmd_buffer[..len])
+            .await
+            .map_err(|err| I2cFault::from(err.kind()))
+            .context(ErrorKind::UnwritableCommand)?;
+
+        let reply = cmd.reply();
+        if reply == Reply::None {
+            return cmd.parse_reply("");
+        }
+
+        let delay = cmd.get_delay();
+        self.delay.delay_ms(delay as u32).await;
+
+        let mut data_buffer = [0u8; MAX_REPLY];
+
+        self.i2c
+            .read(self.address, &mut data_buffer)
+            .await
+            .map_err(|err| I2cFault::from(err.kind()))
+            .context(ErrorKind::I2CRead)?;
+
+        let data = decode_reply(&data_buffer, reply).map_err(|err| name_rejected(err, cmd))?;
+
+        cmd.parse_reply(&data)
+    }
+}
+
+/// An I2C bus shared by several chips, each on its own task.
+///
+/// Every transaction locks the bus, so a command holds it only while its
+/// command is written and while its reply is read, not during the delay in
+/// between, which lets the commands of other chips go through meanwhile.
+/// Clones share the same bus.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct SharedI2c<I> {
+    bus: Arc<tokio::sync::Mutex<I>>,
+}
+
+#[cfg(feature = "tokio")]
+impl<I> SharedI2c<I> {
+    /// Shares the given bus.
+    pub fn new(bus: I) -> SharedI2c<I> {
+        SharedI2c {
+            bus: Arc::new(tokio::sync::Mutex::new(bus)),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<I> Clone for SharedI2c<I> {
+    fn clone(&self) -> SharedI2c<I> {
+        SharedI2c {
+            bus: Arc::clone(&self.bus),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<I: I2c> ErrorType for SharedI2c<I> {
+    type Error = I::Error;
+}
+
+#[cfg(feature = "tokio")]
+impl<I: I2c> I2c for SharedI2c<I> {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), I::Error> {
+        let mut bus = self.bus.lock().await;
+        bus.transaction(address, operations).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{Command, LedOn, ScaleState, Sleep};
+    use crate::response::{ResponseStatus, TemperatureScale};
+    use std::convert::Infallible;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    /// Records writes and delays, and replies with a fixed buffer.
+    struct MockBus {
+        reply: Vec<u8>,
+        written: Vec<(u8, Vec<u8>)>,
+        reads: usize,
+    }
+
+    impl embedded_hal_async::i2c::ErrorType for MockBus {
+        type Error = Infallible;
+    }
+
+    impl I2c for MockBus {
+        async fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [embedded_hal_async::i2c::Operation<'_>],
+        ) -> Result<(), Infallible> {
+            for op in operations {
+                match op {
+                    embedded_hal_async::i2c::Operation::Write(bytes) => {
+                        self.written.push((address, bytes.to_vec()))
+                    }
+                    embedded_hal_async::i2c::Operation::Read(buf) => {
+                        let len = self.reply.len().min(buf.len());
+                        buf[..len].copy_from_slice(&self.reply[..len]);
+                        self.reads += 1;
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct MockDelay(u64);
+
+    impl DelayNs for MockDelay {
+        async fn delay_ns(&mut self, ns: u32) {
+            self.0 += u64::from(ns);
+        }
+    }
+
+    fn mock_device(reply: &[u8]) -> AsyncHalDevice<MockBus, MockDelay> {
+        let bus = MockBus {
+            reply: reply.to_vec(),
+            written: Vec::new(),
+            reads: 0,
+        };
+        AsyncHalDevice::new(bus, MockDelay::default(), 0x66)
+    }
+
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let mut fut = pin!(fut);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+                return out;
+            }
+        }
+    }
+
+    #[test]
+    fn runs_data_command() {
+        let mut dev = mock_device(b"\x01?S,K\0");
+        let scale = block_on(dev.run(&ScaleState)).unwrap();
+        assert_eq!(scale, TemperatureScale::Kelvin);
+        let (bus, delay) = dev.release();
+        assert_eq!(bus.written, vec![(0x66, b"S,?\0".to_vec())]);
+        assert_eq!(delay.0, ScaleState.get_delay() * 1_000_000);
+    }
+
+    #[test]
+    fn runs_ack_command() {
+        let mut dev = mock_device(&[1, 0]);
+        assert_eq!(block_on(dev.run(&LedOn)).unwrap(), ResponseStatus::Ack);
+    }
+
+    #[test]
+    fn skips_reading_when_no_reply_is_sent() {
+        let mut dev = mock_device(&[]);
+        block_on(dev.run(&Sleep)).unwrap();
+        let (bus, delay) = dev.release();
+        assert_eq!(bus.reads, 0);
+        assert_eq!(delay.0, 0);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn shared_bus_runs_commands_of_each_chip() {
+        let bus = SharedI2c::new(mock_device(&[1, 0]).release().0);
+        let mut first = AsyncHalDevice::new(bus.clone(), MockDelay::default(), 0x66);
+        let mut second = AsyncHalDevice::new(bus.clone(), MockDelay::default(), 0x67);
+        assert_eq!(block_on(first.run(&LedOn)).unwrap(), ResponseStatus::Ack);
+        assert_eq!(block_on(second.run(&LedOn)).unwrap(), ResponseStatus::Ack);
+
+        let bus = block_on(bus.bus.lock());
+        assert_eq!(
+            bus.written,
+            vec![(0x66, b"L,1\0".to_vec()), (0x67, b"L,1\0".to_vec())]
+        );
+        assert_eq!(bus.reads, 2);
+    }
+
+    #[test]
+    fn pending_reply_yields_error() {
+        let mut dev = mock_device(&[254, 0]);
+        let err = block_on(dev.run(&ScaleState)).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::PendingResponse);
+    }
+}
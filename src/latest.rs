@@ -0,0 +1,140 @@
+//! The latest temperature of a sensor, along with how old it is.
+//!
+//! A `LatestReading` holds the newest temperature and the instant it was
+//! captured. Its clones share the same value across threads, so consumers
+//! can ask for the current temperature, and how old it is, without running
+//! an I2C transaction. It is a `Publish` destination of a `Sampler`.
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+use super::response::Temperature;
+#[cfg(feature = "linux-i2c")]
+use super::sampler::Publish;
+
+/// A temperature, along with the instant at which it was captured.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CachedReading {
+    pub temperature: Temperature,
+    pub captured: Instant,
+}
+
+impl CachedReading {
+    /// Returns the time elapsed since the temperature was captured.
+    pub fn age(&self) -> Duration {
+        self.captured.elapsed()
+    }
+
+    /// Returns `true` if the temperature was captured at most `max_age`
+    /// ago.
+    pub fn is_fresh(&self, max_age: Duration) -> bool {
+        self.age() <= max_age
+    }
+}
+
+/// The latest temperature, shared by its clones. It starts empty.
+#[derive(Clone, Debug, Default)]
+pub struct LatestReading {
+    reading: Arc<RwLock<Option<CachedReading>>>,
+}
+
+impl LatestReading {
+    /// Creates an empty cell.
+    pub fn new() -> LatestReading {
+        LatestReading::default()
+    }
+
+    /// Stores `temperature`, captured now.
+    pub fn set(&self, temperature: Temperature) {
+        self.set_at(temperature, Instant::now());
+    }
+
+    /// Stores `temperature`, captured at `captured`, unless the cell holds
+    /// a newer one.
+    pub fn set_at(&self, temperature: Temperature, captured: Instant) {
+        let mut reading = self.reading.write().unwrap_or_else(PoisonError::into_inner);
+        match *reading {
+            Some(ref latest) if latest.captured > captured => {}
+            _ => {
+                *reading = Some(CachedReading {
+                    temperature,
+                    captured,
+                })
+            }
+        }
+    }
+
+    /// Returns the latest temperature, and when it was captured, if any.
+    pub fn get(&self) -> Option<CachedReading> {
+        *self.reading.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the latest temperature, if any.
+    pub fn temperature(&self) -> Option<Temperature> {
+        self.get().map(|reading| reading.temperature)
+    }
+
+    /// Returns the time elapsed since the latest temperature was captured,
+    /// if any.
+    pub fn age(&self) -> Option<Duration> {
+        self.get().map(|reading| reading.age())
+    }
+
+    /// Returns the latest temperature, if it was captured at most `max_age`
+    /// ago.
+    pub fn fresh(&self, max_age: Duration) -> Option<Temperature> {
+        match self.get() {
+            Some(reading) if reading.is_fresh(max_age) => Some(reading.temperature),
+            _ => None,
+        }
+    }
+
+    /// Empties the cell, e.g. once the probe was disconnected.
+    pub fn clear(&self) {
+        *self.reading.write().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+/// Stores every temperature, captured as it is published. Readers may come
+/// and go through clones of the cell, so it never stops the sampler.
+#[cfg(feature = "linux-i2c")]
+impl Publish for LatestReading {
+    fn publish(&mut self, temperature: Temperature) -> bool {
+        self.set(temperature);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_newest_temperature() {
+        let latest = LatestReading::new();
+        assert_eq!(latest.get(), None);
+        assert_eq!(latest.fresh(Duration::from_secs(60)), None);
+
+        let shared = latest.clone();
+        let now = Instant::now();
+        shared.set_at(Temperature::Celsius(21.5), now);
+        shared.set_at(Temperature::Celsius(20.0), now - Duration::from_secs(1));
+        assert_eq!(latest.temperature(), Some(Temperature::Celsius(21.5)));
+        assert_eq!(
+            latest.fresh(Duration::from_secs(60)),
+            Some(Temperature::Celsius(21.5))
+        );
+
+        latest.clear();
+        assert_eq!(shared.temperature(), None);
+    }
+
+    #[test]
+    fn tells_how_old_the_temperature_is() {
+        let latest = LatestReading::new();
+        let captured = Instant::now() - Duration::from_secs(5);
+        latest.set_at(Temperature::Kelvin(300.0), captured);
+        assert!(latest.age().unwrap() >= Duration::from_secs(5));
+        assert_eq!(latest.fresh(Duration::from_secs(1)), None);
+        assert!(latest.fresh(Duration::from_secs(60)).is_some());
+    }
+}
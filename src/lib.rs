@@ -1,16 +1,136 @@
 //! I2C Commands for EZO RTD Chip, taken from their Datasheet.
 //! This chip is used for temperature measurement. It features
 //! calibration, sleep mode, scale, etc.
+#[cfg(feature = "approx")]
+extern crate approx;
+#[cfg(feature = "async-std")]
+extern crate async_std;
+extern crate chrono;
+#[cfg(feature = "defmt")]
+extern crate defmt;
+#[cfg(feature = "embedded-hal")]
+extern crate embedded_hal;
+#[cfg(feature = "embedded-hal-async")]
+extern crate embedded_hal_async;
 extern crate failure;
+#[cfg(feature = "ftdi")]
+extern crate ftdi_embedded_hal;
 #[macro_use]
 extern crate ezo_common;
+#[cfg(feature = "heapless")]
+extern crate heapless;
 extern crate i2cdev;
+extern crate nix;
+#[cfg(feature = "rusqlite")]
+extern crate rusqlite;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+extern crate thiserror;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "toml")]
+extern crate toml;
 
 /// Issuable commands for the EZO RTD Chip.
 pub mod command;
 
+/// Command and response grammar of the EZO RTD Chip, using only `core`.
+pub mod grammar;
+
 /// Parseable responses from the EZO RTD Chip.
 pub mod response;
 
+/// Errors for the high-level sensor API.
+pub mod errors;
+
+/// High-level handle for the EZO RTD Chip.
+#[cfg(feature = "linux-i2c")]
+pub mod sensor;
+
+/// Diagnostic reports for the EZO RTD Chip.
+pub mod report;
+
+/// Exported configuration of the EZO RTD Chip.
+pub mod export;
+
+/// Configuration settings of the EZO RTD Chip.
+pub mod config;
+
+/// Sensor settings from the environment or a configuration file.
+#[cfg(feature = "linux-i2c")]
+pub mod settings;
+
+/// Pipelined reads of several EZO RTD Chips.
+#[cfg(feature = "linux-i2c")]
+pub mod multi;
+
+/// Cancellation of long command sequences.
+pub mod cancel;
+
+/// Background sampling of the EZO RTD Chip.
+#[cfg(feature = "linux-i2c")]
+pub mod sampler;
+
+/// In-memory history of the readings of the EZO RTD Chip.
+#[cfg(feature = "linux-i2c")]
+pub mod buffer;
+
+/// Latest temperature of the EZO RTD Chip, and how old it is.
+pub mod latest;
+
+/// Smoothing filters for the readings of the EZO RTD Chip.
+pub mod filters;
+
+/// Windowed statistics of the readings of the EZO RTD Chip.
+pub mod stats;
+
+/// Alarms on the temperatures of the EZO RTD Chip.
+pub mod alarm;
+
+/// Host-side data logging of the EZO RTD Chip.
+#[cfg(feature = "linux-i2c")]
+pub mod logger;
+
+/// SQLite storage of readings and device events.
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+/// Recovery of the EZO RTD Chip from bus hiccups.
+#[cfg(feature = "linux-i2c")]
+pub mod watchdog;
+
+/// Steps of the I2C transaction of a command.
+pub mod protocol;
+
+/// Commands over type-erased I2C devices.
+pub mod transport;
+
+/// Retries of commands on flaky buses.
+pub mod retry;
+
+/// Middleware layered around command execution.
+pub mod middleware;
+
+/// Async commands, with a pluggable runtime.
+#[cfg(feature = "linux-i2c")]
+pub mod nonblocking;
+
+/// Commands over `embedded-hal` I2C buses.
+#[cfg(feature = "embedded-hal")]
+pub mod hal;
+
+/// Commands over FTDI USB-to-I2C bridges.
+#[cfg(feature = "ftdi")]
+pub mod ftdi;
+
+/// Async commands over `embedded-hal-async` I2C buses.
+#[cfg(feature = "embedded-hal-async")]
+pub mod hal_async;
+
 // Re-export errors from ezo_common crate.
 pub use ezo_common::errors::{ErrorKind, EzoError};
+
+// Errors of the high-level API, which wrap the ones above; see `errors`.
+pub use errors::{RtdError, RtdErrorKind, Transient};
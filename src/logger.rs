@@ -0,0 +1,954 @@
+//! Host-side data logging of the readings of a sensor.
+//!
+//! A `Sink` writes one record per timestamped reading. `CsvLogger` writes
+//! CSV rows, with the columns it is given, and `JsonLinesLogger` writes one
+//! JSON object per line, for ingestion systems that prefer structured
+//! records. Either writes to a file, or to any `io::Write`. `Rotating`
+//! splits the records of either across daily files, or files of a given
+//! size, keeping as many of them as it is told.
+//!
+//! A `Datalogger` reads a sensor at a fixed interval, and logs every reading
+//! to its sink until it is cancelled, which is what the `read-loop` example
+//! does by hand. After an outage of the host, `backfill()` recovers the
+//! readings that the chip logged in its own memory meanwhile.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::cancel::CancelToken;
+use super::command::{MemoryClear, MemoryRecallLast};
+use super::errors::{ResultExt, RtdError, RtdErrorKind};
+use super::sensor::{RtdSensor, TimestampedReading};
+
+use chrono::{DateTime, Utc};
+
+/// Destination of the readings logged by a `Datalogger`.
+pub trait Sink {
+    /// Writes a record for `reading`, taken from the chip at `address`.
+    fn log(&mut self, address: u16, reading: &TimestampedReading) -> Result<(), RtdError>;
+
+    /// Writes a record for `reading`, recovered from the memory of the chip
+    /// at `address` by `backfill()`, so its timestamp is an estimate.
+    ///
+    /// By default, it is written as any other record.
+    fn log_recovered(
+        &mut self,
+        address: u16,
+        reading: &TimestampedReading,
+    ) -> Result<(), RtdError> {
+        self.log(address, reading)
+    }
+
+    /// Flushes the records written so far.
+    fn flush(&mut self) -> Result<(), RtdError>;
+}
+
+/// When a logger flushes the records it writes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// After every record, so that no reading is lost if the process dies.
+    EveryRecord,
+    /// After every `n` records, to write less often, e.g. to an SD card.
+    EveryRecords(u32),
+    /// Only when `flush()` is called, when `Datalogger::run()` returns, or
+    /// when the writer flushes itself, e.g. when a full `BufWriter` does.
+    Manual,
+}
+
+// Counts the records written since the last flush.
+#[derive(Copy, Clone, Debug)]
+struct Flushing {
+    policy: FlushPolicy,
+    unflushed: u32,
+}
+
+impl Flushing {
+    fn new(policy: FlushPolicy) -> Flushing {
+        Flushing {
+            policy,
+            unflushed: 0,
+        }
+    }
+
+    // Records a written record, returning `true` if it is time to flush.
+    fn written(&mut self) -> bool {
+        self.unflushed += 1;
+        let due = match self.policy {
+            FlushPolicy::EveryRecord => true,
+            FlushPolicy::EveryRecords(n) => self.unflushed >= n,
+            FlushPolicy::Manual => false,
+        };
+        if due {
+            self.unflushed = 0;
+        }
+        due
+    }
+}
+
+// Opens `path` to append records to it, telling whether it is empty.
+fn open_append(path: &Path) -> Result<(BufWriter<File>, bool), RtdError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context(RtdErrorKind::LogWrite)?;
+    let empty = file.metadata().context(RtdErrorKind::LogWrite)?.len() == 0;
+    Ok((BufWriter::new(file), empty))
+}
+
+/// A column of the rows written by a `CsvLogger`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Column {
+    /// The time of the reading, in RFC 3339 format, e.g.
+    /// `2018-07-14T12:00:00+00:00`.
+    Timestamp,
+    /// The value of the reading, e.g. `21.5`.
+    Value,
+    /// The scale of the reading, e.g. `celsius`.
+    Scale,
+    /// The number of the row, starting at 0.
+    Sequence,
+    /// The I2C address of the chip, e.g. `102`.
+    Address,
+    /// Whether the reading was recovered from the memory of the chip by
+    /// `backfill()`, i.e. `true` or `false`.
+    Recovered,
+}
+
+impl Column {
+    /// Returns the name of the column, written in the header.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Column::Timestamp => "timestamp",
+            Column::Value => "value",
+            Column::Scale => "scale",
+            Column::Sequence => "sequence",
+            Column::Address => "address",
+            Column::Recovered => "recovered",
+        }
+    }
+}
+
+/// Writes timestamped readings as CSV rows.
+///
+/// By default, the timestamp, value, scale, and sequence columns are
+/// written, and rows are flushed one by one.
+pub struct CsvLogger<W: Write> {
+    writer: W,
+    columns: Vec<Column>,
+    flushing: Flushing,
+    header: bool,
+    sequence: u64,
+}
+
+impl CsvLogger<BufWriter<File>> {
+    /// Appends rows to the file at `path`, creating it if needed.
+    ///
+    /// The header is written only if the file is empty, so that a logger
+    /// that is restarted keeps appending to the same table.
+    pub fn append<P: AsRef<Path>>(path: P) -> Result<CsvLogger<BufWriter<File>>, RtdError> {
+        let (writer, empty) = open_append(path.as_ref())?;
+        Ok(CsvLogger::new(writer).header(empty))
+    }
+}
+
+impl<W: Write> CsvLogger<W> {
+    /// Writes rows to `writer`, starting with a header.
+    pub fn new(writer: W) -> CsvLogger<W> {
+        CsvLogger {
+            writer,
+            columns: vec![
+                Column::Timestamp,
+                Column::Value,
+                Column::Scale,
+                Column::Sequence,
+            ],
+            flushing: Flushing::new(FlushPolicy::EveryRecord),
+            header: true,
+            sequence: 0,
+        }
+    }
+
+    /// Sets the columns of every row, in order.
+    pub fn columns(mut self, columns: &[Column]) -> CsvLogger<W> {
+        self.columns = columns.to_vec();
+        self
+    }
+
+    /// Sets when rows are flushed.
+    pub fn flush_policy(mut self, flush: FlushPolicy) -> CsvLogger<W> {
+        self.flushing = Flushing::new(flush);
+        self
+    }
+
+    /// Sets whether a header is written before the first row.
+    pub fn header(mut self, header: bool) -> CsvLogger<W> {
+        self.header = header;
+        self
+    }
+
+    /// Returns the number of rows written so far.
+    pub fn rows(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Gives back the writer, without flushing it.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_row(
+        &mut self,
+        address: u16,
+        reading: &TimestampedReading,
+        recovered: bool,
+    ) -> io::Result<()> {
+        if self.header {
+            let names: Vec<_> = self.columns.iter().map(Column::name).collect();
+            writeln!(self.writer, "{}", names.join(","))?;
+            self.header = false;
+        }
+
+        for (i, column) in self.columns.iter().enumerate() {
+            if i > 0 {
+                write!(self.writer, ",")?;
+            }
+            match *column {
+                Column::Timestamp => write!(self.writer, "{}", reading.timestamp.to_rfc3339())?,
+                Column::Value => write!(self.writer, "{}", reading.temperature.value())?,
+                Column::Scale => write!(self.writer, "{}", reading.temperature.scale())?,
+                Column::Sequence => write!(self.writer, "{}", self.sequence)?,
+                Column::Address => write!(self.writer, "{}", address)?,
+                Column::Recovered => write!(self.writer, "{}", recovered)?,
+            }
+        }
+        writeln!(self.writer)?;
+        self.sequence += 1;
+
+        if self.flushing.written() {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Sink for CsvLogger<W> {
+    fn log(&mut self, address: u16, reading: &TimestampedReading) -> Result<(), RtdError> {
+        self.write_row(address, reading, false)
+            .context(RtdErrorKind::LogWrite)
+    }
+
+    /// Marks the row as recovered in the `Recovered` column, if any.
+    fn log_recovered(
+        &mut self,
+        address: u16,
+        reading: &TimestampedReading,
+    ) -> Result<(), RtdError> {
+        self.write_row(address, reading, true)
+            .context(RtdErrorKind::LogWrite)
+    }
+
+    fn flush(&mut self) -> Result<(), RtdError> {
+        self.writer.flush().context(RtdErrorKind::LogWrite)
+    }
+}
+
+/// Writes timestamped readings as JSON lines, one object per reading, e.g.
+/// `{"timestamp":"2018-07-14T12:00:00+00:00","value":21.5,"unit":"°C","address":102}`.
+///
+/// Readings recovered by `backfill()` have an additional `"recovered":true`
+/// field.
+///
+/// By default, lines are flushed one by one.
+pub struct JsonLinesLogger<W: Write> {
+    writer: W,
+    flushing: Flushing,
+}
+
+impl JsonLinesLogger<BufWriter<File>> {
+    /// Appends lines to the file at `path`, creating it if needed.
+    pub fn append<P: AsRef<Path>>(path: P) -> Result<JsonLinesLogger<BufWriter<File>>, RtdError> {
+        let (writer, _empty) = open_append(path.as_ref())?;
+        Ok(JsonLinesLogger::new(writer))
+    }
+}
+
+impl<W: Write> JsonLinesLogger<W> {
+    /// Writes lines to `writer`.
+    pub fn new(writer: W) -> JsonLinesLogger<W> {
+        JsonLinesLogger {
+            writer,
+            flushing: Flushing::new(FlushPolicy::EveryRecord),
+        }
+    }
+
+    /// Sets when lines are flushed.
+    pub fn flush_policy(mut self, flush: FlushPolicy) -> JsonLinesLogger<W> {
+        self.flushing = Flushing::new(flush);
+        self
+    }
+
+    /// Gives back the writer, without flushing it.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    // Every field is a number, or a string that needs no escaping, so the
+    // object is written as is.
+    fn write_line(
+        &mut self,
+        address: u16,
+        reading: &TimestampedReading,
+        recovered: bool,
+    ) -> io::Result<()> {
+        write!(
+            self.writer,
+            r#"{{"timestamp":"{}","value":{},"unit":"{}","address":{}"#,
+            reading.timestamp.to_rfc3339(),
+            reading.temperature.value(),
+            reading.temperature.scale().symbol(),
+            address
+        )?;
+        if recovered {
+            write!(self.writer, r#","recovered":true"#)?;
+        }
+        writeln!(self.writer, "}}")?;
+        if self.flushing.written() {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Sink for JsonLinesLogger<W> {
+    fn log(&mut self, address: u16, reading: &TimestampedReading) -> Result<(), RtdError> {
+        self.write_line(address, reading, false)
+            .context(RtdErrorKind::LogWrite)
+    }
+
+    fn log_recovered(
+        &mut self,
+        address: u16,
+        reading: &TimestampedReading,
+    ) -> Result<(), RtdError> {
+        self.write_line(address, reading, true)
+            .context(RtdErrorKind::LogWrite)
+    }
+
+    fn flush(&mut self) -> Result<(), RtdError> {
+        self.writer.flush().context(RtdErrorKind::LogWrite)
+    }
+}
+
+/// When a `Rotating` sink starts a new file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    /// Every day, in UTC, naming each file after the day of its readings,
+    /// e.g. `readings-2018-07-14.csv`.
+    ///
+    /// A reading of an earlier day than the file being written, e.g. one
+    /// recovered by `backfill()`, is appended to the file of its day,
+    /// without starting a new file or removing old ones.
+    Daily,
+    /// Once the file holds at least the given number of bytes, naming each
+    /// file after the time of its first reading, e.g.
+    /// `readings-2018-07-14T12-00-00.csv`.
+    ///
+    /// Only the flushed records are counted, so with `FlushPolicy::Manual`
+    /// a file may grow well beyond its limit.
+    Size(u64),
+}
+
+/// Splits the records of a file-based sink across several files, so that
+/// long-running loggers do not grow a single file without bounds.
+///
+/// Files are named after the path given to `new()`, with the day or time
+/// of their first reading before the extension, and are opened with the
+/// given function, e.g. one calling `CsvLogger::append`, which writes a
+/// header at the start of every file. By default, old files are kept.
+pub struct Rotating<S> {
+    dir: PathBuf,
+    stem: String,
+    extension: String,
+    rotation: Rotation,
+    max_files: Option<usize>,
+    open: Box<dyn FnMut(&Path) -> Result<S, RtdError> + Send>,
+    current: Option<(S, PathBuf)>,
+}
+
+impl<S: Sink> Rotating<S> {
+    /// Rotates files named after `path`, opening each one with `open`.
+    pub fn new<P, F>(path: P, rotation: Rotation, open: F) -> Rotating<S>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&Path) -> Result<S, RtdError> + Send + 'static,
+    {
+        let path = path.as_ref();
+        let dir = match path.parent() {
+            Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let extension = path
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        Rotating {
+            dir,
+            stem,
+            extension,
+            rotation,
+            max_files: None,
+            open: Box::new(open),
+            current: None,
+        }
+    }
+
+    /// Keeps at most `max_files` files, deleting the oldest ones as new
+    /// files are started.
+    pub fn max_files(mut self, max_files: usize) -> Rotating<S> {
+        self.max_files = Some(max_files.max(1));
+        self
+    }
+
+    /// Returns the path of the file being written, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.current.as_ref().map(|(_, path)| path.as_path())
+    }
+
+    // Returns the path of the file for `reading`, if it is not the one
+    // being written.
+    fn next_path(&self, reading: &TimestampedReading) -> Option<PathBuf> {
+        let current = match self.current {
+            Some((_, ref path)) => path,
+            None => return Some(self.path_for(reading)),
+        };
+        let due = match self.rotation {
+            Rotation::Daily => self.path_for(reading) != *current,
+            Rotation::Size(max_bytes) => match fs::metadata(current) {
+                Ok(metadata) => metadata.len() >= max_bytes,
+                Err(_) => false,
+            },
+        };
+        if due {
+            Some(self.path_for(reading))
+        } else {
+            None
+        }
+    }
+
+    fn path_for(&self, reading: &TimestampedReading) -> PathBuf {
+        let format = match self.rotation {
+            Rotation::Daily => "%Y-%m-%d",
+            Rotation::Size(_) => "%Y-%m-%dT%H-%M-%S",
+        };
+        self.dir.join(format!(
+            "{}-{}{}",
+            self.stem,
+            reading.timestamp.format(format),
+            self.extension
+        ))
+    }
+
+    // Returns the path of the file for `reading`, with `Rotation::Daily`, if
+    // it is of an earlier day than the file being written, e.g. one
+    // recalled from the memory of the chip. Daily files are named after
+    // their day, so earlier days come first by name.
+    fn earlier_path(&self, reading: &TimestampedReading) -> Option<PathBuf> {
+        let current = match (self.rotation, &self.current) {
+            (Rotation::Daily, Some((_, path))) => path,
+            _ => return None,
+        };
+        let path = self.path_for(reading);
+        if path < *current {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    // Writes `reading` with `write`, to the file of its day if it is of an
+    // earlier day, or else to the file being written, starting a new file
+    // if needed. The file of an earlier day is opened on its own, so that
+    // the file being written stays the same, and no file is removed.
+    fn write_to<F>(&mut self, reading: &TimestampedReading, write: F) -> Result<(), RtdError>
+    where
+        F: FnOnce(&mut S) -> Result<(), RtdError>,
+    {
+        if let Some(path) = self.earlier_path(reading) {
+            let mut sink = (self.open)(&path)?;
+            write(&mut sink)?;
+            return sink.flush();
+        }
+        if let Some(path) = self.next_path(reading) {
+            self.rotate(path)?;
+        }
+        match self.current {
+            Some((ref mut sink, _)) => write(sink),
+            None => Ok(()),
+        }
+    }
+
+    // Flushes the file being written, and starts writing to `path`.
+    fn rotate(&mut self, path: PathBuf) -> Result<(), RtdError> {
+        if let Some((mut sink, _)) = self.current.take() {
+            sink.flush()?;
+        }
+        let sink = (self.open)(&path)?;
+        self.current = Some((sink, path));
+        self.remove_old_files()
+    }
+
+    // Rotated files are named after their first reading, so the oldest
+    // ones come first by name.
+    fn remove_old_files(&self) -> Result<(), RtdError> {
+        let max_files = match self.max_files {
+            Some(max_files) => max_files,
+            None => return Ok(()),
+        };
+        let prefix = format!("{}-", self.stem);
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&self.dir).context(RtdErrorKind::LogWrite)? {
+            let path = entry.context(RtdErrorKind::LogWrite)?.path();
+            let rotated = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.starts_with(&prefix) && name.ends_with(&self.extension),
+                None => false,
+            };
+            if rotated && Some(path.as_path()) != self.path() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        // The file being written counts as one of the files kept.
+        let excess = (paths.len() + 1).saturating_sub(max_files);
+        for path in paths.iter().take(excess) {
+            fs::remove_file(path).context(RtdErrorKind::LogWrite)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: Sink> Sink for Rotating<S> {
+    fn log(&mut self, address: u16, reading: &TimestampedReading) -> Result<(), RtdError> {
+        self.write_to(reading, |sink| sink.log(address, reading))
+    }
+
+    fn log_recovered(
+        &mut self,
+        address: u16,
+        reading: &TimestampedReading,
+    ) -> Result<(), RtdError> {
+        self.write_to(reading, |sink| sink.log_recovered(address, reading))
+    }
+
+    fn flush(&mut self) -> Result<(), RtdError> {
+        match self.current {
+            Some((ref mut sink, _)) => sink.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What a `Datalogger` does when a reading fails.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stops, returning the error.
+    Stop,
+    /// Skips the reading, counting it in `failures()`.
+    Skip,
+}
+
+/// Reads a sensor at a fixed interval, logging every reading to a `Sink`.
+///
+/// By default, it stops at the first failed reading.
+pub struct Datalogger<S> {
+    sink: S,
+    on_error: ErrorPolicy,
+    failures: u64,
+    last: Option<DateTime<Utc>>,
+}
+
+impl<S: Sink> Datalogger<S> {
+    /// Logs readings to `sink`.
+    pub fn new(sink: S) -> Datalogger<S> {
+        Datalogger {
+            sink,
+            on_error: ErrorPolicy::Stop,
+            failures: 0,
+            last: None,
+        }
+    }
+
+    /// Sets what `run()` does when a reading fails.
+    pub fn on_error(mut self, on_error: ErrorPolicy) -> Datalogger<S> {
+        self.on_error = on_error;
+        self
+    }
+
+    /// Returns the number of readings that were skipped so far.
+    pub fn failures(&self) -> u64 {
+        self.failures
+    }
+
+    /// Returns the timestamp of the latest reading logged by `run()`, if
+    /// any.
+    pub fn last_logged(&self) -> Option<DateTime<Utc>> {
+        self.last
+    }
+
+    /// Drains the memory of the chip into the sink, as `backfill()` does,
+    /// skipping the readings that are not newer than the latest one logged
+    /// by `run()`, e.g. to fill the gap left by an outage of the host.
+    pub fn backfill(&mut self, sensor: &mut RtdSensor) -> Result<usize, RtdError> {
+        backfill(sensor, &mut self.sink, self.last)
+    }
+
+    /// Mutable access to the sink.
+    pub fn sink(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Gives back the sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+
+    /// Reads `sensor` every `interval`, logging every reading, until
+    /// `cancel` is cancelled.
+    ///
+    /// Failed readings are handled as the error policy tells; records that
+    /// cannot be written always stop the logger. The records written so
+    /// far are flushed before returning.
+    pub fn run(
+        &mut self,
+        sensor: &mut RtdSensor,
+        interval: Duration,
+        cancel: &CancelToken,
+    ) -> Result<(), RtdError> {
+        let res = self.log_readings(sensor, interval, cancel);
+        let flushed = self.sink.flush();
+        match res {
+            Err(ref err) if err.kind() == RtdErrorKind::Cancelled => flushed,
+            Err(err) => Err(err),
+            Ok(()) => flushed,
+        }
+    }
+
+    fn log_readings(
+        &mut self,
+        sensor: &mut RtdSensor,
+        interval: Duration,
+        cancel: &CancelToken,
+    ) -> Result<(), RtdError> {
+        let address = sensor.address();
+        for reading in sensor.readings(interval) {
+            match reading {
+                Ok(reading) => {
+                    self.sink.log(address, &reading)?;
+                    self.last = Some(reading.timestamp);
+                }
+                Err(_) if self.on_error == ErrorPolicy::Skip => self.failures += 1,
+                Err(err) => return Err(err),
+            }
+            cancel.check()?;
+        }
+        Ok(())
+    }
+}
+
+/// Drains the readings stored in the memory of the chip by its data logger
+/// into `sink`, marking them as recovered, and returns how many were
+/// logged.
+///
+/// The chip keeps logging while the host is down, so this recovers the
+/// readings of an outage. Only the readings newer than `since`, e.g. the
+/// latest one logged before the outage, are logged, so that readings that
+/// are already in the log are not duplicated; their timestamps are
+/// estimates, as `RtdSensor::recall_memory()` tells. Once the readings are
+/// written and flushed, the memory of the chip is cleared.
+///
+/// The data logger keeps storing readings while the memory is drained, and
+/// clearing it would erase those that were not recalled. So the memory is
+/// only cleared if its last location is still the latest one recalled;
+/// otherwise it is left as it is, and the next backfill recalls it all
+/// again, skipping the readings that are not newer than `since`. A reading
+/// stored between that check and the clear itself is still lost.
+pub fn backfill<S: Sink>(
+    sensor: &mut RtdSensor,
+    sink: &mut S,
+    since: Option<DateTime<Utc>>,
+) -> Result<usize, RtdError> {
+    let address = sensor.address();
+    let (readings, drained) = sensor.drain_memory()?;
+    let mut logged = 0;
+    for reading in readings {
+        let newer = match since {
+            Some(since) => reading.timestamp > since,
+            None => true,
+        };
+        if newer {
+            sink.log_recovered(address, &reading)?;
+            logged += 1;
+        }
+    }
+    sink.flush()?;
+    if sensor.run(MemoryRecallLast)?.location <= drained {
+        sensor.run(MemoryClear)?;
+    }
+    Ok(logged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::{Float, Temperature};
+
+    fn reading(value: Float) -> TimestampedReading {
+        reading_at("2018-07-14T12:00:00Z", value)
+    }
+
+    fn reading_at(timestamp: &str, value: Float) -> TimestampedReading {
+        TimestampedReading {
+            timestamp: timestamp.parse::<DateTime<Utc>>().unwrap(),
+            temperature: Temperature::Celsius(value),
+        }
+    }
+
+    // A fresh directory for the files written by a test.
+    fn log_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ezo-rtd-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn writes_header_then_rows() {
+        let mut logger = CsvLogger::new(Vec::new());
+        logger.log(0x66, &reading(21.5)).unwrap();
+        logger.log(0x66, &reading(22.0)).unwrap();
+        assert_eq!(logger.rows(), 2);
+        assert_eq!(
+            String::from_utf8(logger.into_inner()).unwrap(),
+            "timestamp,value,scale,sequence\n\
+             2018-07-14T12:00:00+00:00,21.5,celsius,0\n\
+             2018-07-14T12:00:00+00:00,22,celsius,1\n"
+        );
+    }
+
+    #[test]
+    fn writes_chosen_columns() {
+        let mut logger = CsvLogger::new(Vec::new())
+            .columns(&[Column::Sequence, Column::Value, Column::Address])
+            .header(false);
+        logger.log(0x66, &reading(21.5)).unwrap();
+        assert_eq!(
+            String::from_utf8(logger.into_inner()).unwrap(),
+            "0,21.5,102\n"
+        );
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        let mut logger = JsonLinesLogger::new(Vec::new());
+        logger.log(0x66, &reading(21.5)).unwrap();
+        logger.log(0x67, &reading(-3.0)).unwrap();
+        let lines = String::from_utf8(logger.into_inner()).unwrap();
+        let lines: Vec<_> = lines.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                r#"{"timestamp":"2018-07-14T12:00:00+00:00","value":21.5,"unit":"°C","address":102}"#,
+                r#"{"timestamp":"2018-07-14T12:00:00+00:00","value":-3,"unit":"°C","address":103}"#,
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_lines_are_valid_json() {
+        let mut logger = JsonLinesLogger::new(Vec::new());
+        logger.log(0x66, &reading(21.5)).unwrap();
+        let line = String::from_utf8(logger.into_inner()).unwrap();
+        let record: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(record["value"], 21.5);
+        assert_eq!(record["unit"], "°C");
+    }
+
+    /// Counts the flushes of the records written to it.
+    #[derive(Default)]
+    struct CountFlushes(Vec<u8>, u32);
+
+    impl Write for CountFlushes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.1 += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn flushes_as_the_policy_tells() {
+        let mut logger =
+            CsvLogger::new(CountFlushes::default()).flush_policy(FlushPolicy::EveryRecords(2));
+        for _ in 0..5 {
+            logger.log(0x66, &reading(21.5)).unwrap();
+        }
+        assert_eq!(logger.into_inner().1, 2);
+
+        let mut logger =
+            JsonLinesLogger::new(CountFlushes::default()).flush_policy(FlushPolicy::Manual);
+        logger.log(0x66, &reading(21.5)).unwrap();
+        logger.flush().unwrap();
+        assert_eq!(logger.into_inner().1, 1);
+    }
+
+    #[test]
+    fn rotates_daily_files() {
+        let dir = log_dir("daily");
+        let mut logger = Rotating::new(dir.join("readings.csv"), Rotation::Daily, |path: &Path| {
+            CsvLogger::append(path)
+        })
+        .max_files(2);
+        logger
+            .log(0x66, &reading_at("2018-07-14T12:00:00Z", 21.0))
+            .unwrap();
+        logger
+            .log(0x66, &reading_at("2018-07-14T23:00:00Z", 21.5))
+            .unwrap();
+        logger
+            .log(0x66, &reading_at("2018-07-15T01:00:00Z", 22.0))
+            .unwrap();
+        logger
+            .log(0x66, &reading_at("2018-07-16T01:00:00Z", 22.5))
+            .unwrap();
+        logger.flush().unwrap();
+
+        assert_eq!(
+            file_names(&dir),
+            ["readings-2018-07-15.csv", "readings-2018-07-16.csv"]
+        );
+        assert_eq!(
+            logger.path(),
+            Some(dir.join("readings-2018-07-16.csv").as_path())
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("readings-2018-07-15.csv")).unwrap(),
+            "timestamp,value,scale,sequence\n\
+             2018-07-15T01:00:00+00:00,22,celsius,0\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn writes_backfilled_readings_to_the_file_of_their_day() {
+        let dir = log_dir("backfill");
+        let mut logger = Rotating::new(dir.join("readings.csv"), Rotation::Daily, |path: &Path| {
+            CsvLogger::append(path)
+        })
+        .max_files(1);
+        logger
+            .log(0x66, &reading_at("2018-07-15T12:00:00Z", 22.0))
+            .unwrap();
+        logger
+            .log_recovered(0x66, &reading_at("2018-07-14T23:00:00Z", 21.0))
+            .unwrap();
+        logger
+            .log(0x66, &reading_at("2018-07-15T12:00:10Z", 22.5))
+            .unwrap();
+        logger
+            .log_recovered(0x66, &reading_at("2018-07-14T23:00:10Z", 21.5))
+            .unwrap();
+        logger.flush().unwrap();
+
+        assert_eq!(
+            file_names(&dir),
+            ["readings-2018-07-14.csv", "readings-2018-07-15.csv"]
+        );
+        assert_eq!(
+            logger.path(),
+            Some(dir.join("readings-2018-07-15.csv").as_path())
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("readings-2018-07-14.csv")).unwrap(),
+            "timestamp,value,scale,sequence\n\
+             2018-07-14T23:00:00+00:00,21,celsius,0\n\
+             2018-07-14T23:00:10+00:00,21.5,celsius,0\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("readings-2018-07-15.csv")).unwrap(),
+            "timestamp,value,scale,sequence\n\
+             2018-07-15T12:00:00+00:00,22,celsius,0\n\
+             2018-07-15T12:00:10+00:00,22.5,celsius,1\n"
+        );
+
+        // The next day prunes the files as usual.
+        logger
+            .log(0x66, &reading_at("2018-07-16T01:00:00Z", 23.0))
+            .unwrap();
+        assert_eq!(file_names(&dir), ["readings-2018-07-16.csv"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_files_by_size() {
+        let dir = log_dir("size");
+        let mut logger = Rotating::new(
+            dir.join("readings.jsonl"),
+            Rotation::Size(1),
+            |path: &Path| JsonLinesLogger::append(path),
+        );
+        logger
+            .log(0x66, &reading_at("2018-07-14T12:00:00Z", 21.0))
+            .unwrap();
+        logger
+            .log(0x66, &reading_at("2018-07-14T12:00:10Z", 21.5))
+            .unwrap();
+        logger.flush().unwrap();
+
+        assert_eq!(
+            file_names(&dir),
+            [
+                "readings-2018-07-14T12-00-00.jsonl",
+                "readings-2018-07-14T12-00-10.jsonl"
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn marks_recovered_readings() {
+        let mut logger = CsvLogger::new(Vec::new())
+            .columns(&[Column::Value, Column::Recovered])
+            .header(false);
+        logger.log(0x66, &reading(21.5)).unwrap();
+        logger.log_recovered(0x66, &reading(20.0)).unwrap();
+        assert_eq!(
+            String::from_utf8(logger.into_inner()).unwrap(),
+            "21.5,false\n20,true\n"
+        );
+
+        let mut logger = JsonLinesLogger::new(Vec::new());
+        logger.log_recovered(0x66, &reading(20.0)).unwrap();
+        assert_eq!(
+            String::from_utf8(logger.into_inner()).unwrap().trim_end(),
+            r#"{"timestamp":"2018-07-14T12:00:00+00:00","value":20,"unit":"°C","address":102,"recovered":true}"#
+        );
+    }
+}
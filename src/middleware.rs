@@ -0,0 +1,225 @@
+//! Middleware layered around the commands run on a device.
+//!
+//! Each `Middleware` wraps the execution of every command, so concerns such
+//! as rate limiting, retries, metrics, or auditing can be added to any
+//! command without changing it. Layers run in the order they were added,
+//! the first one being the outermost.
+use std::error::Error as StdError;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::protocol::RunOn;
+use super::transport::Transport;
+use super::{ErrorKind, EzoError};
+
+use failure::ResultExt;
+
+/// Runs the next layer, or the command itself after the last layer.
+pub type Next<'a> = dyn FnMut() -> Result<(), EzoError> + 'a;
+
+/// A layer around the execution of commands.
+///
+/// Closures taking the same arguments as `handle()` are middleware too.
+pub trait Middleware {
+    /// Handles the command whose string is `cmd_str`, by calling `next`
+    /// to run it.
+    ///
+    /// `next` may be called more than once, e.g. to retry, or not at all, in
+    /// which case an error should be returned.
+    fn handle(&mut self, cmd_str: &str, next: &mut Next<'_>) -> Result<(), EzoError>;
+}
+
+impl<F> Middleware for F
+where
+    F: FnMut(&str, &mut Next<'_>) -> Result<(), EzoError>,
+{
+    fn handle(&mut self, cmd_str: &str, next: &mut Next<'_>) -> Result<(), EzoError> {
+        self(cmd_str, next)
+    }
+}
+
+/// Waits so that commands are written at least `min_interval` apart.
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+    min_interval: Duration,
+    last: Option<Instant>,
+}
+
+impl RateLimit {
+    /// Allows one command every `min_interval`.
+    pub fn new(min_interval: Duration) -> RateLimit {
+        RateLimit {
+            min_interval,
+            last: None,
+        }
+    }
+}
+
+impl Middleware for RateLimit {
+    fn handle(&mut self, _cmd_str: &str, next: &mut Next<'_>) -> Result<(), EzoError> {
+        if let Some(last) = self.last {
+            let ready_at = last + self.min_interval;
+            thread::sleep(ready_at.saturating_duration_since(Instant::now()));
+        }
+        self.last = Some(Instant::now());
+        next()
+    }
+}
+
+/// The command string of a command that a layer returned from without
+/// running it, nor returning an error.
+///
+/// It is kept as the cause of the `ErrorKind::UnwritableCommand` error
+/// returned by `Layered::run`, which is not transient, since the layer
+/// would skip the command again.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SkippedCommand(pub String);
+
+impl fmt::Display for SkippedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a layer skipped `{}`", self.0)
+    }
+}
+
+impl StdError for SkippedCommand {}
+
+/// A device whose commands run through a stack of middleware.
+pub struct Layered<T> {
+    dev: T,
+    layers: Vec<Box<dyn Middleware + Send>>,
+}
+
+impl<T: Transport> Layered<T> {
+    /// Wraps `dev`, with no middleware yet.
+    pub fn new(dev: T) -> Layered<T> {
+        Layered {
+            dev,
+            layers: Vec::new(),
+        }
+    }
+
+    /// Adds a layer, inside the ones added before it.
+    pub fn with<M: Middleware + Send + 'static>(mut self, layer: M) -> Layered<T> {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    /// Mutable access to the wrapped device.
+    ///
+    /// Commands issued directly on it bypass the middleware.
+    pub fn device(&mut self) -> &mut T {
+        &mut self.dev
+    }
+
+    /// Gives back the wrapped device.
+    pub fn into_inner(self) -> T {
+        self.dev
+    }
+
+    /// Runs the command through every layer, returning its response.
+    ///
+    /// If a layer returns without running the command, nor an error, an
+    /// error of kind `UnwritableCommand` is returned, caused by a
+    /// `SkippedCommand`.
+    pub fn run<C: RunOn>(&mut self, cmd: &C) -> Result<C::Response, EzoError> {
+        let dev = &mut self.dev;
+        let cmd_str = cmd.get_command_string();
+        let mut response = None;
+        run_through(&mut self.layers, &cmd_str, &mut || {
+            response = Some(cmd.run_dyn(dev)?);
+            Ok(())
+        })?;
+        match response {
+            Some(response) => Ok(response),
+            None => Err(SkippedCommand(cmd_str))
+                .context(ErrorKind::UnwritableCommand)
+                .map_err(EzoError::from),
+        }
+    }
+}
+
+fn run_through(
+    layers: &mut [Box<dyn Middleware + Send>],
+    cmd_str: &str,
+    last: &mut Next<'_>,
+) -> Result<(), EzoError> {
+    match layers.split_first_mut() {
+        Some((layer, rest)) => layer.handle(cmd_str, &mut || run_through(rest, cmd_str, last)),
+        None => last(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{LedOn, ScaleState};
+    use crate::errors::Transient;
+    use crate::response::{ResponseStatus, TemperatureScale};
+    use i2cdev::mock::MockI2CDevice;
+    use std::sync::{Arc, Mutex};
+
+    fn mock_reply(offset: usize, data: &[u8]) -> MockI2CDevice {
+        // The mock takes the first byte written as a register offset, and
+        // the rest as data; reads continue right after it.
+        let mut dev = MockI2CDevice::new();
+        dev.regmap.write_regs(offset, data);
+        dev
+    }
+
+    #[test]
+    fn layers_wrap_commands_in_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let outer = Arc::clone(&log);
+        let inner = Arc::clone(&log);
+
+        let mut dev = Layered::new(mock_reply(usize::from(b'S') + 3, b"\x01?S,C\0"))
+            .with(move |cmd_str: &str, next: &mut Next<'_>| {
+                outer.lock().unwrap().push(format!("outer {}", cmd_str));
+                next()
+            })
+            .with(move |cmd_str: &str, next: &mut Next<'_>| {
+                inner.lock().unwrap().push(format!("inner {}", cmd_str));
+                let res = next();
+                inner.lock().unwrap().push(format!("done {}", res.is_ok()));
+                res
+            });
+
+        assert_eq!(dev.run(&ScaleState).unwrap(), TemperatureScale::Celsius);
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["outer S,?", "inner S,?", "done true"]
+        );
+    }
+
+    #[test]
+    fn layers_can_retry_or_skip_commands() {
+        let mut dev = Layered::new(mock_reply(usize::from(b'L') + 3, &[2, 0])).with(
+            |_: &str, next: &mut Next<'_>| match next() {
+                Err(_) => next(),
+                ok => ok,
+            },
+        );
+        let err = dev.run(&LedOn).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::DeviceErrorResponse);
+
+        dev.device()
+            .regmap
+            .write_regs(usize::from(b'L') + 3, &[1, 0]);
+        assert_eq!(dev.run(&LedOn).unwrap(), ResponseStatus::Ack);
+
+        let mut dev = dev.with(|_: &str, _: &mut Next<'_>| -> Result<(), EzoError> { Ok(()) });
+        let err = dev.run(&LedOn).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::UnwritableCommand);
+        assert!(!err.is_transient());
+    }
+
+    #[test]
+    fn rate_limit_spaces_commands() {
+        let mut limit = RateLimit::new(Duration::from_millis(20));
+        let start = Instant::now();
+        limit.handle("R", &mut || Ok(())).unwrap();
+        limit.handle("R", &mut || Ok(())).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
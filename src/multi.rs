@@ -0,0 +1,310 @@
+//! Pipelined reads of several sensors.
+//!
+//! Reading one chip is mostly waiting: `R` takes 600 ms to process. With
+//! several chips, the command is issued to all of them first, then a single
+//! wait covers every chip before the responses are collected, instead of
+//! waiting once per chip.
+use std::thread;
+use std::time::Duration;
+
+use super::command::{Command, DeviceInformation, Reading, ScaleState};
+use super::errors::{ResultExt, RtdError, RtdErrorKind};
+use super::protocol::{collect_dyn, CommandReply, Issued};
+use super::response::Temperature;
+use super::sensor::{RtdSensor, WAKE_DELAY};
+use super::transport::{self, Transport};
+
+/// Reads the temperature of every sensor, in their current scales.
+///
+/// The scales are queried, and the readings taken, in two pipelined rounds,
+/// so that the time taken hardly grows with the number of sensors. The
+/// results are in the same order as `sensors`; a failure of one sensor does
+/// not affect the others.
+pub fn read_all(sensors: &mut [RtdSensor]) -> Vec<Result<Temperature, RtdError>> {
+    with_members(sensors, read_members)
+}
+
+/// Runs a command on every sensor, issuing it to all of them before
+/// collecting the responses.
+///
+/// `cmd` is called once per sensor to build the command. The results are
+/// in the same order as `sensors`. A sensor that was asleep, and could not
+/// be woken up, yields an error of kind `RtdErrorKind::Asleep`, and stays
+/// asleep.
+pub fn run_all<C, F>(sensors: &mut [RtdSensor], cmd: F) -> Vec<Result<C::Response, RtdError>>
+where
+    C: CommandReply,
+    F: FnMut() -> C,
+{
+    with_members(sensors, |members| run_members(members, cmd))
+}
+
+/// A sensor taking part in a round, seen through its device, so that the
+/// rounds run on any `Transport`.
+struct Member<'a> {
+    dev: &'a mut dyn Transport,
+    asleep: bool,
+}
+
+// Runs a round on the devices of the sensors, then records which sensors
+// were woken up.
+fn with_members<T, F>(sensors: &mut [RtdSensor], round: F) -> T
+where
+    F: FnOnce(&mut [Member<'_>]) -> T,
+{
+    let (result, awake) = {
+        let mut members: Vec<_> = sensors
+            .iter_mut()
+            .map(|sensor| Member {
+                asleep: sensor.is_asleep(),
+                dev: sensor.device(),
+            })
+            .collect();
+        let result = round(&mut members);
+        let awake: Vec<_> = members.iter().map(|m| !m.asleep).collect();
+        (result, awake)
+    };
+    for (sensor, awake) in sensors.iter_mut().zip(awake) {
+        if awake {
+            sensor.set_awake();
+        }
+    }
+    result
+}
+
+fn read_members(members: &mut [Member<'_>]) -> Vec<Result<Temperature, RtdError>> {
+    let scales = run_members(members, || ScaleState);
+    let mut pending = Vec::with_capacity(members.len());
+    for (member, scale) in members.iter_mut().zip(scales) {
+        pending.push(scale.and_then(|scale| issue(&mut *member.dev, Reading).map(|r| (scale, r))));
+    }
+    wait_for(pending.iter().filter_map(|p| p.as_ref().ok()).map(|p| &p.1));
+    members
+        .iter_mut()
+        .zip(pending)
+        .map(|(member, pending)| {
+            let (scale, issued) = pending?;
+            let reading = collect_named(&mut *member.dev, issued)?;
+            Ok(reading.with_scale(scale))
+        })
+        .collect()
+}
+
+fn run_members<C, F>(members: &mut [Member<'_>], mut cmd: F) -> Vec<Result<C::Response, RtdError>>
+where
+    C: CommandReply,
+    F: FnMut() -> C,
+{
+    let woken = wake_all(members);
+    let issued: Vec<_> = members
+        .iter_mut()
+        .zip(woken)
+        .map(|(member, woken)| {
+            let cmd = cmd();
+            woken.and_then(|()| issue(&mut *member.dev, cmd))
+        })
+        .collect();
+    wait_for(issued.iter().filter_map(|i| i.as_ref().ok()));
+    members
+        .iter_mut()
+        .zip(issued)
+        .map(|(member, issued)| collect_named(&mut *member.dev, issued?))
+        .collect()
+}
+
+fn issue<C: CommandReply>(dev: &mut dyn Transport, cmd: C) -> Result<Issued<C>, RtdError> {
+    let cmd_str = cmd.get_command_string();
+    cmd.issue_dyn(dev)
+        .context(RtdErrorKind::Command)
+        .map_err(|err| err.with_command(cmd_str))
+}
+
+// Collects the reply of a command, naming the command if it fails.
+fn collect_named<C: CommandReply>(
+    dev: &mut dyn Transport,
+    issued: Issued<C>,
+) -> Result<C::Response, RtdError> {
+    let cmd_str = issued.command().get_command_string();
+    collect_dyn(dev, issued)
+        .context(RtdErrorKind::Command)
+        .map_err(|err| err.with_command(cmd_str))
+}
+
+/// Sleeps until every issued command is ready to be collected.
+fn wait_for<'a, C: 'a, I>(issued: I)
+where
+    I: Iterator<Item = &'a Issued<C>>,
+{
+    if let Some(wait) = issued.map(Issued::remaining).max() {
+        thread::sleep(wait);
+    }
+}
+
+/// Wakes the sleeping sensors, with a single wait for all of them.
+///
+/// A sensor whose wake-up command cannot be written stays asleep, and gets
+/// an error of kind `RtdErrorKind::Asleep` instead of the command.
+fn wake_all(members: &mut [Member<'_>]) -> Vec<Result<(), RtdError>> {
+    let mut woken = false;
+    let mut results = Vec::with_capacity(members.len());
+    for member in members.iter_mut() {
+        let result = if member.asleep {
+            transport::write_command(&mut *member.dev, &DeviceInformation)
+                .context(RtdErrorKind::Asleep)
+        } else {
+            Ok(())
+        };
+        if member.asleep && result.is_ok() {
+            member.asleep = false;
+            woken = true;
+        }
+        results.push(result);
+    }
+    if woken {
+        thread::sleep(Duration::from_millis(WAKE_DELAY));
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::LedOn;
+    use crate::response::{ResponseStatus, TemperatureScale};
+    use crate::{ErrorKind, EzoError};
+    use std::collections::VecDeque;
+    use std::time::Instant;
+
+    /// A device that answers each read with the next scripted reply, and
+    /// fails every write once unplugged.
+    #[derive(Default)]
+    struct Scripted {
+        written: Vec<Vec<u8>>,
+        replies: VecDeque<&'static [u8]>,
+        unplugged: bool,
+    }
+
+    impl Scripted {
+        fn replying(replies: &[&'static [u8]]) -> Scripted {
+            Scripted {
+                replies: replies.iter().copied().collect(),
+                ..Scripted::default()
+            }
+        }
+
+        fn unplugged() -> Scripted {
+            Scripted {
+                unplugged: true,
+                ..Scripted::default()
+            }
+        }
+    }
+
+    impl Transport for Scripted {
+        fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), EzoError> {
+            if self.unplugged {
+                return Err(ErrorKind::UnwritableCommand.into());
+            }
+            self.written.push(bytes.to_vec());
+            Ok(())
+        }
+
+        fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(), EzoError> {
+            let reply = self.replies.pop_front().ok_or(ErrorKind::I2CRead)?;
+            buf[..reply.len()].copy_from_slice(reply);
+            Ok(())
+        }
+    }
+
+    fn members(devices: &mut [Scripted]) -> Vec<Member<'_>> {
+        devices
+            .iter_mut()
+            .map(|dev| Member { dev, asleep: false })
+            .collect()
+    }
+
+    #[test]
+    fn reading_no_sensors_yields_nothing() {
+        assert!(read_all(&mut []).is_empty());
+        assert!(run_all(&mut [], || ScaleState).is_empty());
+    }
+
+    #[test]
+    fn sensors_share_a_single_wait() {
+        let mut devices = [
+            Scripted::replying(&[b"\x01?S,C\0"]),
+            Scripted::replying(&[b"\x01?S,K\0"]),
+            Scripted::replying(&[b"\x01?S,F\0"]),
+        ];
+
+        // `S,?` takes 300 ms, so waiting once per sensor would take 900 ms.
+        let started = Instant::now();
+        let scales = run_members(&mut members(&mut devices), || ScaleState);
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(300));
+        assert!(elapsed < Duration::from_millis(600));
+
+        let scales: Vec<_> = scales.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            scales,
+            vec![
+                TemperatureScale::Celsius,
+                TemperatureScale::Kelvin,
+                TemperatureScale::Fahrenheit,
+            ]
+        );
+        for dev in devices.iter() {
+            assert_eq!(dev.written, vec![b"S,?\0".to_vec()]);
+        }
+    }
+
+    #[test]
+    fn failing_sensors_do_not_affect_the_others() {
+        let mut devices = [
+            Scripted::replying(&[b"\x01?S,F\0", b"\x0198.6\0"]),
+            Scripted::unplugged(),
+            Scripted::replying(&[b"\x01?S,C\0", b"\x02\0"]),
+            Scripted::replying(&[b"\x01?S,C\0", b"\x0121.5\0"]),
+        ];
+
+        let readings = read_members(&mut members(&mut devices));
+        assert_eq!(readings.len(), 4);
+        let mut readings = readings.into_iter();
+
+        assert_eq!(
+            readings.next().unwrap().unwrap(),
+            Temperature::Fahrenheit(98.6)
+        );
+        let err = readings.next().unwrap().err().unwrap();
+        assert_eq!(err.kind(), RtdErrorKind::Command);
+        assert_eq!(err.command(), Some("S,?"));
+        let err = readings.next().unwrap().err().unwrap();
+        assert_eq!(err.command(), Some("R"));
+        assert_eq!(
+            readings.next().unwrap().unwrap(),
+            Temperature::Celsius(21.5)
+        );
+
+        assert!(devices[1].written.is_empty());
+        assert_eq!(devices[3].written, vec![b"S,?\0".to_vec(), b"R\0".to_vec()]);
+    }
+
+    #[test]
+    fn sensors_that_cannot_be_woken_stay_asleep() {
+        let mut devices = [Scripted::replying(&[&[1, 0]]), Scripted::unplugged()];
+
+        {
+            let mut members = members(&mut devices);
+            members[0].asleep = true;
+            members[1].asleep = true;
+
+            let acks = run_members(&mut members, || LedOn);
+            assert_eq!(acks[0].as_ref().unwrap(), &ResponseStatus::Ack);
+            assert_eq!(acks[1].as_ref().err().unwrap().kind(), RtdErrorKind::Asleep);
+            assert!(!members[0].asleep);
+            assert!(members[1].asleep);
+        }
+
+        assert_eq!(devices[0].written, vec![b"I\0".to_vec(), b"L,1\0".to_vec()]);
+    }
+}
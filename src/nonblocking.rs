@@ -0,0 +1,253 @@
+//! Async counterpart of `Command::run`, independent of the async runtime.
+//!
+//! The I2C reads and writes are blocking, so they are handed to the
+//! runtime's blocking thread pool; the delay between them is awaited, which
+//! leaves the runtime free in the meantime. Both are provided by a `Runtime`,
+//! with implementations for Tokio and async-std behind the `tokio` and
+//! `async-std` features.
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::errors::Transient;
+use super::protocol::{name_rejected, read_reply, write_command, CommandReply, Reply};
+use super::{ErrorKind, EzoError};
+
+use i2cdev::linux::LinuxI2CDevice;
+
+/// Delay and blocking-task provider of an async runtime.
+pub trait Runtime: Clone + Send + Sync + 'static {
+    /// Waits for the given duration without blocking the runtime.
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send;
+
+    /// Runs `f` where it may block, returning `None` if it did not complete.
+    fn spawn_blocking<F, T>(&self, f: F) -> impl Future<Output = Option<T>> + Send
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static;
+}
+
+/// The Tokio runtime.
+#[cfg(feature = "tokio")]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tokio;
+
+#[cfg(feature = "tokio")]
+impl Runtime for Tokio {
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        tokio::time::sleep(duration)
+    }
+
+    fn spawn_blocking<F, T>(&self, f: F) -> impl Future<Output = Option<T>> + Send
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let task = tokio::task::spawn_blocking(f);
+        async move { task.await.ok() }
+    }
+}
+
+/// The async-std runtime.
+#[cfg(feature = "async-std")]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AsyncStd;
+
+#[cfg(feature = "async-std")]
+impl Runtime for AsyncStd {
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        async_std::task::sleep(duration)
+    }
+
+    fn spawn_blocking<F, T>(&self, f: F) -> impl Future<Output = Option<T>> + Send
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let task = async_std::task::spawn_blocking(f);
+        async move { Some(task.await) }
+    }
+}
+
+/// An I2C device that can be shared between tasks of the runtime `R`.
+#[derive(Clone)]
+pub struct AsyncDevice<R: Runtime> {
+    dev: Arc<Mutex<LinuxI2CDevice>>,
+    runtime: R,
+}
+
+impl<R: Runtime> AsyncDevice<R> {
+    /// Wraps an open I2C device, to be used with the given runtime.
+    pub fn new(dev: LinuxI2CDevice, runtime: R) -> AsyncDevice<R> {
+        AsyncDevice {
+            dev: Arc::new(Mutex::new(dev)),
+            runtime,
+        }
+    }
+
+    /// Returns the runtime used by the device.
+    pub fn runtime(&self) -> &R {
+        &self.runtime
+    }
+
+    /// Writes the command, as `write_command()` does, awaits its delay, then
+    /// reads and parses the reply.
+    pub async fn run<C: CommandReply>(&self, cmd: &C) -> Result<C::Response, EzoError> {
+        let cmd_str = cmd.get_command_string();
+        let reply = cmd.reply();
+
+        self.blocking(move |dev| write_command(dev, &cmd_str))
+            .await?;
+
+        if reply == Reply::None {
+            return cmd.parse_reply("");
+        }
+
+        self.runtime
+            .sleep(Duration::from_millis(cmd.get_delay()))
+            .await;
+
+        let data = self
+            .blocking(move |dev| read_reply(dev, reply))
+            .await
+            .map_err(|err| name_rejected(err, cmd))?;
+
+        cmd.parse_reply(&data)
+    }
+
+    /// Runs the command, retrying with exponential backoff while it fails
+    /// with a pending reply or a transient I2C error, until the deadline
+    /// of `backoff` is reached.
+    ///
+    /// Other errors, and the last error once the deadline is reached, are
+    /// returned as is.
+    pub async fn retry_with_backoff<C: CommandReply>(
+        &self,
+        cmd: &C,
+        backoff: &Backoff,
+    ) -> Result<C::Response, EzoError> {
+        let deadline = Instant::now() + backoff.deadline;
+        let mut delay = backoff.initial;
+        loop {
+            match self.run(cmd).await {
+                Err(ref e) if e.is_transient() && Instant::now() + delay < deadline => {
+                    self.runtime.sleep(delay).await;
+                    delay = backoff.next_delay(delay);
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Runs `f` on the device from the runtime's blocking thread pool.
+    async fn blocking<F, T>(&self, f: F) -> Result<T, EzoError>
+    where
+        F: FnOnce(&mut LinuxI2CDevice) -> Result<T, EzoError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let dev = Arc::clone(&self.dev);
+        let task = self.runtime.spawn_blocking(move || {
+            let mut dev = dev.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut dev)
+        });
+        match task.await {
+            Some(res) => res,
+            None => Err(ErrorKind::I2CRead.into()),
+        }
+    }
+}
+
+impl<R: Runtime + Default> From<LinuxI2CDevice> for AsyncDevice<R> {
+    fn from(dev: LinuxI2CDevice) -> AsyncDevice<R> {
+        AsyncDevice::new(dev, R::default())
+    }
+}
+
+/// Exponential backoff used by `AsyncDevice::retry_with_backoff`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Backoff {
+    /// Wait before the first retry.
+    pub initial: Duration,
+    /// Longest wait between two attempts.
+    pub max_delay: Duration,
+    /// Time after which no more attempts are made, counted from the first.
+    pub deadline: Duration,
+}
+
+impl Backoff {
+    /// Doubles the wait after each retry, starting at `initial`, until
+    /// `deadline`.
+    pub fn new(initial: Duration, deadline: Duration) -> Backoff {
+        Backoff {
+            initial,
+            max_delay: deadline,
+            deadline,
+        }
+    }
+
+    /// Caps the wait between two attempts.
+    pub fn max_delay(mut self, max_delay: Duration) -> Backoff {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Returns the wait that follows `delay`.
+    pub fn next_delay(&self, delay: Duration) -> Duration {
+        (delay * 2).min(self.max_delay)
+    }
+}
+
+impl Default for Backoff {
+    /// Starts at 100 ms, waits at most 2 s between attempts, and gives up
+    /// after 10 s.
+    fn default() -> Backoff {
+        Backoff::new(Duration::from_millis(100), Duration::from_secs(10))
+            .max_delay(Duration::from_secs(2))
+    }
+}
+
+/// Async counterpart of `Command`, with the same typed responses.
+pub trait AsyncCommand: CommandReply {
+    /// Runs the command on the device without blocking the runtime.
+    fn run_async<R: Runtime>(
+        &self,
+        dev: &AsyncDevice<R>,
+    ) -> impl Future<Output = Result<Self::Response, EzoError>> + Send;
+}
+
+impl<C> AsyncCommand for C
+where
+    C: CommandReply + Sync,
+    C::Response: Send,
+{
+    fn run_async<R: Runtime>(
+        &self,
+        dev: &AsyncDevice<R>,
+    ) -> impl Future<Output = Result<Self::Response, EzoError>> + Send {
+        dev.run(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_max_delay() {
+        let backoff = Backoff::default();
+        let delay = backoff.next_delay(backoff.initial);
+        assert_eq!(delay, Duration::from_millis(200));
+        assert_eq!(
+            backoff.next_delay(Duration::from_millis(1500)),
+            Duration::from_secs(2)
+        );
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        assert!(EzoError::from(ErrorKind::PendingResponse).is_transient());
+        assert!(EzoError::from(ErrorKind::I2CRead).is_transient());
+        assert!(!EzoError::from(ErrorKind::DeviceErrorResponse).is_transient());
+        assert!(!EzoError::from(ErrorKind::ResponseParse).is_transient());
+    }
+}
@@ -0,0 +1,1113 @@
+//! The I2C transaction of a command, split into its steps.
+//!
+//! `Command::run` writes the command, sleeps, reads the reply, and parses
+//! it, all in one blocking call. The pieces here allow other runners, such
+//! as the async ones, to perform each step on their own terms.
+use std::error::Error as StdError;
+use std::ffi::CString;
+use std::fmt;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::command::{
+    Baud, CalibrationClear, CalibrationState, CalibrationTemperature, Command, DataloggerDisable,
+    DataloggerInterval, DataloggerPeriod, DeviceAddress, DeviceInformation, Export, ExportInfo,
+    Factory, Find, Import, LedOff, LedOn, LedState, MemoryClear, MemoryRecall, MemoryRecallLast,
+    Name, NameState, ProtocolLockDisable, ProtocolLockEnable, ProtocolLockState, Reading,
+    ScaleCelsius, ScaleFahrenheit, ScaleKelvin, ScaleState, Sleep, Status,
+};
+use super::response::{
+    CalibrationStatus, DataLoggerStorageIntervalSeconds, DeviceInfo, DeviceName, DeviceStatus,
+    Exported, ExportedInfo, LedStatus, MemoryReading, ProtocolLockStatus, ResponseStatus,
+    SensorReading, TemperatureScale,
+};
+use super::transport::{self, Transport};
+use super::{ErrorKind, EzoError};
+
+use ezo_common::{response_code, string_from_response_data, ResponseCode};
+
+use failure::{Fail, ResultExt};
+
+use i2cdev::core::I2CDevice;
+use i2cdev::linux::LinuxI2CError;
+
+use nix::errno::Errno;
+
+// `embedded-hal-async` re-exports the error kinds of `embedded-hal`.
+#[cfg(feature = "embedded-hal")]
+use embedded_hal::i2c::{ErrorKind as HalErrorKind, NoAcknowledgeSource};
+#[cfg(all(feature = "embedded-hal-async", not(feature = "embedded-hal")))]
+use embedded_hal_async::i2c::{ErrorKind as HalErrorKind, NoAcknowledgeSource};
+
+/// Size of the buffer used to read replies, large enough for any reply.
+pub const MAX_REPLY: usize = 42;
+
+/// Size of the buffer used to write commands, large enough for any command
+/// string and its NUL terminator.
+pub const MAX_COMMAND: usize = 32;
+
+/// Milliseconds to wait before retrying a failed write.
+pub const WRITE_RETRY_DELAY: u64 = 100;
+
+/// Times a transfer is retried at once when its system call was interrupted
+/// by a signal, `EINTR`, or would block, `EAGAIN`.
+pub const INTERRUPTED_RETRIES: u32 = 3;
+
+/// What the chip sends back after a command.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Reply {
+    /// Nothing is read back, e.g. after `SLEEP`.
+    None,
+    /// Only a response code is read back.
+    Ack,
+    /// A response code followed by a string of data.
+    Data,
+}
+
+/// Why an I2C transfer failed, when the bus driver tells.
+///
+/// `ErrorKind` already tells a failed write, `UnwritableCommand`, from a
+/// failed read, `I2CRead`; an `I2cFault` kept as the cause of either tells
+/// a problem of the bus, such as noise or another controller, from one of
+/// the chip, such as a wrong address or a chip that is asleep. It is found
+/// with `i2c_fault()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum I2cFault {
+    /// A bus error, e.g. a misplaced start or stop condition.
+    Bus,
+    /// Another controller took over the bus.
+    ArbitrationLoss,
+    /// No chip acknowledged the address.
+    AddressNack,
+    /// The chip did not acknowledge a data byte.
+    DataNack,
+    /// The chip did not acknowledge, at an unknown point of the transfer.
+    Nack,
+    /// Data came in faster than it could be read.
+    Overrun,
+    /// Any other failure.
+    Other,
+}
+
+impl I2cFault {
+    /// Returns `true` for faults of the bus itself, which point to wiring,
+    /// noise, or other controllers rather than to the chip.
+    pub fn is_bus_level(&self) -> bool {
+        matches!(
+            *self,
+            I2cFault::Bus | I2cFault::ArbitrationLoss | I2cFault::Overrun
+        )
+    }
+
+    /// Returns `true` for faults of the chip, which did not acknowledge the
+    /// transfer.
+    pub fn is_device_level(&self) -> bool {
+        matches!(
+            *self,
+            I2cFault::AddressNack | I2cFault::DataNack | I2cFault::Nack
+        )
+    }
+}
+
+impl fmt::Display for I2cFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fault = match *self {
+            I2cFault::Bus => "bus error",
+            I2cFault::ArbitrationLoss => "arbitration lost",
+            I2cFault::AddressNack => "address not acknowledged",
+            I2cFault::DataNack => "data not acknowledged",
+            I2cFault::Nack => "transfer not acknowledged",
+            I2cFault::Overrun => "data overrun",
+            I2cFault::Other => "I2C transfer failed",
+        };
+        write!(f, "{}", fault)
+    }
+}
+
+impl StdError for I2cFault {}
+
+/// Tells why an `embedded-hal` or `embedded-hal-async` bus failed, from the
+/// kind of its error.
+#[cfg(any(feature = "embedded-hal", feature = "embedded-hal-async"))]
+impl From<HalErrorKind> for I2cFault {
+    fn from(kind: HalErrorKind) -> I2cFault {
+        match kind {
+            HalErrorKind::Bus => I2cFault::Bus,
+            HalErrorKind::ArbitrationLoss => I2cFault::ArbitrationLoss,
+            HalErrorKind::NoAcknowledge(NoAcknowledgeSource::Address) => I2cFault::AddressNack,
+            HalErrorKind::NoAcknowledge(NoAcknowledgeSource::Data) => I2cFault::DataNack,
+            HalErrorKind::NoAcknowledge(_) => I2cFault::Nack,
+            HalErrorKind::Overrun => I2cFault::Overrun,
+            _ => I2cFault::Other,
+        }
+    }
+}
+
+/// Returns the `I2cFault` behind an error, if the bus driver told why the
+/// transfer failed.
+pub fn i2c_fault(err: &EzoError) -> Option<I2cFault> {
+    let err: &dyn Fail = err;
+    err.iter_causes()
+        .filter_map(|cause| cause.downcast_ref::<I2cFault>())
+        .copied()
+        .next()
+}
+
+// Wraps the error of a failed I2C transfer in `kind`. For a
+// `LinuxI2CDevice`, the `I2cFault` told by the errno of the transfer is
+// kept as the cause instead, so that `i2c_fault()` finds it.
+pub(crate) fn transfer_error<E>(err: E, kind: ErrorKind) -> EzoError
+where
+    E: StdError + Send + Sync + 'static,
+{
+    match linux_fault(&err) {
+        Some(fault) => Err::<(), _>(fault).context(kind).unwrap_err().into(),
+        None => Err::<(), _>(err).context(kind).unwrap_err().into(),
+    }
+}
+
+// Tells the `I2cFault` behind the errno of a failed transfer on a
+// `LinuxI2CDevice`, as the kernel bus drivers report them: `ENXIO` for an
+// address that no chip acknowledged, `EREMOTEIO`, or `EIO` for some
+// drivers, for any other missing acknowledge, and `EAGAIN` for a lost
+// arbitration.
+fn linux_fault(err: &(dyn StdError + 'static)) -> Option<I2cFault> {
+    let errno = match *err.downcast_ref::<LinuxI2CError>()? {
+        LinuxI2CError::Nix(nix::Error::Sys(errno)) => errno,
+        LinuxI2CError::Io(ref err) => Errno::from_i32(err.raw_os_error()?),
+        _ => return None,
+    };
+    match errno {
+        Errno::ENXIO => Some(I2cFault::AddressNack),
+        Errno::EREMOTEIO | Errno::EIO => Some(I2cFault::Nack),
+        Errno::EAGAIN => Some(I2cFault::ArbitrationLoss),
+        _ => None,
+    }
+}
+
+/// The command string of a command that the chip refused with a device
+/// error, i.e. response code 2, e.g. because it does not parse.
+///
+/// It is kept as the cause of the `ErrorKind::DeviceErrorResponse` error
+/// returned by the runners of this crate, so that a malformed command,
+/// such as one with a float formatted for another locale, is visible. It is
+/// found with `rejected_command()`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RejectedCommand(pub String);
+
+impl fmt::Display for RejectedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the chip rejected `{}`", self.0)
+    }
+}
+
+impl StdError for RejectedCommand {}
+
+/// Returns the command string that the chip refused, if the error is a
+/// device error that kept it.
+pub fn rejected_command(err: &EzoError) -> Option<&str> {
+    let err: &dyn Fail = err;
+    err.iter_causes()
+        .filter_map(|cause| cause.downcast_ref::<RejectedCommand>())
+        .map(|rejected| rejected.0.as_str())
+        .next()
+}
+
+// Keeps the command string of `cmd` as the cause of a device error; other
+// errors are returned as they are.
+pub(crate) fn name_rejected<C: Command>(err: EzoError, cmd: &C) -> EzoError {
+    if err.kind() != ErrorKind::DeviceErrorResponse {
+        return err;
+    }
+    let rejected = RejectedCommand(cmd.get_command_string());
+    Err::<(), _>(rejected)
+        .context(ErrorKind::DeviceErrorResponse)
+        .unwrap_err()
+        .into()
+}
+
+/// A command that can write its command string without allocating.
+pub trait WriteCommand: Command {
+    /// Writes the command string into `w`.
+    ///
+    /// The default goes through `get_command_string()`, which allocates;
+    /// the commands defined by this crate, and the shared commands without
+    /// an argument, write their string directly.
+    fn write_command<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_str(&self.get_command_string())
+    }
+}
+
+/// A command whose reply can be read and parsed separately from `run()`.
+pub trait CommandReply: Command<Error = EzoError> + WriteCommand {
+    /// Kind of reply sent back by the chip.
+    fn reply(&self) -> Reply;
+
+    /// Builds the response from the data string read back from the chip,
+    /// which is empty unless `reply()` is `Reply::Data`.
+    fn parse_reply(&self, data: &str) -> Result<Self::Response, EzoError>;
+
+    /// Writes the command to the device without waiting for its reply.
+    ///
+    /// The returned token tells how long the chip needs before the reply
+    /// can be read with `collect()`; the caller is free to do other work
+    /// in the meantime.
+    fn issue<D>(self, dev: &mut D) -> Result<Issued<Self>, EzoError>
+    where
+        Self: Sized,
+        D: I2CDevice,
+        D::Error: Send + Sync + 'static,
+    {
+        write_encoded(dev, &self)?;
+        Ok(Issued::now(self))
+    }
+
+    /// Writes the command to a type-erased device without waiting for its
+    /// reply, as `issue()` does.
+    fn issue_dyn(self, dev: &mut dyn Transport) -> Result<Issued<Self>, EzoError>
+    where
+        Self: Sized,
+    {
+        transport::write_command(dev, &self)?;
+        Ok(Issued::now(self))
+    }
+
+    /// Writes the command, then polls for its reply instead of sleeping
+    /// for the full delay of the command.
+    fn run_polled<D>(&self, dev: &mut D, polling: &Polling) -> Result<Self::Response, EzoError>
+    where
+        D: I2CDevice,
+        D::Error: Send + Sync + 'static,
+    {
+        write_encoded(dev, self)?;
+        let data =
+            poll_reply(dev, self.reply(), polling).map_err(|err| name_rejected(err, self))?;
+        self.parse_reply(&data)
+    }
+}
+
+/// A command that runs on any I2C device, not only on a `LinuxI2CDevice`,
+/// e.g. on a mock device in tests, or on another backend.
+pub trait RunOn: Command<Error = EzoError> {
+    /// Runs the command on `dev`, as `Command::run` does on a
+    /// `LinuxI2CDevice`.
+    fn run_on<D>(&self, dev: &mut D) -> Result<Self::Response, EzoError>
+    where
+        D: I2CDevice,
+        D::Error: Send + Sync + 'static;
+
+    /// Runs the command on a type-erased device, e.g. one of several kept
+    /// in a `Vec<Box<dyn Transport>>`.
+    fn run_dyn(&self, dev: &mut dyn Transport) -> Result<Self::Response, EzoError>;
+}
+
+impl<C: CommandReply> RunOn for C {
+    fn run_on<D>(&self, dev: &mut D) -> Result<C::Response, EzoError>
+    where
+        D: I2CDevice,
+        D::Error: Send + Sync + 'static,
+    {
+        write_encoded(dev, self)?;
+        let reply = self.reply();
+        if reply != Reply::None {
+            thread::sleep(Duration::from_millis(self.get_delay()));
+        }
+        let data = read_reply(dev, reply).map_err(|err| name_rejected(err, self))?;
+        self.parse_reply(&data)
+    }
+
+    fn run_dyn(&self, dev: &mut dyn Transport) -> Result<C::Response, EzoError> {
+        transport::write_command(dev, self)?;
+        let reply = self.reply();
+        if reply != Reply::None {
+            thread::sleep(Duration::from_millis(self.get_delay()));
+        }
+        let data = transport::read_reply(dev, reply).map_err(|err| name_rejected(err, self))?;
+        self.parse_reply(&data)
+    }
+}
+
+/// How to poll for a reply that is still pending.
+///
+/// The chip answers with a pending response code while it is processing a
+/// command, so instead of sleeping for the worst-case delay of the command,
+/// the reply can be read every `interval` until it is ready, or until
+/// `timeout` has elapsed since the command was written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Polling {
+    /// Time to wait before each read.
+    pub interval: Duration,
+    /// Time after which a pending reply is returned as an error.
+    pub timeout: Duration,
+}
+
+impl Polling {
+    /// Polls every `interval` until `timeout`.
+    pub fn new(interval: Duration, timeout: Duration) -> Polling {
+        Polling { interval, timeout }
+    }
+}
+
+impl Default for Polling {
+    /// Polls every 50 ms, for up to one second.
+    fn default() -> Polling {
+        Polling::new(Duration::from_millis(50), Duration::from_millis(1000))
+    }
+}
+
+/// A command written to the chip, whose reply is yet to be collected.
+#[derive(Debug)]
+pub struct Issued<C> {
+    cmd: C,
+    issued_at: Instant,
+    delay: Duration,
+}
+
+impl<C: CommandReply> Issued<C> {
+    // Records that `cmd` was written just now.
+    fn now(cmd: C) -> Issued<C> {
+        let delay = match cmd.reply() {
+            Reply::None => Duration::from_millis(0),
+            _ => Duration::from_millis(cmd.get_delay()),
+        };
+        Issued {
+            cmd,
+            issued_at: Instant::now(),
+            delay,
+        }
+    }
+}
+
+impl<C> Issued<C> {
+    /// Returns the command that was issued.
+    pub fn command(&self) -> &C {
+        &self.cmd
+    }
+
+    /// Returns the time the chip needs to process the command.
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Returns the instant after which the reply can be collected.
+    pub fn ready_at(&self) -> Instant {
+        self.issued_at + self.delay
+    }
+
+    /// Returns the time left until the reply can be collected.
+    pub fn remaining(&self) -> Duration {
+        self.ready_at().saturating_duration_since(Instant::now())
+    }
+
+    /// Returns `true` once the delay of the command has elapsed.
+    pub fn is_ready(&self) -> bool {
+        Instant::now() >= self.ready_at()
+    }
+}
+
+/// Reads and parses the reply of an issued command.
+///
+/// The reply is read right away; if the delay of the command has not yet
+/// elapsed, the chip may answer with `ErrorKind::PendingResponse`.
+pub fn collect<C, D>(dev: &mut D, issued: Issued<C>) -> Result<C::Response, EzoError>
+where
+    C: CommandReply,
+    D: I2CDevice,
+    D::Error: Send + Sync + 'static,
+{
+    let data =
+        read_reply(dev, issued.cmd.reply()).map_err(|err| name_rejected(err, &issued.cmd))?;
+    issued.cmd.parse_reply(&data)
+}
+
+/// Reads and parses the reply of a command issued to a type-erased device,
+/// as `collect()` does.
+pub fn collect_dyn<C: CommandReply>(
+    dev: &mut dyn Transport,
+    issued: Issued<C>,
+) -> Result<C::Response, EzoError> {
+    let data = transport::read_reply(dev, issued.cmd.reply())
+        .map_err(|err| name_rejected(err, &issued.cmd))?;
+    issued.cmd.parse_reply(&data)
+}
+
+/// Polls for the reply of an issued command, starting right away, and
+/// parses it.
+///
+/// The timeout of `polling` counts from the moment the command was issued.
+pub fn collect_polled<C, D>(
+    dev: &mut D,
+    issued: Issued<C>,
+    polling: &Polling,
+) -> Result<C::Response, EzoError>
+where
+    C: CommandReply,
+    D: I2CDevice,
+    D::Error: Send + Sync + 'static,
+{
+    let deadline = issued.issued_at + polling.timeout;
+    let data = poll_until(dev, issued.cmd.reply(), polling.interval, deadline)
+        .map_err(|err| name_rejected(err, &issued.cmd))?;
+    issued.cmd.parse_reply(&data)
+}
+
+/// Reads a reply of the given kind every `polling.interval` while the chip
+/// answers that it is pending, for up to `polling.timeout`.
+pub fn poll_reply<D>(dev: &mut D, reply: Reply, polling: &Polling) -> Result<String, EzoError>
+where
+    D: I2CDevice,
+    D::Error: Send + Sync + 'static,
+{
+    let deadline = Instant::now() + polling.timeout;
+    poll_until(dev, reply, polling.interval, deadline)
+}
+
+fn poll_until<D>(
+    dev: &mut D,
+    reply: Reply,
+    interval: Duration,
+    deadline: Instant,
+) -> Result<String, EzoError>
+where
+    D: I2CDevice,
+    D::Error: Send + Sync + 'static,
+{
+    if reply == Reply::None {
+        return Ok(String::new());
+    }
+
+    loop {
+        thread::sleep(interval);
+        match read_reply(dev, reply) {
+            Err(ref e) if e.kind() == ErrorKind::PendingResponse && Instant::now() < deadline => {
+                continue
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Runs an I2C transfer, retrying it up to `INTERRUPTED_RETRIES` times
+/// while it fails with `EINTR` or `EAGAIN`.
+///
+/// These errors tell that the system call was cut short, e.g. by a signal
+/// delivered to a long-running sampler, not that the transfer failed, so
+/// they are not worth surfacing.
+pub fn retry_interrupted<T, E, F>(mut transfer: F) -> Result<T, E>
+where
+    F: FnMut() -> Result<T, E>,
+    E: StdError + 'static,
+{
+    let mut retries = 0;
+    loop {
+        match transfer() {
+            Err(ref err) if retries < INTERRUPTED_RETRIES && is_interrupted(err) => retries += 1,
+            res => return res,
+        }
+    }
+}
+
+// Tells whether the error of an I2C transfer is `EINTR` or `EAGAIN`, from
+// the ioctl of a `LinuxI2CDevice`, or from any device that reports errors
+// as `io::Error`.
+fn is_interrupted(err: &(dyn StdError + 'static)) -> bool {
+    if let Some(err) = err.downcast_ref::<LinuxI2CError>() {
+        return match *err {
+            LinuxI2CError::Nix(nix::Error::Sys(errno)) => {
+                errno == Errno::EINTR || errno == Errno::EAGAIN
+            }
+            LinuxI2CError::Io(ref err) => is_interrupted(err),
+            _ => false,
+        };
+    }
+    match err.downcast_ref::<io::Error>() {
+        Some(err) => matches!(
+            err.kind(),
+            io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+        ),
+        None => false,
+    }
+}
+
+/// Returns the bytes written to the chip for a command string, which are
+/// the string itself followed by a NUL byte.
+pub fn command_bytes(cmd_str: &str) -> Result<Vec<u8>, EzoError> {
+    let cmd = CString::new(cmd_str).context(ErrorKind::UnreadableCommand)?;
+    Ok(cmd.into_bytes_with_nul())
+}
+
+/// Writes a command string to the device, followed by a NUL byte.
+///
+/// A failed write is retried once, after `WRITE_RETRY_DELAY` milliseconds.
+/// Interrupted writes are retried at once, as `retry_interrupted()` does.
+pub fn write_command<D>(dev: &mut D, cmd_str: &str) -> Result<(), EzoError>
+where
+    D: I2CDevice,
+    D::Error: Send + Sync + 'static,
+{
+    let bytes = command_bytes(cmd_str)?;
+    write_bytes(dev, &bytes)
+}
+
+/// Writes the command string of `cmd` to the device, followed by a NUL
+/// byte, as `write_command()` does.
+///
+/// The string is encoded on the stack, with `encode_command()`, so nothing
+/// is allocated for the commands defined by this crate.
+pub fn write_encoded<C, D>(dev: &mut D, cmd: &C) -> Result<(), EzoError>
+where
+    C: WriteCommand,
+    D: I2CDevice,
+    D::Error: Send + Sync + 'static,
+{
+    let mut cmd_buffer = [0u8; MAX_COMMAND];
+    let len = encode_command(cmd, &mut cmd_buffer)?;
+    write_bytes(dev, &cmd_buffer[..len])
+}
+
+// Writes the bytes of a command, retrying once after `WRITE_RETRY_DELAY`.
+fn write_bytes<D>(dev: &mut D, bytes: &[u8]) -> Result<(), EzoError>
+where
+    D: I2CDevice,
+    D::Error: Send + Sync + 'static,
+{
+    if retry_interrupted(|| dev.write(bytes)).is_err() {
+        thread::sleep(Duration::from_millis(WRITE_RETRY_DELAY));
+        retry_interrupted(|| dev.write(bytes))
+            .map_err(|err| transfer_error(err, ErrorKind::UnwritableCommand))?;
+    }
+    Ok(())
+}
+
+/// Writes the bytes of a command string, followed by a NUL byte, into
+/// `buf`, returning how many bytes were written.
+///
+/// Unlike `command_bytes()`, nothing is allocated, which suits embedded
+/// targets and hot loops.
+pub fn write_command_bytes(cmd_str: &str, buf: &mut [u8]) -> Result<usize, EzoError> {
+    let bytes = cmd_str.as_bytes();
+    if bytes.contains(&0) || bytes.len() >= buf.len() {
+        return Err(ErrorKind::UnreadableCommand.into());
+    }
+    buf[..bytes.len()].copy_from_slice(bytes);
+    buf[bytes.len()] = 0;
+    Ok(bytes.len() + 1)
+}
+
+/// Writes the command string of `cmd`, followed by a NUL byte, into `buf`,
+/// returning how many bytes were written.
+///
+/// Nothing is allocated for the commands defined by this crate.
+pub fn encode_command<C: WriteCommand>(cmd: &C, buf: &mut [u8]) -> Result<usize, EzoError> {
+    let len = {
+        let mut writer = SliceWriter {
+            buf: &mut *buf,
+            len: 0,
+        };
+        cmd.write_command(&mut writer)
+            .map_err(|_| EzoError::from(ErrorKind::UnreadableCommand))?;
+        writer.len
+    };
+    if buf[..len].contains(&0) || len >= buf.len() {
+        return Err(ErrorKind::UnreadableCommand.into());
+    }
+    buf[len] = 0;
+    Ok(len + 1)
+}
+
+/// Returns the command string of `cmd` in a `heapless::String`, which holds
+/// up to `N` bytes, without allocating.
+#[cfg(feature = "heapless")]
+pub fn command_string<C: WriteCommand, const N: usize>(
+    cmd: &C,
+) -> Result<heapless::String<N>, EzoError> {
+    let mut cmd_str = heapless::String::new();
+    cmd.write_command(&mut cmd_str)
+        .map_err(|_| EzoError::from(ErrorKind::UnreadableCommand))?;
+    Ok(cmd_str)
+}
+
+/// `fmt::Write` into a byte slice, failing once it is full.
+struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> fmt::Write for SliceWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.buf.len() {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Reads a reply of the given kind from the device, returning its data
+/// string, which is empty for `Reply::Ack`.
+///
+/// The response code is checked, as `Command::run` does. Interrupted reads
+/// are retried, as `retry_interrupted()` does.
+pub fn read_reply<D>(dev: &mut D, reply: Reply) -> Result<String, EzoError>
+where
+    D: I2CDevice,
+    D::Error: Send + Sync + 'static,
+{
+    if reply == Reply::None {
+        return Ok(String::new());
+    }
+
+    let mut data_buffer = [0u8; MAX_REPLY];
+
+    retry_interrupted(|| dev.read(&mut data_buffer))
+        .map_err(|err| transfer_error(err, ErrorKind::I2CRead))?;
+
+    decode_reply(&data_buffer, reply)
+}
+
+/// Checks the response code at the start of a buffer read from the chip,
+/// returning the data string that follows it, which is empty unless `reply`
+/// is `Reply::Data`.
+pub fn decode_reply(data_buffer: &[u8], reply: Reply) -> Result<String, EzoError> {
+    if reply == Reply::None {
+        return Ok(String::new());
+    }
+
+    let code = match data_buffer.first() {
+        Some(&code) => code,
+        None => return Err(ErrorKind::MalformedResponse.into()),
+    };
+
+    check_response_code(code)?;
+
+    let data = &data_buffer[1..];
+    match reply {
+        Reply::Data => {
+            match data.iter().position(|&c| c == 0) {
+                Some(len) => Ok(string_from_response_data(&data[..=len])
+                    .context(ErrorKind::MalformedResponse)?),
+                _ => Err(ErrorKind::MalformedResponse.into()),
+            }
+        }
+        _ => Ok(String::new()),
+    }
+}
+
+/// Checks the response code at the start of a buffer read from the chip,
+/// returning the raw bytes that follow it, up to the NUL byte.
+pub fn decode_raw_reply(data_buffer: &[u8]) -> Result<Vec<u8>, EzoError> {
+    let code = match data_buffer.first() {
+        Some(&code) => code,
+        None => return Err(ErrorKind::MalformedResponse.into()),
+    };
+
+    check_response_code(code)?;
+
+    let data = &data_buffer[1..];
+    match data.iter().position(|&c| c == 0) {
+        Some(len) => Ok(data[..len].to_vec()),
+        None => Err(ErrorKind::MalformedResponse.into()),
+    }
+}
+
+fn check_response_code(code: u8) -> Result<(), EzoError> {
+    match response_code(code) {
+        ResponseCode::Success => Ok(()),
+
+        ResponseCode::Pending => Err(ErrorKind::PendingResponse.into()),
+
+        ResponseCode::DeviceError => Err(ErrorKind::DeviceErrorResponse.into()),
+
+        ResponseCode::NoDataExpected => Err(ErrorKind::NoDataExpectedResponse.into()),
+
+        ResponseCode::UnknownError => Err(ErrorKind::MalformedResponse.into()),
+    }
+}
+
+/// Reads a raw reply from the device, returning the bytes that follow its
+/// response code, up to the NUL byte.
+///
+/// The response code is checked, as `read_reply()` does, but the data is
+/// neither parsed nor required to be ASCII.
+pub fn raw_read<D>(dev: &mut D) -> Result<Vec<u8>, EzoError>
+where
+    D: I2CDevice,
+    D::Error: Send + Sync + 'static,
+{
+    let mut data_buffer = [0u8; MAX_REPLY];
+
+    retry_interrupted(|| dev.read(&mut data_buffer))
+        .map_err(|err| transfer_error(err, ErrorKind::I2CRead))?;
+
+    decode_raw_reply(&data_buffer)
+}
+
+/// Writes any command string to the device, waits for `delay`, then reads
+/// its raw reply, as `raw_read()` does.
+///
+/// This allows issuing commands from the datasheet that this crate does not
+/// define. Commands that get no reply, such as `SLEEP`, should be written
+/// with `write_command()` instead.
+pub fn raw_command<D>(dev: &mut D, cmd_str: &str, delay: Duration) -> Result<Vec<u8>, EzoError>
+where
+    D: I2CDevice,
+    D::Error: Send + Sync + 'static,
+{
+    write_command(dev, cmd_str)?;
+    thread::sleep(delay);
+    raw_read(dev)
+}
+
+macro_rules! impl_command_reply {
+    ($name:ty, None) => {
+        impl CommandReply for $name {
+            fn reply(&self) -> Reply {
+                Reply::None
+            }
+
+            fn parse_reply(&self, _data: &str) -> Result<(), EzoError> {
+                Ok(())
+            }
+        }
+    };
+    ($name:ty, Ack) => {
+        impl CommandReply for $name {
+            fn reply(&self) -> Reply {
+                Reply::Ack
+            }
+
+            fn parse_reply(&self, _data: &str) -> Result<ResponseStatus, EzoError> {
+                Ok(ResponseStatus::Ack)
+            }
+        }
+    };
+    ($name:ty, $response:ident) => {
+        impl CommandReply for $name {
+            fn reply(&self) -> Reply {
+                Reply::Data
+            }
+
+            fn parse_reply(&self, data: &str) -> Result<$response, EzoError> {
+                $response::parse(data)
+            }
+        }
+    };
+}
+
+impl_command_reply!(Baud, None);
+impl_command_reply!(CalibrationClear, Ack);
+impl_command_reply!(CalibrationState, CalibrationStatus);
+impl_command_reply!(CalibrationTemperature, Ack);
+impl_command_reply!(DataloggerDisable, Ack);
+impl_command_reply!(DataloggerInterval, DataLoggerStorageIntervalSeconds);
+impl_command_reply!(DataloggerPeriod, Ack);
+impl_command_reply!(DeviceAddress, None);
+impl_command_reply!(DeviceInformation, DeviceInfo);
+impl_command_reply!(Export, Exported);
+impl_command_reply!(ExportInfo, ExportedInfo);
+impl_command_reply!(Factory, None);
+impl_command_reply!(Find, Ack);
+impl_command_reply!(Import, Ack);
+impl_command_reply!(LedOff, Ack);
+impl_command_reply!(LedOn, Ack);
+impl_command_reply!(LedState, LedStatus);
+impl_command_reply!(MemoryClear, Ack);
+impl_command_reply!(MemoryRecall, MemoryReading);
+impl_command_reply!(MemoryRecallLast, MemoryReading);
+impl_command_reply!(Name, Ack);
+impl_command_reply!(NameState, DeviceName);
+impl_command_reply!(ProtocolLockDisable, Ack);
+impl_command_reply!(ProtocolLockEnable, Ack);
+impl_command_reply!(ProtocolLockState, ProtocolLockStatus);
+impl_command_reply!(Reading, SensorReading);
+impl_command_reply!(ScaleCelsius, Ack);
+impl_command_reply!(ScaleFahrenheit, Ack);
+impl_command_reply!(ScaleKelvin, Ack);
+impl_command_reply!(ScaleState, TemperatureScale);
+impl_command_reply!(Sleep, None);
+impl_command_reply!(Status, DeviceStatus);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use i2cdev::mock::MockI2CDevice;
+
+    fn mock_reply(data: &[u8]) -> MockI2CDevice {
+        let mut dev = MockI2CDevice::new();
+        dev.regmap.write_regs(0, data);
+        dev
+    }
+
+    #[test]
+    fn reads_data_reply() {
+        let mut dev = mock_reply(b"\x01?S,C\0");
+        let data = read_reply(&mut dev, Reply::Data).unwrap();
+        assert_eq!(
+            ScaleState.parse_reply(&data).unwrap(),
+            TemperatureScale::Celsius
+        );
+    }
+
+    #[test]
+    fn reads_ack_reply() {
+        let mut dev = mock_reply(&[1, 0]);
+        let data = read_reply(&mut dev, Reply::Ack).unwrap();
+        assert_eq!(data, "");
+        assert_eq!(ScaleKelvin.parse_reply(&data).unwrap(), ResponseStatus::Ack);
+    }
+
+    #[test]
+    fn reading_error_codes_yields_error() {
+        let mut dev = mock_reply(&[254, 0]);
+        let err = read_reply(&mut dev, Reply::Data).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::PendingResponse);
+
+        let mut dev = mock_reply(&[2, 0]);
+        let err = read_reply(&mut dev, Reply::Ack).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::DeviceErrorResponse);
+
+        let mut dev = mock_reply(&[255, 0]);
+        let err = read_reply(&mut dev, Reply::Data).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::NoDataExpectedResponse);
+    }
+
+    #[test]
+    fn finds_i2c_fault_behind_errors() {
+        let err: EzoError = Err::<(), _>(I2cFault::AddressNack)
+            .context(ErrorKind::UnwritableCommand)
+            .unwrap_err()
+            .into();
+        assert_eq!(err.kind(), ErrorKind::UnwritableCommand);
+        assert_eq!(i2c_fault(&err), Some(I2cFault::AddressNack));
+        assert!(I2cFault::AddressNack.is_device_level());
+        assert!(I2cFault::ArbitrationLoss.is_bus_level());
+        assert!(!I2cFault::Other.is_bus_level() && !I2cFault::Other.is_device_level());
+
+        assert_eq!(i2c_fault(&ErrorKind::I2CRead.into()), None);
+    }
+
+    #[test]
+    fn tells_i2c_faults_from_linux_errnos() {
+        let nix = |errno| LinuxI2CError::Nix(nix::Error::Sys(errno));
+        let faults = [
+            (nix(Errno::ENXIO), Some(I2cFault::AddressNack)),
+            (nix(Errno::EREMOTEIO), Some(I2cFault::Nack)),
+            (nix(Errno::EIO), Some(I2cFault::Nack)),
+            (nix(Errno::EAGAIN), Some(I2cFault::ArbitrationLoss)),
+            (nix(Errno::EBADF), None),
+            (
+                LinuxI2CError::Io(io::Error::from_raw_os_error(Errno::ENXIO as i32)),
+                Some(I2cFault::AddressNack),
+            ),
+        ];
+        for (err, fault) in faults {
+            let err = transfer_error(err, ErrorKind::I2CRead);
+            assert_eq!(err.kind(), ErrorKind::I2CRead);
+            assert_eq!(i2c_fault(&err), fault);
+        }
+
+        let err = transfer_error(
+            io::Error::from(io::ErrorKind::Other),
+            ErrorKind::UnwritableCommand,
+        );
+        assert_eq!(i2c_fault(&err), None);
+    }
+
+    #[test]
+    fn retries_interrupted_transfers() {
+        let mut attempts = 0;
+        let res = retry_interrupted(|| {
+            attempts += 1;
+            match attempts {
+                1 => Err(LinuxI2CError::Nix(nix::Error::Sys(Errno::EINTR))),
+                2 => Err(LinuxI2CError::Io(io::ErrorKind::WouldBlock.into())),
+                _ => Ok(attempts),
+            }
+        });
+        assert_eq!(res.unwrap(), 3);
+
+        let mut attempts = 0;
+        let res: Result<(), io::Error> = retry_interrupted(|| {
+            attempts += 1;
+            Err(io::ErrorKind::Interrupted.into())
+        });
+        assert!(res.is_err());
+        assert_eq!(attempts, INTERRUPTED_RETRIES + 1);
+
+        let mut attempts = 0;
+        let res: Result<(), io::Error> = retry_interrupted(|| {
+            attempts += 1;
+            Err(io::ErrorKind::PermissionDenied.into())
+        });
+        assert!(res.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn decodes_reply_buffers() {
+        assert_eq!(decode_reply(b"\x01?L,1\0", Reply::Data).unwrap(), "?L,1");
+        assert_eq!(decode_reply(&[1, 0], Reply::Ack).unwrap(), "");
+        assert_eq!(decode_reply(&[], Reply::None).unwrap(), "");
+        let err = decode_reply(&[], Reply::Data).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MalformedResponse);
+        let err = decode_reply(&[1], Reply::Data).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MalformedResponse);
+        let err = decode_reply(b"\x01?L,1", Reply::Data).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MalformedResponse);
+    }
+
+    #[test]
+    fn command_bytes_end_with_nul() {
+        assert_eq!(command_bytes("R").unwrap(), b"R\0");
+        let err = command_bytes("R\0").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::UnreadableCommand);
+    }
+
+    #[test]
+    fn issues_then_collects_command() {
+        let mut dev = mock_reply(&[]);
+        let issued = ScaleState.issue(&mut dev).unwrap();
+        assert_eq!(
+            issued.delay(),
+            Duration::from_millis(ScaleState.get_delay())
+        );
+        assert!(issued.remaining() <= issued.delay());
+
+        let mut dev = mock_reply(b"\x01?S,F\0");
+        let scale = collect(&mut dev, issued).unwrap();
+        assert_eq!(scale, TemperatureScale::Fahrenheit);
+    }
+
+    #[test]
+    fn device_errors_name_the_rejected_command() {
+        let mut dev = mock_reply(&[]);
+        let issued = CalibrationTemperature(25.5).issue(&mut dev).unwrap();
+
+        let mut dev = mock_reply(&[2, 0]);
+        let err = collect(&mut dev, issued).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::DeviceErrorResponse);
+        assert_eq!(rejected_command(&err), Some("CAL,25.50"));
+
+        let mut dev = mock_reply(&[254, 0]);
+        let err = read_reply(&mut dev, Reply::Ack).err().unwrap();
+        assert_eq!(rejected_command(&err), None);
+    }
+
+    #[test]
+    fn issued_command_without_reply_is_ready() {
+        let mut dev = mock_reply(&[]);
+        let issued = Sleep.issue(&mut dev).unwrap();
+        assert!(issued.is_ready());
+        assert_eq!(collect(&mut dev, issued).unwrap(), ());
+    }
+
+    fn pending_then(data: &[u8]) -> MockI2CDevice {
+        let mut dev = mock_reply(&[254, 0]);
+        dev.regmap.write_regs(MAX_REPLY, data);
+        dev
+    }
+
+    fn quick_polling(timeout_ms: u64) -> Polling {
+        Polling::new(Duration::from_millis(1), Duration::from_millis(timeout_ms))
+    }
+
+    #[test]
+    fn polls_until_reply_is_ready() {
+        let mut dev = pending_then(b"\x01?S,C\0");
+        let data = poll_reply(&mut dev, Reply::Data, &quick_polling(1000)).unwrap();
+        assert_eq!(data, "?S,C");
+
+        let mut dev = pending_then(&[1, 0]);
+        let issued = Issued {
+            cmd: LedOn,
+            issued_at: Instant::now(),
+            delay: Duration::from_millis(300),
+        };
+        let ack = collect_polled(&mut dev, issued, &quick_polling(1000)).unwrap();
+        assert_eq!(ack, ResponseStatus::Ack);
+    }
+
+    #[test]
+    fn polling_times_out_while_pending() {
+        let mut dev = pending_then(&[254, 0]);
+        let err = poll_reply(&mut dev, Reply::Data, &quick_polling(0))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::PendingResponse);
+    }
+
+    #[test]
+    fn writes_command_bytes_into_buffer() {
+        let mut buf = [0xff; MAX_COMMAND];
+        let len = write_command_bytes("CAL,25.0", &mut buf).unwrap();
+        assert_eq!(&buf[..len], b"CAL,25.0\0");
+
+        let mut small = [0; 4];
+        let err = write_command_bytes("D,60", &mut small).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::UnreadableCommand);
+    }
+
+    #[test]
+    fn runs_command_on_generic_device() {
+        // The mock takes the first byte written as a register offset, and
+        // the rest as data; reads continue right after it.
+        let mut dev = MockI2CDevice::new();
+        dev.regmap.write_regs(usize::from(b'S') + 3, b"\x01?S,K\0");
+        assert_eq!(
+            ScaleState.run_on(&mut dev).unwrap(),
+            TemperatureScale::Kelvin
+        );
+    }
+
+    #[test]
+    fn encodes_commands_into_buffer() {
+        let mut buf = [0xff; MAX_COMMAND];
+        let len = encode_command(&DataloggerPeriod(60), &mut buf).unwrap();
+        assert_eq!(&buf[..len], b"D,60\0");
+
+        let len = encode_command(&Sleep, &mut buf).unwrap();
+        assert_eq!(&buf[..len], b"SLEEP\0");
+
+        let mut small = [0; 4];
+        let err = encode_command(&DataloggerPeriod(60), &mut small)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::UnreadableCommand);
+    }
+
+    #[cfg(feature = "heapless")]
+    #[test]
+    fn builds_heapless_command_strings() {
+        let cmd: heapless::String<16> = command_string(&CalibrationTemperature(25.0)).unwrap();
+        assert_eq!(cmd.as_str(), "CAL,25.00");
+        assert!(command_string::<_, 4>(&CalibrationTemperature(25.0)).is_err());
+    }
+
+    #[test]
+    fn decodes_raw_reply_buffers() {
+        assert_eq!(decode_raw_reply(b"\x01?T,1\0\0").unwrap(), b"?T,1");
+        assert_eq!(decode_raw_reply(&[1, 0]).unwrap(), b"");
+        let err = decode_raw_reply(&[1, b'?']).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MalformedResponse);
+        let err = decode_raw_reply(&[2, 0]).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::DeviceErrorResponse);
+    }
+
+    #[test]
+    fn runs_raw_commands() {
+        let mut dev = MockI2CDevice::new();
+        dev.regmap.write_regs(usize::from(b'T') + 3, b"\x01?T,1\0");
+        let data = raw_command(&mut dev, "T,?", Duration::from_millis(0)).unwrap();
+        assert_eq!(data, b"?T,1");
+    }
+
+    #[test]
+    fn commands_declare_their_reply() {
+        assert_eq!(Sleep.reply(), Reply::None);
+        assert_eq!(CalibrationTemperature(25.0).reply(), Reply::Ack);
+        assert_eq!(Reading.reply(), Reply::Data);
+    }
+}
@@ -0,0 +1,109 @@
+//! Diagnostic reports gathered from the RTD EZO chip.
+use std::fmt;
+
+use super::response::{
+    CalibrationStatus, DataLoggerStorageIntervalSeconds, DeviceInfo, DeviceStatus, LedStatus,
+    ProtocolLockStatus, TemperatureScale,
+};
+
+/// Overall state of the device, gathered by `RtdSensor::health_report()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HealthReport {
+    pub info: DeviceInfo,
+    pub status: DeviceStatus,
+    pub calibration: CalibrationStatus,
+    pub scale: TemperatureScale,
+    pub led: LedStatus,
+    pub protocol_lock: ProtocolLockStatus,
+    pub datalogger: DataLoggerStorageIntervalSeconds,
+}
+
+impl fmt::Display for HealthReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "device: {}", self.info.device)?;
+        writeln!(f, "firmware: {}", self.info.firmware)?;
+        writeln!(f, "restart-reason: {}", self.status.restart_reason)?;
+        writeln!(f, "vcc-voltage: {:.*}", 3, self.status.vcc_voltage)?;
+        writeln!(f, "calibration: {}", self.calibration)?;
+        writeln!(f, "scale: {}", self.scale)?;
+        writeln!(f, "led: {}", self.led)?;
+        writeln!(f, "protocol-lock: {}", self.protocol_lock)?;
+        write!(f, "datalogger-interval: {}", self.datalogger)
+    }
+}
+
+/// Current status and settings, gathered by `RtdSensor::summary()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub status: DeviceStatus,
+    pub calibration: CalibrationStatus,
+    pub scale: TemperatureScale,
+    pub datalogger: DataLoggerStorageIntervalSeconds,
+    pub led: LedStatus,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "restart-reason: {}", self.status.restart_reason)?;
+        writeln!(f, "vcc-voltage: {:.*}", 3, self.status.vcc_voltage)?;
+        writeln!(f, "calibration: {}", self.calibration)?;
+        writeln!(f, "scale: {}", self.scale)?;
+        writeln!(f, "datalogger-interval: {}", self.datalogger)?;
+        write!(f, "led: {}", self.led)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::RestartReason;
+
+    #[test]
+    fn formats_health_report_as_one_setting_per_line() {
+        let report = HealthReport {
+            info: DeviceInfo {
+                device: "RTD".to_string(),
+                firmware: "2.01".to_string(),
+            },
+            status: DeviceStatus {
+                restart_reason: RestartReason::PoweredOff,
+                vcc_voltage: 5.038,
+            },
+            calibration: CalibrationStatus::Calibrated,
+            scale: TemperatureScale::Celsius,
+            led: LedStatus::On,
+            protocol_lock: ProtocolLockStatus::Off,
+            datalogger: DataLoggerStorageIntervalSeconds(0),
+        };
+        let dump = format!("{}", report);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0], "device: RTD");
+        assert_eq!(lines[1], "firmware: 2.01");
+        assert_eq!(lines[3], "vcc-voltage: 5.038");
+        assert_eq!(lines[4], "calibration: calibrated");
+        assert_eq!(lines[5], "scale: celsius");
+        assert_eq!(lines[8], "datalogger-interval: 0");
+    }
+
+    #[test]
+    fn formats_summary_as_one_setting_per_line() {
+        let summary = Summary {
+            status: DeviceStatus {
+                restart_reason: RestartReason::PoweredOff,
+                vcc_voltage: 3.3,
+            },
+            calibration: CalibrationStatus::NotCalibrated,
+            scale: TemperatureScale::Kelvin,
+            datalogger: DataLoggerStorageIntervalSeconds(60),
+            led: LedStatus::Off,
+        };
+        let dump = format!("{}", summary);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[1], "vcc-voltage: 3.300");
+        assert_eq!(lines[2], "calibration: not-calibrated");
+        assert_eq!(lines[3], "scale: kelvin");
+        assert_eq!(lines[4], "datalogger-interval: 60");
+    }
+}
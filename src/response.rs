@@ -1,4 +1,7 @@
 //! Initial code graciously donated by "Federico Mena Quintero <federico@gnome.org>".
+//!
+//! The `parse()` functions ignore the trailing NUL bytes, line endings, and
+//! spaces that some transports leave after a response.
 use std::fmt;
 use std::str::FromStr;
 
@@ -8,257 +11,347 @@ pub use ezo_common::response::{
     ResponseStatus, RestartReason,
 };
 
-use failure::ResultExt;
+pub use super::grammar::{
+    CalibrationStatus, DataLoggerStorageIntervalSeconds, Float, InvalidInterval,
+    InvalidTemperature, MemoryReading, MixedScales, SensorReading, Temperature, TemperatureDelta,
+    TemperatureMilli, TemperatureRange, TemperatureScale, Version, Volts,
+};
 
-/// Calibration status of the RTD EZO chip.
-#[derive(Copy, Clone, PartialEq)]
-pub enum CalibrationStatus {
-    Calibrated,
-    NotCalibrated,
-}
+use super::grammar;
 
 impl CalibrationStatus {
     /// Parses the result of the "Cal,?" command to query the device's
     /// calibration status.  Returns ...
     pub fn parse(response: &str) -> Result<CalibrationStatus, EzoError> {
-        if response.starts_with("?CAL,") {
-            let rest = response.get(5..).unwrap();
-            let mut split = rest.split(',');
-
-            let _calibration = match split.next() {
-                Some("1") => Ok(CalibrationStatus::Calibrated),
-                Some("0") => Ok(CalibrationStatus::NotCalibrated),
-                _ => return Err(ErrorKind::ResponseParse.into()),
-            };
-
-            match split.next() {
-                None => _calibration,
-                _ => Err(ErrorKind::ResponseParse.into()),
-            }
-        } else {
-            Err(ErrorKind::ResponseParse.into())
-        }
-    }
-}
-
-impl fmt::Debug for CalibrationStatus {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            CalibrationStatus::Calibrated => write!(f, "?CAL,1"),
-            CalibrationStatus::NotCalibrated => write!(f, "?CAL,0"),
-        }
+        grammar::parse_calibration_status(grammar::trim_response(response))
+            .map_err(|_| ErrorKind::ResponseParse.into())
     }
 }
 
-impl fmt::Display for CalibrationStatus {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            CalibrationStatus::Calibrated => write!(f, "calibrated"),
-            CalibrationStatus::NotCalibrated => write!(f, "not-calibrated"),
-        }
-    }
-}
-
-/// Seconds between automatic logging of readings
-#[derive(Copy, Clone, PartialEq)]
-pub struct DataLoggerStorageIntervalSeconds(pub u32);
-
 impl DataLoggerStorageIntervalSeconds {
     /// Parses the result of the "D,?" command to query the data logger's
     /// storage interval.  Returns the number of seconds between readings.
     pub fn parse(response: &str) -> Result<DataLoggerStorageIntervalSeconds, EzoError> {
-        if response.starts_with("?D,") {
-            let num_str = response.get(3..).unwrap();
-            let num = u32::from_str(num_str).context(ErrorKind::ResponseParse)?;
-            match num {
-                0 | 10...320_000 => Ok(DataLoggerStorageIntervalSeconds(num)),
-                _ => Err(ErrorKind::ResponseParse.into()),
-            }
-        } else {
-            Err(ErrorKind::ResponseParse.into())
-        }
-    }
-}
-
-impl fmt::Debug for DataLoggerStorageIntervalSeconds {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "?D,{}", self.0)
+        grammar::parse_datalogger_interval(grammar::trim_response(response))
+            .map_err(|_| ErrorKind::ResponseParse.into())
     }
 }
 
-impl fmt::Display for DataLoggerStorageIntervalSeconds {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
-
-/// A recalled temperature reading from memory.
-#[derive(Copy, Clone, PartialEq)]
-pub struct MemoryReading {
-    pub location: u32,
-    pub reading: f64,
-}
-
-impl MemoryReading {
-    pub fn parse(response: &str) -> Result<MemoryReading, EzoError> {
-        let mut split = response.split(",");
-
-        let location: u32 = if let Some(location_str) = split.next() {
-            u32::from_str(location_str).context(ErrorKind::ResponseParse)?
-        } else {
-            return Err(ErrorKind::ResponseParse.into());
-        };
+/// Name given to the device, up to 16 characters. It may be empty.
+#[derive(Clone, PartialEq)]
+pub struct DeviceName(pub String);
 
-        let reading: f64 = if let Some(reading_str) = split.next() {
-            f64::from_str(reading_str).context(ErrorKind::ResponseParse)?
-        } else {
-            return Err(ErrorKind::ResponseParse.into());
-        };
-
-        if let Some(_) = split.next() {
-            return Err(ErrorKind::ResponseParse.into());
+impl DeviceName {
+    /// Parses the result of the "NAME,?" command to query the device's name.
+    pub fn parse(response: &str) -> Result<DeviceName, EzoError> {
+        let response = grammar::trim_response(response);
+        match response.get(..6) {
+            Some("?NAME,") if response.len() <= 6 + 16 => Ok(DeviceName(response[6..].to_string())),
+            _ => Err(ErrorKind::ResponseParse.into()),
         }
-
-        Ok(MemoryReading { location, reading })
     }
 }
 
-impl fmt::Debug for MemoryReading {
+impl fmt::Debug for DeviceName {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{},{}", self.location, self.reading)
+        write!(f, "?NAME,{}", self.0)
     }
 }
 
-impl fmt::Display for MemoryReading {
+impl fmt::Display for DeviceName {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{},{}", self.location, self.reading)
+        write!(f, "{}", self.0)
     }
 }
 
-/// Temperature scales supported by the RTD EZO sensor.
-#[derive(Copy, Clone, PartialEq)]
-pub enum TemperatureScale {
-    Celsius,
-    Kelvin,
-    Fahrenheit,
+impl MemoryReading {
+    /// Parses the result of the "M" and "M,?" commands to recall a reading
+    /// from memory.
+    pub fn parse(response: &str) -> Result<MemoryReading, EzoError> {
+        grammar::parse_memory_reading(grammar::trim_response(response))
+            .map_err(|_| ErrorKind::ResponseParse.into())
+    }
 }
 
 impl TemperatureScale {
     /// Parses the result of the "S,?" command to query temperature scale.
     pub fn parse(response: &str) -> Result<TemperatureScale, EzoError> {
-        match response {
-            "?S,C" => Ok(TemperatureScale::Celsius),
-            "?S,K" => Ok(TemperatureScale::Kelvin),
-            "?S,F" => Ok(TemperatureScale::Fahrenheit),
-            _ => Err(ErrorKind::ResponseParse.into()),
-        }
-    }
-}
-
-impl fmt::Debug for TemperatureScale {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let status = match *self {
-            TemperatureScale::Celsius => "?S,C",
-            TemperatureScale::Kelvin => "?S,K",
-            TemperatureScale::Fahrenheit => "?S,F",
-        };
-        write!(f, "{}", status)
+        grammar::parse_temperature_scale(grammar::trim_response(response))
+            .map_err(|_| ErrorKind::ResponseParse.into())
     }
 }
 
-impl fmt::Display for TemperatureScale {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let status = match *self {
-            TemperatureScale::Celsius => "celsius",
-            TemperatureScale::Kelvin => "kelvin",
-            TemperatureScale::Fahrenheit => "fahrenheit",
-        };
-        write!(f, "{}", status)
-    }
-}
-
-/// A temperature value from a temperature reading
-#[derive(Copy, Clone, PartialEq)]
-pub enum Temperature {
-    Celsius(f64),
-    Kelvin(f64),
-    Fahrenheit(f64),
-}
-
 impl Temperature {
-    /// Creates a new temperature value from a given temperature
-    /// `scale`.  Note that this function simply copies the `value`
-    /// regardless of the `scale`; it does not validate e.g. that a
-    /// Kelvin value is not negative.
-    pub fn new(scale: TemperatureScale, value: f64) -> Temperature {
-        match scale {
-            TemperatureScale::Celsius => Temperature::Celsius(value),
-            TemperatureScale::Kelvin => Temperature::Kelvin(value),
-            TemperatureScale::Fahrenheit => Temperature::Fahrenheit(value),
-        }
-    }
-
     /// Parses the result of the "R" command to get a temperature reading.
     /// Note that this depends on knowing the temperature scale
     /// which the device is configured to use.
     pub fn parse(response: &str, scale: TemperatureScale) -> Result<Temperature, EzoError> {
-        let val = f64::from_str(response).context(ErrorKind::ResponseParse)?;
-        Ok(Temperature::new(scale, val))
+        grammar::parse_temperature(grammar::trim_response(response), scale)
+            .map_err(|_| ErrorKind::ResponseParse.into())
     }
-}
 
-impl fmt::Debug for Temperature {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let (temp, scale) = match *self {
-            Temperature::Celsius(t) => (t, "celsius"),
-            Temperature::Kelvin(t) => (t, "kelvin"),
-            Temperature::Fahrenheit(t) => (t, "fahrenheit"),
-        };
-        write!(f, "{},{}", temp, scale)
+    /// Parses the result of the "R" command as `parse()` does, but rejects
+    /// temperatures below absolute zero, such as those read from a faulty
+    /// probe.
+    pub fn parse_strict(response: &str, scale: TemperatureScale) -> Result<Temperature, EzoError> {
+        grammar::parse_temperature_strict(grammar::trim_response(response), scale)
+            .map_err(|_| ErrorKind::ResponseParse.into())
     }
 }
 
-impl fmt::Display for Temperature {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let (temp, scale) = match *self {
-            Temperature::Celsius(t) => (t, "celsius"),
-            Temperature::Kelvin(t) => (t, "kelvin"),
-            Temperature::Fahrenheit(t) => (t, "fahrenheit"),
-        };
-        write!(f, "{},{}", temp, scale)
+impl TemperatureMilli {
+    /// Parses the result of the "R" command into thousandths of a degree,
+    /// without floating point. Like `Temperature::parse()`, this depends on
+    /// knowing the temperature scale which the device is configured to use.
+    pub fn parse(response: &str, scale: TemperatureScale) -> Result<TemperatureMilli, EzoError> {
+        grammar::parse_temperature_milli(grammar::trim_response(response), scale)
+            .map_err(|_| ErrorKind::ResponseParse.into())
     }
 }
 
-/// A temperature reading
-#[derive(Copy, Clone, PartialEq)]
-pub struct SensorReading(pub f64);
-
 impl SensorReading {
     /// Parses the result of the "R" command to get a temperature reading.
     /// Note that the returned value has no known units. It is your
     /// responsibility to know the current `TemperatureScale` setting.
     pub fn parse(response: &str) -> Result<SensorReading, EzoError> {
-        let val = f64::from_str(response).context(ErrorKind::ResponseParse)?;
-        Ok(SensorReading(val))
+        grammar::parse_sensor_reading(grammar::trim_response(response))
+            .map_err(|_| ErrorKind::ResponseParse.into())
     }
 }
 
-impl fmt::Debug for SensorReading {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:.*}", 3, self.0)
+impl Version {
+    /// Parses the firmware field of a `DeviceInfo`, e.g. `2.10`.
+    pub fn parse(firmware: &str) -> Result<Version, EzoError> {
+        grammar::parse_version(grammar::trim_response(firmware))
+            .map_err(|_| ErrorKind::ResponseParse.into())
     }
 }
 
-impl fmt::Display for SensorReading {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:.*}", 3, self.0)
+/// The voltage is narrowed to `f32` with the `f32` feature.
+impl<'a> From<&'a DeviceStatus> for Volts {
+    #[allow(clippy::unnecessary_cast)]
+    fn from(status: &'a DeviceStatus) -> Volts {
+        Volts(status.vcc_voltage as Float)
+    }
+}
+
+macro_rules! impl_from_str {
+    ($name:ident) => {
+        impl FromStr for $name {
+            type Err = EzoError;
+
+            /// Parses the response as `parse()` does.
+            fn from_str(s: &str) -> Result<$name, EzoError> {
+                $name::parse(s)
+            }
+        }
+    };
+}
+
+impl_from_str!(CalibrationStatus);
+impl_from_str!(DataLoggerStorageIntervalSeconds);
+impl_from_str!(MemoryReading);
+impl_from_str!(TemperatureScale);
+impl_from_str!(SensorReading);
+impl_from_str!(Version);
+
+/// Any response of the RTD EZO chip, e.g. to an `RtdCommand`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RtdResponse {
+    /// No reply was read back, e.g. after `SLEEP`.
+    None,
+    /// The command was acknowledged, without any data.
+    Ack(ResponseStatus),
+    CalibrationStatus(CalibrationStatus),
+    DataLoggerStorageIntervalSeconds(DataLoggerStorageIntervalSeconds),
+    DeviceInfo(DeviceInfo),
+    DeviceName(DeviceName),
+    DeviceStatus(DeviceStatus),
+    Exported(Exported),
+    ExportedInfo(ExportedInfo),
+    LedStatus(LedStatus),
+    MemoryReading(MemoryReading),
+    ProtocolLockStatus(ProtocolLockStatus),
+    SensorReading(SensorReading),
+    Temperature(Temperature),
+    TemperatureScale(TemperatureScale),
+}
+
+impl From<()> for RtdResponse {
+    fn from(_: ()) -> RtdResponse {
+        RtdResponse::None
     }
 }
 
+impl From<ResponseStatus> for RtdResponse {
+    fn from(status: ResponseStatus) -> RtdResponse {
+        RtdResponse::Ack(status)
+    }
+}
+
+macro_rules! impl_from_response {
+    ($($name:ident),*) => {
+        $(
+            impl From<$name> for RtdResponse {
+                fn from(response: $name) -> RtdResponse {
+                    RtdResponse::$name(response)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_response!(
+    CalibrationStatus,
+    DataLoggerStorageIntervalSeconds,
+    DeviceInfo,
+    DeviceName,
+    DeviceStatus,
+    Exported,
+    ExportedInfo,
+    LedStatus,
+    MemoryReading,
+    ProtocolLockStatus,
+    SensorReading,
+    Temperature,
+    TemperatureScale
+);
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn responses_convert_into_rtd_response() {
+        assert_eq!(RtdResponse::from(()), RtdResponse::None);
+        assert_eq!(
+            RtdResponse::from(ResponseStatus::Ack),
+            RtdResponse::Ack(ResponseStatus::Ack)
+        );
+        assert_eq!(
+            RtdResponse::from(TemperatureScale::Kelvin),
+            RtdResponse::TemperatureScale(TemperatureScale::Kelvin)
+        );
+        match RtdResponse::from(SensorReading(25.104)) {
+            RtdResponse::SensorReading(reading) => assert_eq!(reading.0, 25.104),
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn responses_parse_from_str() {
+        assert_eq!(
+            "?CAL,1".parse::<CalibrationStatus>().unwrap(),
+            CalibrationStatus::Calibrated
+        );
+        assert_eq!(
+            "?D,60".parse::<DataLoggerStorageIntervalSeconds>().unwrap(),
+            DataLoggerStorageIntervalSeconds(60)
+        );
+        assert_eq!(
+            "3,21.5".parse::<MemoryReading>().unwrap(),
+            MemoryReading {
+                location: 3,
+                reading: 21.5,
+            }
+        );
+        assert_eq!(
+            "?S,K".parse::<TemperatureScale>().unwrap(),
+            TemperatureScale::Kelvin
+        );
+        assert_eq!(
+            "25.104".parse::<SensorReading>().unwrap(),
+            SensorReading(25.104)
+        );
+        assert!("?S,X".parse::<TemperatureScale>().is_err());
+    }
+
+    #[test]
+    fn responses_work_as_keys_and_sort() {
+        use std::collections::HashMap;
+
+        let mut counts = HashMap::new();
+        for scale in &[
+            TemperatureScale::Celsius,
+            TemperatureScale::Kelvin,
+            TemperatureScale::Celsius,
+        ] {
+            *counts.entry(*scale).or_insert(0) += 1;
+        }
+        assert_eq!(counts[&TemperatureScale::Celsius], 2);
+        assert_eq!(counts[&TemperatureScale::Kelvin], 1);
+
+        let mut intervals = vec![
+            DataLoggerStorageIntervalSeconds(60),
+            DataLoggerStorageIntervalSeconds(0),
+            DataLoggerStorageIntervalSeconds(10),
+        ];
+        intervals.sort();
+        assert_eq!(
+            intervals,
+            vec![
+                DataLoggerStorageIntervalSeconds(0),
+                DataLoggerStorageIntervalSeconds(10),
+                DataLoggerStorageIntervalSeconds(60),
+            ]
+        );
+    }
+
+    #[test]
+    fn parsing_ignores_trailing_padding() {
+        for padding in ["\0", "\0\0\0", "\r\n", "\n", " ", " \r\n\0"].iter() {
+            let pad = |response: &str| format!("{}{}", response, padding);
+            assert_eq!(
+                CalibrationStatus::parse(&pad("?CAL,1")).unwrap(),
+                CalibrationStatus::Calibrated
+            );
+            assert_eq!(
+                DataLoggerStorageIntervalSeconds::parse(&pad("?D,60")).unwrap(),
+                DataLoggerStorageIntervalSeconds(60)
+            );
+            assert_eq!(
+                DeviceName::parse(&pad("?NAME,Tank-A")).unwrap(),
+                DeviceName("Tank-A".to_string())
+            );
+            assert_eq!(
+                MemoryReading::parse(&pad("3,21.5")).unwrap(),
+                MemoryReading {
+                    location: 3,
+                    reading: 21.5,
+                }
+            );
+            assert_eq!(
+                TemperatureScale::parse(&pad("?S,F")).unwrap(),
+                TemperatureScale::Fahrenheit
+            );
+            assert_eq!(
+                SensorReading::parse(&pad("25.104")).unwrap(),
+                SensorReading(25.104)
+            );
+            assert_eq!(
+                Temperature::parse(&pad("25.104"), TemperatureScale::Celsius).unwrap(),
+                Temperature::Celsius(25.104)
+            );
+            assert_eq!(
+                TemperatureMilli::parse(&pad("25.104"), TemperatureScale::Celsius).unwrap(),
+                TemperatureMilli::new(TemperatureScale::Celsius, 25_104)
+            );
+        }
+        assert!(SensorReading::parse(" 25.104").is_err());
+        assert!(SensorReading::parse("25.104\0x").is_err());
+    }
+
+    #[test]
+    fn supply_voltage_comes_from_device_status() {
+        let status = DeviceStatus {
+            restart_reason: RestartReason::PoweredOff,
+            vcc_voltage: 2.912,
+        };
+        let vcc = Volts::from(&status);
+        assert_eq!(vcc, Volts(2.912));
+        assert!(vcc.is_undervoltage(Volts::BROWN_OUT));
+    }
+
     #[test]
     fn parses_response_to_calibration_status() {
         let response = "?CAL,1";
@@ -358,6 +451,43 @@ mod tests {
         assert!(DataLoggerStorageIntervalSeconds::parse(response).is_err());
     }
 
+    #[test]
+    fn parses_response_to_device_name() {
+        let response = "?NAME,";
+        assert_eq!(
+            DeviceName::parse(response).unwrap(),
+            DeviceName("".to_string())
+        );
+
+        let response = "?NAME,Tank-A";
+        assert_eq!(
+            DeviceName::parse(response).unwrap(),
+            DeviceName("Tank-A".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_device_name_to_response() {
+        let name = DeviceName("Tank-A".to_string());
+        assert_eq!(format!("{}", name), "Tank-A");
+    }
+
+    #[test]
+    fn parsing_invalid_device_name_yields_error() {
+        let response = "";
+        assert!(DeviceName::parse(response).is_err());
+
+        let response = "?NAME";
+        assert!(DeviceName::parse(response).is_err());
+
+        let response = "?NAME,abcdefghijklmnopq";
+        assert!(DeviceName::parse(response).is_err());
+
+        for response in ["?NAM", "?NAM€", "€NAME,", "?NAME€"].iter() {
+            assert!(DeviceName::parse(response).is_err());
+        }
+    }
+
     #[test]
     fn parses_response_to_memory_reading() {
         let response = "0,0";
@@ -461,6 +591,14 @@ mod tests {
 
         let response = "-x";
         assert!(SensorReading::parse(response).is_err());
+
+        let err = SensorReading::parse("NaN").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::ResponseParse);
+
+        let err = Temperature::parse("inf", TemperatureScale::Kelvin)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::ResponseParse);
     }
 
     #[test]
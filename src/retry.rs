@@ -0,0 +1,264 @@
+//! Retries of commands that fail on flaky buses.
+//!
+//! A `RetryPolicy` tells how many times a command is attempted, how long to
+//! wait between attempts, and which errors are worth retrying. It is used
+//! by `RunWithRetry::run_with_retry` on any I2C device, and by
+//! `RtdSensor::run_with_retry`.
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::thread;
+use std::time::Duration;
+
+use super::errors::{RtdError, Transient};
+use super::protocol::RunOn;
+use super::{ErrorKind, EzoError};
+
+use i2cdev::core::I2CDevice;
+
+/// How to retry a command that fails.
+///
+/// The wait between attempts starts at the initial delay, and doubles
+/// after each retry, up to the maximum delay. With jitter, each wait is
+/// shortened by a random fraction of up to `jitter`, so that devices that
+/// failed together do not retry in lockstep.
+#[derive(Copy, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+    jitter: f64,
+    retry_if: fn(ErrorKind) -> bool,
+}
+
+impl RetryPolicy {
+    /// Attempts a command up to `max_attempts` times, retrying transient
+    /// errors after waiting 100 ms, then 200 ms, and so on, up to 2 s.
+    ///
+    /// A command is always attempted at least once.
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+            jitter: 0.0,
+            retry_if: |kind| kind.is_transient(),
+        }
+    }
+
+    /// Sets the wait before the first retry, and the longest wait between
+    /// two attempts.
+    pub fn backoff(mut self, initial_delay: Duration, max_delay: Duration) -> RetryPolicy {
+        self.initial_delay = initial_delay;
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Shortens each wait by a random fraction of up to `jitter`, which is
+    /// clamped to `0.0..=1.0`.
+    pub fn jitter(mut self, jitter: f64) -> RetryPolicy {
+        self.jitter = if jitter > 0.0 { jitter.min(1.0) } else { 0.0 };
+        self
+    }
+
+    /// Sets which kinds of errors are retried. By default, only the
+    /// transient ones are.
+    pub fn retry_if(mut self, retry_if: fn(ErrorKind) -> bool) -> RetryPolicy {
+        self.retry_if = retry_if;
+        self
+    }
+
+    /// Returns the most times a command is attempted.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns `true` if an error of the given kind is retried.
+    pub fn retries(&self, kind: ErrorKind) -> bool {
+        (self.retry_if)(kind)
+    }
+
+    /// Returns the wait before the retry that follows `attempt` failed
+    /// attempts, without jitter.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    /// Runs `op` until it succeeds, fails with an error that is not
+    /// retried, or has been attempted `max_attempts` times, returning its
+    /// last result.
+    pub fn retry<T, E, F>(&self, mut op: F) -> Result<T, E>
+    where
+        E: Retryable,
+        F: FnMut() -> Result<T, E>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op() {
+                Err(ref err) if attempt < self.max_attempts && self.should_retry(err) => {
+                    thread::sleep(self.jittered(self.delay(attempt)));
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    fn should_retry<E: Retryable>(&self, err: &E) -> bool {
+        match err.ezo_kind() {
+            Some(kind) => self.retries(kind),
+            None => false,
+        }
+    }
+
+    fn jittered(&self, delay: Duration) -> Duration {
+        if self.jitter == 0.0 {
+            return delay;
+        }
+        // A fresh `RandomState` is randomly seeded, which is random enough
+        // to spread retries apart.
+        let random = RandomState::new().build_hasher().finish();
+        let fraction = (random >> 11) as f64 / (1u64 << 53) as f64;
+        delay.mul_f64(1.0 - self.jitter * fraction)
+    }
+}
+
+/// Attempts a command 3 times.
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new(3)
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_delay", &self.initial_delay)
+            .field("max_delay", &self.max_delay)
+            .field("jitter", &self.jitter)
+            .finish()
+    }
+}
+
+/// An error that a `RetryPolicy` can tell whether to retry, by the kind of
+/// the `EzoError` behind it.
+pub trait Retryable {
+    /// Returns the kind of the `EzoError` behind the error, if any.
+    fn ezo_kind(&self) -> Option<ErrorKind>;
+}
+
+impl Retryable for EzoError {
+    fn ezo_kind(&self) -> Option<ErrorKind> {
+        Some(self.kind())
+    }
+}
+
+impl Retryable for RtdError {
+    fn ezo_kind(&self) -> Option<ErrorKind> {
+        RtdError::ezo_kind(self)
+    }
+}
+
+/// Runs commands under a `RetryPolicy`.
+pub trait RunWithRetry: RunOn {
+    /// Runs the command on `dev`, as `RunOn::run_on` does, retrying it as
+    /// `policy` tells.
+    fn run_with_retry<D>(
+        &self,
+        dev: &mut D,
+        policy: &RetryPolicy,
+    ) -> Result<Self::Response, EzoError>
+    where
+        D: I2CDevice,
+        D::Error: Send + Sync + 'static,
+    {
+        policy.retry(|| self.run_on(dev))
+    }
+}
+
+impl<C: RunOn> RunWithRetry for C {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScaleState;
+    use crate::response::TemperatureScale;
+    use i2cdev::mock::MockI2CDevice;
+
+    fn quick_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy::new(max_attempts).backoff(Duration::from_millis(1), Duration::from_millis(4))
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max_delay() {
+        let policy = quick_policy(5);
+        let delays: Vec<_> = (1..=5).map(|attempt| policy.delay(attempt)).collect();
+        assert_eq!(
+            delays,
+            [1, 2, 4, 4, 4]
+                .iter()
+                .map(|&ms| Duration::from_millis(ms))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(policy.delay(100), Duration::from_millis(4));
+    }
+
+    #[test]
+    fn jitter_only_shortens_waits() {
+        let policy = quick_policy(3).jitter(0.5);
+        for _ in 0..10 {
+            let delay = policy.jittered(Duration::from_millis(100));
+            assert!(delay > Duration::from_millis(50) && delay <= Duration::from_millis(100));
+        }
+        assert_eq!(quick_policy(3).jitter(7.0).jitter, 1.0);
+    }
+
+    #[test]
+    fn retries_transient_errors_up_to_max_attempts() {
+        let mut attempts = 0;
+        let res: Result<(), EzoError> = quick_policy(3).retry(|| {
+            attempts += 1;
+            Err(ErrorKind::I2CRead.into())
+        });
+        assert_eq!(res.err().unwrap().kind(), ErrorKind::I2CRead);
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let res: Result<(), EzoError> = quick_policy(3).retry(|| {
+            attempts += 1;
+            Err(ErrorKind::DeviceErrorResponse.into())
+        });
+        assert!(res.is_err());
+        assert_eq!(attempts, 1);
+
+        let mut attempts = 0;
+        let res: Result<(), RtdError> = quick_policy(3)
+            .retry_if(|kind| kind == ErrorKind::DeviceErrorResponse)
+            .retry(|| {
+                attempts += 1;
+                match attempts {
+                    1 => Err(EzoError::from(ErrorKind::DeviceErrorResponse).into()),
+                    _ => Ok(()),
+                }
+            });
+        assert!(res.is_ok());
+        assert_eq!(attempts, 2);
+    }
+
+    #[test]
+    fn runs_commands_with_retry() {
+        let mut dev = MockI2CDevice::new();
+        dev.regmap.write_regs(usize::from(b'S') + 3, b"\x01?S,K\0");
+        let scale = ScaleState
+            .run_with_retry(&mut dev, &quick_policy(2))
+            .unwrap();
+        assert_eq!(scale, TemperatureScale::Kelvin);
+    }
+}
@@ -0,0 +1,229 @@
+//! Background sampling of an `RtdSensor`.
+//!
+//! The `Sampler` owns the sensor and reads it at a fixed period on its own
+//! thread, so that the callers consuming the readings never wait on the
+//! timing of the I2C transactions.
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::command::ReadingWithScale;
+use super::errors::{RtdError, RtdErrorKind};
+use super::response::Temperature;
+use super::sensor::RtdSensor;
+
+/// Destination of the temperatures read by a `Sampler`.
+pub trait Publish: Send + 'static {
+    /// Publishes a new temperature, returning `false` once nobody is left
+    /// to receive it, which stops the sampler.
+    fn publish(&mut self, temperature: Temperature) -> bool;
+}
+
+impl Publish for Sender<Temperature> {
+    fn publish(&mut self, temperature: Temperature) -> bool {
+        self.send(temperature).is_ok()
+    }
+}
+
+impl Publish for SyncSender<Temperature> {
+    fn publish(&mut self, temperature: Temperature) -> bool {
+        self.send(temperature).is_ok()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Publish for tokio::sync::watch::Sender<Temperature> {
+    fn publish(&mut self, temperature: Temperature) -> bool {
+        self.send(temperature).is_ok()
+    }
+}
+
+/// Reads a sensor at a fixed period on a background thread, and publishes
+/// each temperature.
+///
+/// Failed readings are not published, but counted by `failures()`. The
+/// thread stops when the `Sampler` is dropped or shut down, or when the
+/// publisher reports that nobody is left to receive the readings. Only
+/// `shutdown()` waits for the thread to finish.
+pub struct Sampler {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<RtdSensor>>,
+}
+
+struct Shared {
+    stop: AtomicBool,
+    failures: AtomicUsize,
+}
+
+impl Sampler {
+    /// Starts sampling the sensor every `period`, returning the sampler and
+    /// the receiving end of the channel the temperatures are sent to.
+    pub fn spawn(sensor: RtdSensor, period: Duration) -> (Sampler, Receiver<Temperature>) {
+        let (sender, receiver) = mpsc::channel();
+        (Sampler::with_publisher(sensor, period, sender), receiver)
+    }
+
+    /// Starts sampling the sensor every `period`, handing each temperature
+    /// to the given publisher, e.g. an existing `Sender`, or a
+    /// `ReadingBuffer` of the latest readings.
+    pub fn with_publisher<P: Publish>(
+        sensor: RtdSensor,
+        period: Duration,
+        publisher: P,
+    ) -> Sampler {
+        let shared = Arc::new(Shared {
+            stop: AtomicBool::new(false),
+            failures: AtomicUsize::new(0),
+        });
+        let thread_shared = Arc::clone(&shared);
+        let handle = thread::spawn(move || sample(sensor, period, publisher, &thread_shared));
+        Sampler {
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    /// Starts sampling the sensor every `period`, keeping a
+    /// `tokio::sync::watch` channel updated with the latest temperature.
+    ///
+    /// The sensor is read once before the thread is started, so that the
+    /// channel always holds a temperature. The receiver can be cloned to
+    /// let many consumers observe the current value.
+    #[cfg(feature = "tokio")]
+    pub fn watch(
+        mut sensor: RtdSensor,
+        period: Duration,
+    ) -> Result<(Sampler, tokio::sync::watch::Receiver<Temperature>), RtdError> {
+        let temperature = sensor.run(ReadingWithScale)?;
+        let (sender, receiver) = tokio::sync::watch::channel(temperature);
+        Ok((Sampler::with_publisher(sensor, period, sender), receiver))
+    }
+
+    /// Stops sampling and joins the thread, giving back the sensor.
+    ///
+    /// A reading in progress is completed first, so that no I2C
+    /// transaction is left half done. If `sleep` is `true`, the chip is
+    /// then put to sleep.
+    pub fn shutdown(mut self, sleep: bool) -> Result<RtdSensor, RtdError> {
+        self.stop();
+        let handle = match self.handle.take() {
+            Some(handle) => handle,
+            None => return Err(RtdErrorKind::SamplerPanic.into()),
+        };
+        let mut sensor = match handle.join() {
+            Ok(sensor) => sensor,
+            Err(_) => return Err(RtdErrorKind::SamplerPanic.into()),
+        };
+        if sleep {
+            sensor.sleep()?;
+        }
+        Ok(sensor)
+    }
+
+    /// Returns the number of readings that failed so far.
+    pub fn failures(&self) -> usize {
+        self.shared.failures.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` while the sampling thread is running.
+    pub fn is_running(&self) -> bool {
+        match self.handle {
+            Some(ref handle) => !handle.is_finished(),
+            None => false,
+        }
+    }
+
+    fn stop(&self) {
+        self.shared.stop.store(true, Ordering::SeqCst);
+        if let Some(ref handle) = self.handle {
+            handle.thread().unpark();
+        }
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn sample<P: Publish>(
+    mut sensor: RtdSensor,
+    period: Duration,
+    mut publisher: P,
+    shared: &Shared,
+) -> RtdSensor {
+    let mut next = Instant::now();
+    while !shared.stop.load(Ordering::SeqCst) {
+        match sensor.run(ReadingWithScale) {
+            Ok(temperature) => {
+                if !publisher.publish(temperature) {
+                    break;
+                }
+            }
+            Err(_) => {
+                shared.failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        next += period;
+        wait_until(next, shared);
+    }
+    sensor
+}
+
+/// Parks the thread until `deadline`, or until the sampler is stopped.
+fn wait_until(deadline: Instant, shared: &Shared) {
+    while !shared.stop.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        thread::park_timeout(deadline - now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::TemperatureScale;
+
+    #[test]
+    fn senders_publish_until_receiver_hangs_up() {
+        let (mut sender, receiver) = mpsc::channel();
+        let temperature = Temperature::new(TemperatureScale::Celsius, 21.5);
+        assert!(sender.publish(temperature));
+        assert_eq!(receiver.recv().unwrap(), temperature);
+        drop(receiver);
+        assert!(!sender.publish(temperature));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn watch_sender_publishes_latest_value() {
+        let celsius = Temperature::new(TemperatureScale::Celsius, 20.0);
+        let (mut sender, receiver) = tokio::sync::watch::channel(celsius);
+        let kelvin = Temperature::new(TemperatureScale::Kelvin, 300.0);
+        assert!(sender.publish(kelvin));
+        assert_eq!(*receiver.borrow(), kelvin);
+        drop(receiver);
+        assert!(!sender.publish(celsius));
+    }
+
+    #[test]
+    fn waiting_ends_at_deadline_or_stop() {
+        let shared = Shared {
+            stop: AtomicBool::new(false),
+            failures: AtomicUsize::new(0),
+        };
+        let start = Instant::now();
+        wait_until(start + Duration::from_millis(5), &shared);
+        assert!(start.elapsed() >= Duration::from_millis(5));
+
+        shared.stop.store(true, Ordering::SeqCst);
+        let start = Instant::now();
+        wait_until(start + Duration::from_secs(60), &shared);
+        assert!(start.elapsed() < Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,1014 @@
+//! High-level handle for the RTD EZO chip.
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::cancel::CancelToken;
+use super::command::{
+    CalibrationState, CalibrationTemperature, Command, DataloggerDisable, DataloggerInterval,
+    DataloggerPeriod, DeviceAddress, DeviceInformation, Export, ExportInfo, Find, Import, LedOff,
+    LedOn, LedState, MemoryRecall, MemoryRecallLast, Name, NameState, ProtocolLockDisable,
+    ProtocolLockEnable, ProtocolLockState, Reading, ReadingWithScale, ScaleCelsius,
+    ScaleFahrenheit, ScaleKelvin, ScaleState, Sleep, Status,
+};
+use super::config::{ConfigDiff, DeviceConfig};
+use super::errors::{ResultExt, RtdError, RtdErrorKind};
+use super::export::ExportedConfig;
+use super::latest::LatestReading;
+use super::protocol::RunOn;
+use super::report::{HealthReport, Summary};
+use super::response::{
+    CalibrationStatus, DeviceInfo, Exported, LedStatus, MemoryReading, ProtocolLockStatus,
+    ResponseStatus, SensorReading, Temperature, TemperatureRange, TemperatureScale, Version, Volts,
+};
+use super::retry::RetryPolicy;
+use super::watchdog::Watchdog;
+use super::{ErrorKind, EzoError};
+
+use ezo_common::write_to_ezo;
+
+use chrono::{DateTime, Utc};
+
+use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError};
+
+/// Default I2C address of the RTD EZO chip.
+pub const DEFAULT_ADDRESS: u16 = 0x66;
+
+/// Device type reported by the RTD EZO chip to the `I` command.
+pub const DEVICE_TYPE: &str = "RTD";
+
+/// Seconds between readings, unless configured otherwise.
+pub const DEFAULT_POLL_INTERVAL: u64 = 10;
+
+/// Milliseconds to wait for the chip to settle after waking it up.
+pub const WAKE_DELAY: u64 = 300;
+
+/// Milliseconds to wait for the chip to reboot after changing its address.
+pub const REBOOT_DELAY: u64 = 1500;
+
+/// An RTD EZO chip attached to a Linux I2C bus.
+pub struct RtdSensor {
+    dev: LinuxI2CDevice,
+    path: String,
+    address: u16,
+    asleep: bool,
+    poll_interval: Duration,
+    watchdog: Option<Watchdog>,
+    latest: LatestReading,
+}
+
+impl RtdSensor {
+    /// Opens the I2C device at `path`, with the given `address`.
+    ///
+    /// If the device cannot be opened for a common reason, such as missing
+    /// permissions, the error has a `hint()` telling how to fix it.
+    pub fn new(path: &str, address: u16) -> Result<RtdSensor, RtdError> {
+        let dev = open(path, address)?;
+        Ok(RtdSensor {
+            dev,
+            path: path.to_string(),
+            address,
+            asleep: false,
+            poll_interval: Duration::from_secs(DEFAULT_POLL_INTERVAL),
+            watchdog: None,
+            latest: LatestReading::new(),
+        })
+    }
+
+    /// Closes the I2C device, and opens it again, e.g. to recover from a
+    /// bus hiccup.
+    pub fn reconnect(&mut self) -> Result<(), RtdError> {
+        self.dev = open(&self.path, self.address)?;
+        Ok(())
+    }
+
+    /// Sets the watchdog that reopens the device after too many commands
+    /// fail in a row, or removes it with `None`.
+    pub fn set_watchdog(&mut self, watchdog: Option<Watchdog>) {
+        self.watchdog = watchdog;
+    }
+
+    /// Returns the watchdog of the sensor, if any.
+    pub fn watchdog(&self) -> Option<&Watchdog> {
+        self.watchdog.as_ref()
+    }
+
+    /// Opens the I2C device for bus number `bus`, and verifies that an RTD
+    /// EZO chip responds at `address`.
+    ///
+    /// The returned error tells whether the device node is missing
+    /// (`DeviceNotFound`), could not be opened (`DeviceOpen`), the chip did
+    /// not respond (`NoResponse`), or it is not an RTD chip (`WrongDevice`).
+    /// A missing or unopenable device node has a `hint()` telling the
+    /// likely cause, e.g. a wrong bus number.
+    pub fn connect(bus: u8, address: u16) -> Result<RtdSensor, RtdError> {
+        let path = format!("/dev/i2c-{}", bus);
+        if !Path::new(&path).exists() {
+            return Err(RtdError::from(RtdErrorKind::DeviceNotFound).with_hint(missing_bus_hint()));
+        }
+        let mut sensor = RtdSensor::new(&path, address)?;
+        sensor.identify()?;
+        Ok(sensor)
+    }
+
+    // Checks that the device responds, and that it is an RTD chip.
+    fn identify(&mut self) -> Result<DeviceInfo, RtdError> {
+        let info = self.run_step(DeviceInformation, RtdErrorKind::NoResponse)?;
+        if info.device.eq_ignore_ascii_case(DEVICE_TYPE) {
+            Ok(info)
+        } else {
+            Err(RtdErrorKind::WrongDevice.into())
+        }
+    }
+
+    /// Returns a builder to open and configure a sensor in one step.
+    pub fn builder() -> RtdSensorBuilder {
+        RtdSensorBuilder::default()
+    }
+
+    /// Runs a command on the sensor, returning its response.
+    ///
+    /// If the chip was put to sleep, it is woken up before running `cmd`.
+    /// A chip that does not answer after being woken up yields an error of
+    /// kind `RtdErrorKind::Asleep`, a reply that is still pending after the
+    /// command delay one of kind `RtdErrorKind::Timeout`, and a command that
+    /// the chip refuses one of kind `RtdErrorKind::Rejected`, which names
+    /// the command. Transfers interrupted by a signal are retried, as
+    /// `retry_interrupted()` does.
+    pub fn run<C>(&mut self, cmd: C) -> Result<C::Response, RtdError>
+    where
+        C: RunOn,
+    {
+        self.run_step(cmd, RtdErrorKind::Command)
+    }
+
+    /// Runs a command on the sensor, as `run()` does, retrying it as
+    /// `policy` tells, e.g. on a flaky bus.
+    pub fn run_with_retry<C>(
+        &mut self,
+        cmd: C,
+        policy: &RetryPolicy,
+    ) -> Result<C::Response, RtdError>
+    where
+        C: RunOn,
+    {
+        policy.retry(|| self.run_ref(&cmd, RtdErrorKind::Command))
+    }
+
+    // Runs a command, reporting failures as the given `kind` of error,
+    // unless the condition of the chip tells more.
+    fn run_step<C>(&mut self, cmd: C, kind: RtdErrorKind) -> Result<C::Response, RtdError>
+    where
+        C: RunOn,
+    {
+        self.run_ref(&cmd, kind)
+    }
+
+    // Runs a command by reference, so that it can be run again.
+    fn run_ref<C>(&mut self, cmd: &C, kind: RtdErrorKind) -> Result<C::Response, RtdError>
+    where
+        C: RunOn,
+    {
+        let was_asleep = self.asleep;
+        self.wake();
+        let result = cmd.run_on(&mut self.dev);
+        let reopen = match self.watchdog {
+            Some(ref mut watchdog) => watchdog.record(result.is_ok()),
+            None => false,
+        };
+        if reopen {
+            self.recover();
+        }
+        let kind = match result {
+            Err(ref err) => device_condition(err.kind(), was_asleep).unwrap_or(kind),
+            Ok(_) => kind,
+        };
+        let response = result
+            .context(kind)
+            .map_err(|err| err.with_command(cmd.get_command_string()))?;
+        if cmd.get_command_string() == Sleep.get_command_string() {
+            self.asleep = true;
+        }
+        Ok(response)
+    }
+
+    // Reopens the device for the watchdog. Errors are not returned, since
+    // the command that failed last already returns one; the watchdog tries
+    // again after as many failures.
+    fn recover(&mut self) {
+        let mut watchdog = match self.watchdog.take() {
+            Some(watchdog) => watchdog,
+            None => return,
+        };
+        if self.restore(&watchdog).is_ok() {
+            watchdog.reconnected();
+        }
+        self.watchdog = Some(watchdog);
+    }
+
+    // Reopens the device, and checks and configures it as the watchdog
+    // tells.
+    fn restore(&mut self, watchdog: &Watchdog) -> Result<(), RtdError> {
+        self.reconnect()?;
+        if watchdog.verifies_identity() {
+            self.identify()?;
+        }
+        if let Some(config) = watchdog.config() {
+            self.apply(config)?;
+        }
+        Ok(())
+    }
+
+    /// Puts the chip to sleep. It is woken up by the next command.
+    pub fn sleep(&mut self) -> Result<(), RtdError> {
+        self.run(Sleep)
+    }
+
+    /// Returns `true` if the chip was put to sleep, and has not been woken up.
+    pub fn is_asleep(&self) -> bool {
+        self.asleep
+    }
+
+    /// Wakes the chip up, if it is asleep.
+    ///
+    /// The first transaction after sleeping only wakes the chip, and its
+    /// response is garbage; so a throwaway command is written, and the
+    /// chip is given `WAKE_DELAY` milliseconds to settle.
+    pub fn wake(&mut self) {
+        if self.asleep {
+            let _ = write_to_ezo(&mut self.dev, "I");
+            thread::sleep(Duration::from_millis(WAKE_DELAY));
+            self.asleep = false;
+        }
+    }
+
+    // Records that the chip was woken up by the caller.
+    pub(crate) fn set_awake(&mut self) {
+        self.asleep = false;
+    }
+
+    /// Changes the I2C address of the chip, and reopens the device at the
+    /// new address.
+    ///
+    /// The chip reboots after the address changes, so this waits
+    /// `REBOOT_DELAY` milliseconds and checks that the device responds at
+    /// `address` before returning. Valid addresses are `1..=127`.
+    pub fn set_i2c_address(&mut self, address: u16) -> Result<(), RtdError> {
+        if !(1..=127).contains(&address) {
+            return Err(RtdErrorKind::InvalidAddress.into());
+        }
+        self.run(DeviceAddress(address))?;
+
+        thread::sleep(Duration::from_millis(REBOOT_DELAY));
+
+        self.dev = open(&self.path, address)?;
+        self.address = address;
+
+        let _info = DeviceInformation
+            .run_on(&mut self.dev)
+            .context(RtdErrorKind::AddressVerify)?;
+        Ok(())
+    }
+
+    /// Turns the LED on.
+    pub fn led_on(&mut self) -> Result<ResponseStatus, RtdError> {
+        self.run(LedOn)
+    }
+
+    /// Turns the LED off.
+    pub fn led_off(&mut self) -> Result<ResponseStatus, RtdError> {
+        self.run(LedOff)
+    }
+
+    /// Turns the LED on or off.
+    pub fn set_led(&mut self, led: LedStatus) -> Result<ResponseStatus, RtdError> {
+        match led {
+            LedStatus::On => self.run_step(LedOn, RtdErrorKind::SetLed),
+            LedStatus::Off => self.run_step(LedOff, RtdErrorKind::SetLed),
+        }
+    }
+
+    /// Sets the temperature scale used by the chip.
+    pub fn set_scale(&mut self, scale: TemperatureScale) -> Result<ResponseStatus, RtdError> {
+        match scale {
+            TemperatureScale::Celsius => self.run_step(ScaleCelsius, RtdErrorKind::SetScale),
+            TemperatureScale::Kelvin => self.run_step(ScaleKelvin, RtdErrorKind::SetScale),
+            TemperatureScale::Fahrenheit => self.run_step(ScaleFahrenheit, RtdErrorKind::SetScale),
+        }
+    }
+
+    /// Sets the data logger interval in seconds. `0` disables the data logger.
+    pub fn set_datalogger(&mut self, seconds: u32) -> Result<ResponseStatus, RtdError> {
+        match seconds {
+            0 => self.run_step(DataloggerDisable, RtdErrorKind::SetDatalogger),
+            n => self.run_step(DataloggerPeriod(n), RtdErrorKind::SetDatalogger),
+        }
+    }
+
+    /// Enables or disables the protocol lock.
+    pub fn set_protocol_lock(
+        &mut self,
+        lock: ProtocolLockStatus,
+    ) -> Result<ResponseStatus, RtdError> {
+        match lock {
+            ProtocolLockStatus::On => {
+                self.run_step(ProtocolLockEnable, RtdErrorKind::SetProtocolLock)
+            }
+            ProtocolLockStatus::Off => {
+                self.run_step(ProtocolLockDisable, RtdErrorKind::SetProtocolLock)
+            }
+        }
+    }
+
+    /// Sets the name of the device.
+    pub fn set_name(&mut self, name: &str) -> Result<ResponseStatus, RtdError> {
+        self.run_step(Name(name.to_string()), RtdErrorKind::SetName)
+    }
+
+    /// Queries the firmware version of the chip, e.g. to work around the
+    /// behaviour of older versions.
+    pub fn firmware(&mut self) -> Result<Version, RtdError> {
+        let info = self.run(DeviceInformation)?;
+        Version::parse(&info.firmware).context(RtdErrorKind::Command)
+    }
+
+    /// Returns an error of kind `RtdErrorKind::UnsupportedFirmware` if the
+    /// firmware of the chip is older than `minimum`.
+    ///
+    /// Useful before relying on commands that older firmware lacks.
+    pub fn require_firmware(&mut self, minimum: Version) -> Result<Version, RtdError> {
+        let version = self.firmware()?;
+        if version < minimum {
+            return Err(RtdErrorKind::UnsupportedFirmware.into());
+        }
+        Ok(version)
+    }
+
+    /// Queries the supply voltage of the chip.
+    ///
+    /// `Volts::is_undervoltage()` tells whether it is too low to trust the
+    /// readings.
+    pub fn supply_voltage(&mut self) -> Result<Volts, RtdError> {
+        let status = self.run(Status)?;
+        Ok(Volts::from(&status))
+    }
+
+    /// Queries whether the LED is on or off.
+    pub fn led_state(&mut self) -> Result<LedStatus, RtdError> {
+        self.run(LedState)
+    }
+
+    /// Makes the LED blink rapidly, to locate the device.
+    pub fn find(&mut self) -> Result<ResponseStatus, RtdError> {
+        self.run(Find)
+    }
+
+    /// Path to the I2C bus device node, e.g. `/dev/i2c-1`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// I2C address of the sensor.
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// Configured time between readings, meant to be passed to `readings()`.
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    /// Mutable access to the underlying I2C device.
+    ///
+    /// Commands issued directly on the device bypass the sleep tracking.
+    pub fn device(&mut self) -> &mut LinuxI2CDevice {
+        &mut self.dev
+    }
+
+    /// Calibrates the sensor at the given temperature, which may be in
+    /// any scale.
+    ///
+    /// The device's current scale is queried first, and `temperature` is
+    /// converted to it before issuing `CAL,t`. A temperature the probe
+    /// cannot measure yields an error of kind
+    /// `RtdErrorKind::CalibrationOutOfRange`, without calibrating.
+    pub fn calibrate_at(&mut self, temperature: Temperature) -> Result<ResponseStatus, RtdError> {
+        if !TemperatureRange::PROBE.contains(&temperature) {
+            return Err(RtdErrorKind::CalibrationOutOfRange.into());
+        }
+        let scale = self.run(ScaleState)?;
+        let value = convert(temperature, scale);
+        self.run(CalibrationTemperature(value))
+    }
+
+    /// Returns an error of kind `RtdErrorKind::NotCalibrated` if the sensor
+    /// reports that it has not been calibrated.
+    ///
+    /// Useful to refuse readings from uncalibrated probes.
+    pub fn ensure_calibrated(&mut self) -> Result<(), RtdError> {
+        match self.run(CalibrationState)? {
+            CalibrationStatus::Calibrated => Ok(()),
+            CalibrationStatus::NotCalibrated => Err(RtdErrorKind::NotCalibrated.into()),
+        }
+    }
+
+    /// Takes a reading, with the current scale, and puts the chip back to
+    /// sleep.
+    ///
+    /// A sleeping chip is woken up first, so this is meant for low-power
+    /// deployments that only wake the chip to read it. A chip without a
+    /// probe yields an error of kind `RtdErrorKind::ProbeOpenCircuit`, after
+    /// being put back to sleep. So does a failed reading, whose error is
+    /// returned even if the chip cannot be put back to sleep.
+    pub fn read_and_sleep(&mut self) -> Result<Temperature, RtdError> {
+        let read = self.run(ReadingWithScale);
+        let slept = self.sleep();
+        let temperature = read?;
+        slept?;
+        self.keep_latest(temperature)
+    }
+
+    /// Returns the latest reading, if it was taken at most `max_age` ago,
+    /// or takes a fresh one, with the current scale.
+    ///
+    /// Readings take about 600 ms, so callers that share a sensor can use
+    /// this to avoid each paying for a reading within the same second.
+    /// Every reading taken by `read_cached()`, `read_and_sleep()`, or
+    /// `readings()` is cached. A chip without a probe yields an error of
+    /// kind `RtdErrorKind::ProbeOpenCircuit`, which is not cached.
+    pub fn read_cached(&mut self, max_age: Duration) -> Result<Temperature, RtdError> {
+        if let Some(temperature) = self.latest.fresh(max_age) {
+            return Ok(temperature);
+        }
+        let temperature = self.run(ReadingWithScale)?;
+        self.keep_latest(temperature)
+    }
+
+    /// Returns the cell holding the latest reading of the sensor, which
+    /// can be cloned to let other threads read it without running an I2C
+    /// transaction.
+    pub fn latest(&self) -> &LatestReading {
+        &self.latest
+    }
+
+    // Caches a reading, unless its probe circuit is open.
+    fn keep_latest(&mut self, temperature: Temperature) -> Result<Temperature, RtdError> {
+        if SensorReading(temperature.value()).is_open_circuit() {
+            return Err(RtdErrorKind::ProbeOpenCircuit.into());
+        }
+        self.latest.set(temperature);
+        Ok(temperature)
+    }
+
+    /// Queries the device's identity, status, and settings, for diagnostics.
+    pub fn health_report(&mut self) -> Result<HealthReport, RtdError> {
+        Ok(HealthReport {
+            info: self.run(DeviceInformation)?,
+            status: self.run(Status)?,
+            calibration: self.run(CalibrationState)?,
+            scale: self.run(ScaleState)?,
+            led: self.run(LedState)?,
+            protocol_lock: self.run(ProtocolLockState)?,
+            datalogger: self.run(DataloggerInterval)?,
+        })
+    }
+
+    /// Queries the device's status and main settings.
+    pub fn summary(&mut self) -> Result<Summary, RtdError> {
+        Ok(Summary {
+            status: self.run(Status)?,
+            calibration: self.run(CalibrationState)?,
+            scale: self.run(ScaleState)?,
+            datalogger: self.run(DataloggerInterval)?,
+            led: self.run(LedState)?,
+        })
+    }
+
+    /// Exports the calibration and settings of the chip.
+    ///
+    /// The exported lines are checked against the number of lines and bytes
+    /// announced by `EXPORT,?`, so that a truncated export is not mistaken
+    /// for a complete one.
+    pub fn export_config(&mut self) -> Result<ExportedConfig, RtdError> {
+        self.export_config_cancellable(&CancelToken::new())
+    }
+
+    /// Exports the calibration and settings of the chip, unless `cancel`
+    /// is triggered, which is checked before each line is requested.
+    pub fn export_config_cancellable(
+        &mut self,
+        cancel: &CancelToken,
+    ) -> Result<ExportedConfig, RtdError> {
+        cancel.check()?;
+        let info = self.run(ExportInfo)?;
+        let mut lines = Vec::new();
+        let mut done = false;
+        for _ in 0..=info.lines {
+            cancel.check()?;
+            match self.run(Export)? {
+                Exported::ExportString(line) => lines.push(line),
+                Exported::Done => {
+                    done = true;
+                    break;
+                }
+            }
+        }
+        if !done {
+            return Err(RtdErrorKind::ExportMismatch.into());
+        }
+        let config = ExportedConfig::new(lines);
+        config.check_info(&info)?;
+        Ok(config)
+    }
+
+    /// Imports calibration and settings exported from a chip.
+    ///
+    /// Every line is validated before anything is sent to the device.
+    pub fn import_config(&mut self, config: &ExportedConfig) -> Result<(), RtdError> {
+        self.import_config_cancellable(config, &CancelToken::new())
+    }
+
+    /// Imports calibration and settings exported from a chip, unless
+    /// `cancel` is triggered, which is checked before each line is sent.
+    ///
+    /// A cancelled import leaves the lines sent so far applied.
+    pub fn import_config_cancellable(
+        &mut self,
+        config: &ExportedConfig,
+        cancel: &CancelToken,
+    ) -> Result<(), RtdError> {
+        config.validate()?;
+        for line in config.lines() {
+            cancel.check()?;
+            self.run(Import(line.clone()))?;
+        }
+        Ok(())
+    }
+
+    /// Takes a snapshot of the device's settings.
+    pub fn snapshot(&mut self) -> Result<DeviceConfig, RtdError> {
+        Ok(DeviceConfig {
+            scale: self.run(ScaleState)?,
+            datalogger: self.run(DataloggerInterval)?,
+            led: self.run(LedState)?,
+            protocol_lock: self.run(ProtocolLockState)?,
+            name: self.run(NameState)?,
+        })
+    }
+
+    /// Applies the settings in `config` to the device.
+    ///
+    /// Once every setting is issued, a new snapshot is taken to verify that
+    /// they took effect, otherwise an error of kind
+    /// `RtdErrorKind::ConfigMismatch` is returned.
+    pub fn apply(&mut self, config: &DeviceConfig) -> Result<(), RtdError> {
+        self.set_name(&config.name.0)?;
+        self.set_scale(config.scale)?;
+        self.set_datalogger(config.datalogger.0)?;
+        self.set_led(config.led)?;
+        self.set_protocol_lock(config.protocol_lock)?;
+
+        if self.snapshot()? != *config {
+            return Err(RtdErrorKind::ConfigMismatch.into());
+        }
+        Ok(())
+    }
+
+    /// Lists the settings of the device that have drifted from `expected`.
+    pub fn drift_from(&mut self, expected: &DeviceConfig) -> Result<Vec<ConfigDiff>, RtdError> {
+        let actual = self.snapshot()?;
+        Ok(expected.diff(&actual))
+    }
+
+    /// Recalls the readings stored in the memory of the chip by its data
+    /// logger, from the oldest to the latest, without clearing them.
+    ///
+    /// The chip does not keep the time of its readings, so their
+    /// timestamps are estimated from the data logger interval, counting
+    /// back from now for the latest one. With the data logger disabled,
+    /// every reading is timestamped now. Readings taken without a probe
+    /// are left out.
+    pub fn recall_memory(&mut self) -> Result<Vec<TimestampedReading>, RtdError> {
+        self.drain_memory().map(|(readings, _)| readings)
+    }
+
+    // Recalls the readings in memory, as `recall_memory()` does, along with
+    // the latest location recalled. The data logger keeps storing readings
+    // while they are drained, so it may be past the last location queried.
+    pub(crate) fn drain_memory(&mut self) -> Result<(Vec<TimestampedReading>, u32), RtdError> {
+        let scale = self.run(ScaleState)?;
+        let interval = self.run(DataloggerInterval)?.as_duration();
+        let last = self.run(MemoryRecallLast)?.location;
+
+        // `M` recalls the next reading in memory, so the readings are
+        // recalled until the last location, or until it stops advancing.
+        let mut recalled = Vec::new();
+        let mut location = 0;
+        while location < last {
+            let reading = self.run(MemoryRecall)?;
+            if reading.location <= location {
+                break;
+            }
+            location = reading.location;
+            recalled.push(reading);
+        }
+
+        let readings = timestamp_recalled(recalled, last, scale, interval, Utc::now());
+        Ok((readings, location))
+    }
+
+    /// Returns an iterator that takes a reading every `interval`.
+    ///
+    /// The temperature scale is queried once, before the first reading,
+    /// and attached to every reading that follows.
+    pub fn readings(&mut self, interval: Duration) -> Readings<'_> {
+        Readings {
+            sensor: self,
+            interval,
+            sleep: false,
+            scale: None,
+            last: None,
+        }
+    }
+}
+
+// Opens the I2C device at `path`, hinting at the fix of common failures.
+fn open(path: &str, address: u16) -> Result<LinuxI2CDevice, RtdError> {
+    let result = LinuxI2CDevice::new(path, address);
+    let hint = match result {
+        Err(ref err) => open_hint(err),
+        Ok(_) => None,
+    };
+    result
+        .context(RtdErrorKind::DeviceOpen)
+        .map_err(|err| match hint {
+            Some(hint) => err.with_hint(hint),
+            None => err,
+        })
+}
+
+// Tells the likely cause of failing to open an I2C device node, and its
+// fix, for the failures that users run into while setting up the bus.
+fn open_hint(err: &LinuxI2CError) -> Option<&'static str> {
+    match *err {
+        LinuxI2CError::Io(ref err) => match err.kind() {
+            io::ErrorKind::PermissionDenied => Some(
+                "permission denied; add the user to the `i2c` group, e.g. with \
+                 `sudo usermod -aG i2c $USER`, and log in again",
+            ),
+            io::ErrorKind::NotFound => Some(missing_bus_hint()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// The device nodes of every bus are created by the `i2c-dev` module, so a
+// missing node is a wrong bus number only if the module is loaded.
+fn missing_bus_hint() -> &'static str {
+    if Path::new("/sys/class/i2c-dev").exists() {
+        "no such bus; check the bus number, `i2cdetect -l` lists the available buses"
+    } else {
+        "the `i2c-dev` kernel module is not loaded; enable I2C with `raspi-config`, \
+         or load it with `sudo modprobe i2c-dev`"
+    }
+}
+
+// Tells the condition of the chip that made a command fail, if it is more
+// telling than the step that failed.
+fn device_condition(kind: ErrorKind, was_asleep: bool) -> Option<RtdErrorKind> {
+    match kind {
+        ErrorKind::PendingResponse => Some(RtdErrorKind::Timeout),
+        ErrorKind::DeviceErrorResponse => Some(RtdErrorKind::Rejected),
+        ErrorKind::I2CRead | ErrorKind::UnwritableCommand if was_asleep => {
+            Some(RtdErrorKind::Asleep)
+        }
+        _ => None,
+    }
+}
+
+// Converts `temperature` to a value in the given `scale`, widened to the
+// `f64` of `CalibrationTemperature` when readings are `f32`.
+#[allow(clippy::useless_conversion)]
+fn convert(temperature: Temperature, scale: TemperatureScale) -> f64 {
+    f64::from(temperature.to_scale(scale).value())
+}
+
+/// A temperature, along with the time at which it was read.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TimestampedReading {
+    pub timestamp: DateTime<Utc>,
+    pub temperature: Temperature,
+}
+
+/// Iterator over periodic readings of an `RtdSensor`.
+///
+/// This iterator never ends. Errors are yielded as they happen, so that
+/// the caller may decide whether to keep reading.
+pub struct Readings<'a> {
+    sensor: &'a mut RtdSensor,
+    interval: Duration,
+    sleep: bool,
+    scale: Option<TemperatureScale>,
+    last: Option<Instant>,
+}
+
+impl<'a> Readings<'a> {
+    /// Puts the chip to sleep between readings, to save power.
+    pub fn sleep_between(mut self, sleep: bool) -> Readings<'a> {
+        self.sleep = sleep;
+        self
+    }
+
+    fn read(&mut self) -> Result<TimestampedReading, RtdError> {
+        let scale = match self.scale {
+            Some(scale) => scale,
+            None => self.sensor.run(ScaleState)?,
+        };
+        self.scale = Some(scale);
+
+        let reading = self.sensor.run(Reading)?;
+        let timestamp = Utc::now();
+
+        if self.sleep {
+            self.sensor.sleep()?;
+        }
+
+        Ok(TimestampedReading {
+            timestamp,
+            temperature: self.sensor.keep_latest(reading.with_scale(scale))?,
+        })
+    }
+}
+
+impl<'a> Iterator for Readings<'a> {
+    type Item = Result<TimestampedReading, RtdError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(last) = self.last {
+            let elapsed = last.elapsed();
+            if elapsed < self.interval {
+                thread::sleep(self.interval - elapsed);
+            }
+        }
+        self.last = Some(Instant::now());
+        Some(self.read())
+    }
+}
+
+/// Builder for an `RtdSensor`.
+///
+/// The device is opened, and the requested settings applied, by `build()`.
+/// Settings that are not specified are left untouched on the chip.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RtdSensorBuilder {
+    path: Option<String>,
+    address: u16,
+    scale: Option<TemperatureScale>,
+    datalogger: Option<u32>,
+    led: Option<LedStatus>,
+    poll_interval: Option<Duration>,
+    watchdog: Option<Watchdog>,
+}
+
+impl Default for RtdSensorBuilder {
+    fn default() -> RtdSensorBuilder {
+        RtdSensorBuilder {
+            path: None,
+            address: DEFAULT_ADDRESS,
+            scale: None,
+            datalogger: None,
+            led: None,
+            poll_interval: None,
+            watchdog: None,
+        }
+    }
+}
+
+impl RtdSensorBuilder {
+    /// Uses the I2C bus device node at `path`, e.g. `/dev/i2c-1`.
+    pub fn path<P: Into<String>>(mut self, path: P) -> RtdSensorBuilder {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Uses the I2C bus number `bus`, i.e. `/dev/i2c-{bus}`.
+    pub fn bus(mut self, bus: u8) -> RtdSensorBuilder {
+        self.path = Some(format!("/dev/i2c-{}", bus));
+        self
+    }
+
+    /// Sets the I2C address of the sensor. Defaults to `DEFAULT_ADDRESS`.
+    pub fn address(mut self, address: u16) -> RtdSensorBuilder {
+        self.address = address;
+        self
+    }
+
+    /// Sets the temperature scale used by the sensor.
+    pub fn scale(mut self, scale: TemperatureScale) -> RtdSensorBuilder {
+        self.scale = Some(scale);
+        self
+    }
+
+    /// Sets the data logger interval in seconds. `0` disables the data logger.
+    pub fn datalogger(mut self, seconds: u32) -> RtdSensorBuilder {
+        self.datalogger = Some(seconds);
+        self
+    }
+
+    /// Turns the LED on or off.
+    pub fn led(mut self, led: LedStatus) -> RtdSensorBuilder {
+        self.led = Some(led);
+        self
+    }
+
+    /// Sets the time between readings. Defaults to `DEFAULT_POLL_INTERVAL`.
+    pub fn poll_interval(mut self, interval: Duration) -> RtdSensorBuilder {
+        self.poll_interval = Some(interval);
+        self
+    }
+
+    /// Sets the watchdog that reopens the device after too many commands
+    /// fail in a row. It starts watching once the sensor is built.
+    pub fn watchdog(mut self, watchdog: Watchdog) -> RtdSensorBuilder {
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    /// Opens the device and applies the configuration.
+    ///
+    /// The returned error identifies the step that failed.
+    pub fn build(self) -> Result<RtdSensor, RtdError> {
+        let path = match self.path {
+            Some(ref path) => path.clone(),
+            None => return Err(RtdErrorKind::MissingBus.into()),
+        };
+
+        let mut sensor = RtdSensor::new(&path, self.address)?;
+
+        if let Some(interval) = self.poll_interval {
+            sensor.poll_interval = interval;
+        }
+
+        if let Some(scale) = self.scale {
+            let _scale = sensor.set_scale(scale)?;
+        }
+
+        if let Some(seconds) = self.datalogger {
+            let _datalogger = sensor.set_datalogger(seconds)?;
+        }
+
+        if let Some(led) = self.led {
+            let _led = sensor.set_led(led)?;
+        }
+
+        sensor.watchdog = self.watchdog;
+
+        Ok(sensor)
+    }
+}
+
+// Timestamps the readings recalled from memory one data logger interval
+// apart, counting back from `now` for the latest location, which is `last`
+// unless the data logger stored more readings as they were recalled.
+fn timestamp_recalled(
+    recalled: Vec<MemoryReading>,
+    last: u32,
+    scale: TemperatureScale,
+    interval: Duration,
+    now: DateTime<Utc>,
+) -> Vec<TimestampedReading> {
+    let latest = recalled
+        .iter()
+        .map(|reading| reading.location)
+        .fold(last, u32::max);
+    let interval =
+        chrono::Duration::from_std(interval).unwrap_or_else(|_| chrono::Duration::zero());
+    recalled
+        .into_iter()
+        .filter(|reading| !SensorReading(reading.reading).is_open_circuit())
+        .map(|reading| {
+            let (location, temperature) = reading.with_scale(scale);
+            TimestampedReading {
+                timestamp: now - interval * (latest - location) as i32,
+                temperature,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_defaults_to_rtd_address() {
+        let builder = RtdSensor::builder();
+        assert_eq!(builder.address, DEFAULT_ADDRESS);
+        assert_eq!(builder.path, None);
+    }
+
+    #[test]
+    fn builder_maps_bus_number_to_device_path() {
+        let builder = RtdSensor::builder().bus(1);
+        assert_eq!(builder.path, Some("/dev/i2c-1".to_string()));
+    }
+
+    #[test]
+    fn converts_calibration_temperature_to_device_scale() {
+        let temp = Temperature::Celsius(100.0);
+        assert_eq!(convert(temp, TemperatureScale::Celsius), 100.0);
+        // Off by the rounding of `373.15` in `f32`, with the `f32` feature.
+        assert!((convert(temp, TemperatureScale::Kelvin) - 373.15).abs() < 1e-4);
+        assert_eq!(convert(temp, TemperatureScale::Fahrenheit), 212.0);
+
+        let temp = Temperature::Fahrenheit(32.0);
+        assert_eq!(convert(temp, TemperatureScale::Celsius), 0.0);
+    }
+
+    #[test]
+    fn timestamps_readings_stored_while_draining_memory() {
+        let now: DateTime<Utc> = "2018-07-14T12:00:00Z".parse().unwrap();
+        let minutes = chrono::Duration::minutes;
+        // `M,?` told 2, but the data logger stored a third reading, and a
+        // reading without a probe, before the memory was drained.
+        let recalled = vec![
+            MemoryReading {
+                location: 1,
+                reading: 20.0,
+            },
+            MemoryReading {
+                location: 2,
+                reading: -1023.0,
+            },
+            MemoryReading {
+                location: 3,
+                reading: 21.0,
+            },
+        ];
+        let readings = timestamp_recalled(
+            recalled,
+            2,
+            TemperatureScale::Celsius,
+            Duration::from_secs(60),
+            now,
+        );
+        assert_eq!(
+            readings,
+            [
+                TimestampedReading {
+                    timestamp: now - minutes(2),
+                    temperature: Temperature::Celsius(20.0),
+                },
+                TimestampedReading {
+                    timestamp: now,
+                    temperature: Temperature::Celsius(21.0),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn tells_device_conditions_apart_from_failed_steps() {
+        assert_eq!(
+            device_condition(ErrorKind::PendingResponse, false),
+            Some(RtdErrorKind::Timeout)
+        );
+        assert_eq!(
+            device_condition(ErrorKind::I2CRead, true),
+            Some(RtdErrorKind::Asleep)
+        );
+        assert_eq!(device_condition(ErrorKind::I2CRead, false), None);
+        assert_eq!(
+            device_condition(ErrorKind::DeviceErrorResponse, false),
+            Some(RtdErrorKind::Rejected)
+        );
+        assert_eq!(device_condition(ErrorKind::ResponseParse, true), None);
+    }
+
+    #[test]
+    fn connecting_to_missing_bus_yields_device_not_found_error() {
+        let err = RtdSensor::connect(255, DEFAULT_ADDRESS).err().unwrap();
+        assert_eq!(err.kind(), RtdErrorKind::DeviceNotFound);
+        assert_eq!(err.hint(), Some(missing_bus_hint()));
+    }
+
+    #[test]
+    fn opening_bus_without_permission_hints_at_group() {
+        let err = LinuxI2CError::Io(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(open_hint(&err).unwrap().contains("`i2c` group"));
+
+        let err = RtdSensor::new("/dev/i2c-255", DEFAULT_ADDRESS)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), RtdErrorKind::DeviceOpen);
+        assert_eq!(err.hint(), Some(missing_bus_hint()));
+    }
+
+    #[test]
+    fn builder_without_bus_yields_missing_bus_error() {
+        let err = RtdSensor::builder().address(101).build().err().unwrap();
+        assert_eq!(err.kind(), RtdErrorKind::MissingBus);
+    }
+}
@@ -0,0 +1,171 @@
+//! Sensor settings read from the environment, or from a configuration file.
+use std::convert::TryFrom;
+use std::env;
+#[cfg(feature = "toml")]
+use std::fs;
+#[cfg(feature = "toml")]
+use std::path::Path;
+use std::time::Duration;
+
+use super::errors::{ResultExt, RtdError, RtdErrorKind};
+use super::response::TemperatureScale;
+use super::sensor::{RtdSensor, RtdSensorBuilder};
+
+/// Prefix of the environment variables read by `from_env()`, e.g.
+/// `EZO_RTD_BUS`, `EZO_RTD_ADDRESS`, `EZO_RTD_SCALE`, `EZO_RTD_INTERVAL`.
+pub const ENV_PREFIX: &str = "EZO_RTD_";
+
+impl RtdSensorBuilder {
+    /// Reads the sensor settings from environment variables.
+    ///
+    /// `EZO_RTD_BUS` is the bus number, or `EZO_RTD_PATH` the path to the
+    /// device node. `EZO_RTD_ADDRESS` may be decimal or `0x`-prefixed hex,
+    /// `EZO_RTD_SCALE` one of `c`, `k`, `f`, and `EZO_RTD_INTERVAL` the
+    /// seconds between readings. All but the bus are optional.
+    pub fn from_env() -> Result<RtdSensorBuilder, RtdError> {
+        from_lookup(|key| env::var(format!("{}{}", ENV_PREFIX, key.to_uppercase())).ok())
+    }
+
+    /// Reads the sensor settings from a TOML file, with the keys `bus` (or
+    /// `path`), `address`, `scale`, and `interval`, as in `from_env()`.
+    ///
+    /// ```toml
+    /// bus = 1
+    /// address = 0x66
+    /// scale = "celsius"
+    /// interval = 10
+    /// ```
+    #[cfg(feature = "toml")]
+    pub fn from_config_file<P: AsRef<Path>>(path: P) -> Result<RtdSensorBuilder, RtdError> {
+        let contents = fs::read_to_string(path).context(RtdErrorKind::ConfigFile)?;
+        let table = contents
+            .parse::<toml::Value>()
+            .context(RtdErrorKind::ConfigFile)?;
+        from_lookup(|key| match table.get(key) {
+            Some(toml::Value::String(s)) => Some(s.clone()),
+            Some(toml::Value::Integer(i)) => Some(i.to_string()),
+            _ => None,
+        })
+    }
+}
+
+impl RtdSensor {
+    /// Opens and configures a sensor from environment variables.
+    ///
+    /// See `RtdSensorBuilder::from_env()` for the variables read.
+    pub fn from_env() -> Result<RtdSensor, RtdError> {
+        RtdSensorBuilder::from_env()?.build()
+    }
+
+    /// Opens and configures a sensor from a TOML file.
+    ///
+    /// See `RtdSensorBuilder::from_config_file()` for the keys read.
+    #[cfg(feature = "toml")]
+    pub fn from_config_file<P: AsRef<Path>>(path: P) -> Result<RtdSensor, RtdError> {
+        RtdSensorBuilder::from_config_file(path)?.build()
+    }
+}
+
+// Builds the settings from a function that returns the value of each key.
+fn from_lookup<F>(lookup: F) -> Result<RtdSensorBuilder, RtdError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut builder = RtdSensorBuilder::default();
+
+    if let Some(path) = lookup("path") {
+        builder = builder.path(path);
+    }
+    if let Some(bus) = lookup("bus") {
+        builder = builder.bus(parse_setting(&bus)?);
+    }
+    if let Some(address) = lookup("address") {
+        let address = if address.starts_with("0x") || address.starts_with("0X") {
+            u16::from_str_radix(&address[2..], 16).ok()
+        } else {
+            address.parse().ok()
+        };
+        match address {
+            Some(address) => builder = builder.address(address),
+            None => return Err(RtdErrorKind::InvalidSetting.into()),
+        }
+    }
+    if let Some(scale) = lookup("scale") {
+        builder = builder.scale(parse_scale(&scale)?);
+    }
+    if let Some(interval) = lookup("interval") {
+        builder = builder.poll_interval(Duration::from_secs(parse_setting(&interval)?));
+    }
+    Ok(builder)
+}
+
+fn parse_setting<T: ::std::str::FromStr>(value: &str) -> Result<T, RtdError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| RtdErrorKind::InvalidSetting.into())
+}
+
+fn parse_scale(value: &str) -> Result<TemperatureScale, RtdError> {
+    TemperatureScale::try_from(value).map_err(|_| RtdErrorKind::InvalidSetting.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn lookup_in(vars: &[(&str, &str)]) -> Result<RtdSensorBuilder, RtdError> {
+        let map: HashMap<String, String> = vars
+            .iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        from_lookup(|key| map.get(key).cloned())
+    }
+
+    #[test]
+    fn reads_settings_from_lookup() {
+        let builder = lookup_in(&[
+            ("bus", "1"),
+            ("address", "0x65"),
+            ("scale", "K"),
+            ("interval", "30"),
+        ])
+        .unwrap();
+        assert_eq!(
+            builder,
+            RtdSensor::builder()
+                .bus(1)
+                .address(101)
+                .scale(TemperatureScale::Kelvin)
+                .poll_interval(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn reads_decimal_address_and_scale_names() {
+        let builder = lookup_in(&[
+            ("path", "/dev/i2c-3"),
+            ("address", "99"),
+            ("scale", "fahrenheit"),
+        ])
+        .unwrap();
+        assert_eq!(
+            builder,
+            RtdSensor::builder()
+                .path("/dev/i2c-3")
+                .address(99)
+                .scale(TemperatureScale::Fahrenheit)
+        );
+    }
+
+    #[test]
+    fn reading_invalid_settings_yields_error() {
+        let err = lookup_in(&[("bus", "one")]).err().unwrap();
+        assert_eq!(err.kind(), RtdErrorKind::InvalidSetting);
+
+        assert!(lookup_in(&[("address", "0xZZ")]).is_err());
+        assert!(lookup_in(&[("scale", "rankine")]).is_err());
+        assert!(lookup_in(&[("interval", "-1")]).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! Local history of readings and device events in a SQLite database.
+//!
+//! A `SqliteStore` is a `Sink` for the host datalogger, which keeps every
+//! reading in the `readings` table, along with device events, such as
+//! calibrations and restarts, in the `events` table:
+//!
+//! ```sql
+//! CREATE TABLE readings (
+//!     id INTEGER PRIMARY KEY,
+//!     timestamp_ms INTEGER NOT NULL,  -- milliseconds since the Unix epoch
+//!     address INTEGER NOT NULL,       -- I2C address of the chip
+//!     value REAL NOT NULL,
+//!     scale TEXT NOT NULL,            -- e.g. 'celsius'
+//!     recovered INTEGER NOT NULL      -- 1 if recovered by `backfill()`
+//! );
+//! CREATE TABLE events (
+//!     id INTEGER PRIMARY KEY,
+//!     timestamp_ms INTEGER NOT NULL,
+//!     address INTEGER NOT NULL,
+//!     kind TEXT NOT NULL,             -- e.g. 'calibration'
+//!     detail TEXT NOT NULL
+//! );
+//! ```
+//!
+//! Rows older than a given age, or beyond a given count, can be pruned as
+//! new ones are written, so that a gateway keeps a bounded history.
+use std::convert::TryFrom;
+use std::path::Path;
+use std::time::Duration;
+
+use super::errors::{ResultExt, RtdError, RtdErrorKind};
+use super::logger::Sink;
+use super::sensor::TimestampedReading;
+
+use chrono::Utc;
+
+use rusqlite::{params, Connection};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS readings (
+        id INTEGER PRIMARY KEY,
+        timestamp_ms INTEGER NOT NULL,
+        address INTEGER NOT NULL,
+        value REAL NOT NULL,
+        scale TEXT NOT NULL,
+        recovered INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX IF NOT EXISTS readings_timestamp ON readings (timestamp_ms);
+    CREATE TABLE IF NOT EXISTS events (
+        id INTEGER PRIMARY KEY,
+        timestamp_ms INTEGER NOT NULL,
+        address INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        detail TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS events_timestamp ON events (timestamp_ms);
+";
+
+/// A device event kept in the `events` table.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    /// The chip was calibrated.
+    Calibration,
+    /// The calibration of the chip was cleared.
+    CalibrationCleared,
+    /// The chip restarted, e.g. after a power loss.
+    Restart,
+    /// The I2C device was reopened, e.g. by a `Watchdog`.
+    Reconnect,
+}
+
+impl EventKind {
+    /// Returns the name kept in the `kind` column, e.g. `calibration`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            EventKind::Calibration => "calibration",
+            EventKind::CalibrationCleared => "calibration-cleared",
+            EventKind::Restart => "restart",
+            EventKind::Reconnect => "reconnect",
+        }
+    }
+}
+
+/// Writes readings and device events into a SQLite database.
+///
+/// By default, nothing is pruned.
+pub struct SqliteStore {
+    conn: Connection,
+    max_age: Option<Duration>,
+    max_readings: Option<u64>,
+}
+
+impl SqliteStore {
+    /// Opens the database at `path`, creating it and its tables if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteStore, RtdError> {
+        let conn = Connection::open(path).context(RtdErrorKind::LogWrite)?;
+        SqliteStore::with_connection(conn)
+    }
+
+    /// Keeps the database in memory, e.g. for tests.
+    pub fn open_in_memory() -> Result<SqliteStore, RtdError> {
+        let conn = Connection::open_in_memory().context(RtdErrorKind::LogWrite)?;
+        SqliteStore::with_connection(conn)
+    }
+
+    /// Uses an open connection, creating the tables if needed.
+    pub fn with_connection(conn: Connection) -> Result<SqliteStore, RtdError> {
+        conn.execute_batch(SCHEMA).context(RtdErrorKind::LogWrite)?;
+        Ok(SqliteStore {
+            conn,
+            max_age: None,
+            max_readings: None,
+        })
+    }
+
+    /// Prunes readings and events older than `max_age`.
+    pub fn keep_for(mut self, max_age: Duration) -> SqliteStore {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Prunes the oldest readings beyond the latest `max_readings`, by
+    /// timestamp, as recovered readings may be written late.
+    pub fn keep_readings(mut self, max_readings: u64) -> SqliteStore {
+        self.max_readings = Some(max_readings);
+        self
+    }
+
+    /// The connection to the database, e.g. to query the history.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Keeps a device event of the chip at `address`, with free-form
+    /// details, e.g. the calibration temperature.
+    pub fn log_event(
+        &mut self,
+        address: u16,
+        kind: EventKind,
+        detail: &str,
+    ) -> Result<(), RtdError> {
+        self.conn
+            .execute(
+                "INSERT INTO events (timestamp_ms, address, kind, detail) VALUES (?1, ?2, ?3, ?4)",
+                params![Utc::now().timestamp_millis(), address, kind.name(), detail],
+            )
+            .context(RtdErrorKind::LogWrite)?;
+        self.prune()
+    }
+
+    // Keeps a reading, then prunes the old ones.
+    fn insert(
+        &mut self,
+        address: u16,
+        reading: &TimestampedReading,
+        recovered: bool,
+    ) -> Result<(), RtdError> {
+        self.conn
+            .execute(
+                "INSERT INTO readings (timestamp_ms, address, value, scale, recovered) \
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    reading.timestamp.timestamp_millis(),
+                    address,
+                    reading.temperature.value(),
+                    reading.temperature.scale().to_string(),
+                    recovered
+                ],
+            )
+            .context(RtdErrorKind::LogWrite)?;
+        self.prune()
+    }
+
+    /// Deletes the rows that are beyond the retention limits.
+    pub fn prune(&mut self) -> Result<(), RtdError> {
+        if let Some(max_age) = self.max_age {
+            let max_age_ms = i64::try_from(max_age.as_millis()).unwrap_or(i64::MAX);
+            let oldest = Utc::now().timestamp_millis().saturating_sub(max_age_ms);
+            for table in ["readings", "events"].iter() {
+                self.conn
+                    .execute(
+                        &format!("DELETE FROM {} WHERE timestamp_ms < ?1", table),
+                        params![oldest],
+                    )
+                    .context(RtdErrorKind::LogWrite)?;
+            }
+        }
+        if let Some(max_readings) = self.max_readings {
+            let max_readings = i64::try_from(max_readings).unwrap_or(i64::MAX);
+            self.conn
+                .execute(
+                    "DELETE FROM readings WHERE id NOT IN \
+                     (SELECT id FROM readings ORDER BY timestamp_ms DESC, id DESC LIMIT ?1)",
+                    params![max_readings],
+                )
+                .context(RtdErrorKind::LogWrite)?;
+        }
+        Ok(())
+    }
+}
+
+impl Sink for SqliteStore {
+    fn log(&mut self, address: u16, reading: &TimestampedReading) -> Result<(), RtdError> {
+        self.insert(address, reading, false)
+    }
+
+    fn log_recovered(
+        &mut self,
+        address: u16,
+        reading: &TimestampedReading,
+    ) -> Result<(), RtdError> {
+        self.insert(address, reading, true)
+    }
+
+    /// Every row is committed as it is written, so there is nothing to
+    /// flush.
+    fn flush(&mut self) -> Result<(), RtdError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::{Float, Temperature};
+    use chrono::Duration as Age;
+
+    fn count(store: &SqliteStore, table: &str) -> i64 {
+        store
+            .connection()
+            .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+    }
+
+    fn reading(age: Age, value: Float) -> TimestampedReading {
+        TimestampedReading {
+            timestamp: Utc::now() - age,
+            temperature: Temperature::Celsius(value),
+        }
+    }
+
+    #[test]
+    fn keeps_readings_and_events() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        store.log(0x66, &reading(Age::zero(), 21.5)).unwrap();
+        store
+            .log_event(0x66, EventKind::Calibration, "CAL,25.00")
+            .unwrap();
+
+        let (address, value, scale): (u16, f64, String) = store
+            .connection()
+            .query_row("SELECT address, value, scale FROM readings", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!((address, value, scale.as_str()), (0x66, 21.5, "celsius"));
+
+        store
+            .log_recovered(0x66, &reading(Age::hours(1), 20.0))
+            .unwrap();
+        let recovered: i64 = store
+            .connection()
+            .query_row("SELECT COUNT(*) FROM readings WHERE recovered", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(recovered, 1);
+
+        let kind: String = store
+            .connection()
+            .query_row("SELECT kind FROM events", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(kind, "calibration");
+    }
+
+    #[test]
+    fn prunes_old_and_excess_readings() {
+        let mut store = SqliteStore::open_in_memory()
+            .unwrap()
+            .keep_for(Duration::from_secs(3600));
+        store.log(0x66, &reading(Age::hours(2), 20.0)).unwrap();
+        store.log(0x66, &reading(Age::zero(), 21.0)).unwrap();
+        assert_eq!(count(&store, "readings"), 1);
+
+        let mut store = store.keep_readings(2);
+        for value in [22.0, 23.0, 24.0].iter() {
+            store.log(0x66, &reading(Age::zero(), *value)).unwrap();
+        }
+        assert_eq!(count(&store, "readings"), 2);
+        let oldest: f64 = store
+            .connection()
+            .query_row("SELECT MIN(value) FROM readings", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(oldest, 23.0);
+    }
+}
@@ -0,0 +1,191 @@
+//! Statistics of the latest readings of a sensor.
+//!
+//! A `Stats` accumulator keeps the temperatures of a window of readings,
+//! either the latest ones or those of a recent time span, and sums them up
+//! in a `StatsSummary` with their minimum, maximum, mean, and standard
+//! deviation, e.g. to compute hourly roll-ups without exporting every
+//! reading.
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use super::response::{Float, Temperature, TemperatureDelta};
+#[cfg(feature = "linux-i2c")]
+use super::sensor::TimestampedReading;
+
+use chrono::{DateTime, Utc};
+
+/// The readings that a `Stats` accumulator sums up.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Window {
+    /// The latest readings, up to the given number, and at least one.
+    Count(usize),
+    /// The readings taken within the given time of the latest one.
+    Span(Duration),
+}
+
+/// The statistics of a window of readings, in the scale of the latest one.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StatsSummary {
+    pub count: usize,
+    pub min: Temperature,
+    pub max: Temperature,
+    pub mean: Temperature,
+    /// The population standard deviation of the temperatures.
+    pub std_dev: TemperatureDelta,
+    pub first: DateTime<Utc>,
+    pub last: DateTime<Utc>,
+}
+
+/// Accumulates the temperatures of a window of readings.
+#[derive(Clone, Debug)]
+pub struct Stats {
+    window: Window,
+    samples: VecDeque<(DateTime<Utc>, Temperature)>,
+}
+
+impl Stats {
+    /// Sums up the readings within `window`.
+    pub fn new(window: Window) -> Stats {
+        let window = match window {
+            Window::Count(count) => Window::Count(count.max(1)),
+            span => span,
+        };
+        Stats {
+            window,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Returns the window of readings summed up.
+    pub fn window(&self) -> Window {
+        self.window
+    }
+
+    /// Returns the number of readings in the window.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns `true` if there are no readings in the window.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Adds a temperature read at `timestamp`, dropping the readings that
+    /// fall out of the window.
+    ///
+    /// Readings are expected in order; the span of a time window is
+    /// measured back from the latest timestamp pushed.
+    pub fn push(&mut self, temperature: Temperature, timestamp: DateTime<Utc>) {
+        self.samples.push_back((timestamp, temperature));
+        match self.window {
+            Window::Count(count) => {
+                while self.samples.len() > count {
+                    self.samples.pop_front();
+                }
+            }
+            Window::Span(span) => {
+                let oldest = chrono::Duration::from_std(span)
+                    .ok()
+                    .and_then(|span| timestamp.checked_sub_signed(span));
+                if let Some(oldest) = oldest {
+                    while let Some(&(first, _)) = self.samples.front() {
+                        if first >= oldest {
+                            break;
+                        }
+                        self.samples.pop_front();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds a timestamped reading, as `push()` does.
+    #[cfg(feature = "linux-i2c")]
+    pub fn push_reading(&mut self, reading: &TimestampedReading) {
+        self.push(reading.temperature, reading.timestamp);
+    }
+
+    /// Drops every reading, e.g. to start the next roll-up.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Returns the statistics of the readings in the window, if any.
+    pub fn summary(&self) -> Option<StatsSummary> {
+        let &(first, _) = self.samples.front()?;
+        let &(last, latest) = self.samples.back()?;
+        let scale = latest.scale();
+        let values: Vec<Float> = self
+            .samples
+            .iter()
+            .map(|&(_, t)| t.to_scale(scale).value())
+            .collect();
+
+        let count = values.len();
+        let min = values.iter().copied().fold(Float::INFINITY, Float::min);
+        let max = values.iter().copied().fold(Float::NEG_INFINITY, Float::max);
+        let mean = values.iter().sum::<Float>() / count as Float;
+        let variance = values
+            .iter()
+            .map(|v| (v - mean) * (v - mean))
+            .sum::<Float>()
+            / count as Float;
+
+        Some(StatsSummary {
+            count,
+            min: Temperature::new(scale, min),
+            max: Temperature::new(scale, max),
+            mean: Temperature::new(scale, mean),
+            std_dev: TemperatureDelta::new(scale, variance.sqrt()),
+            first,
+            last,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::TemperatureScale;
+
+    fn at(timestamp: &str) -> DateTime<Utc> {
+        timestamp.parse().unwrap()
+    }
+
+    #[test]
+    fn sums_up_the_latest_readings() {
+        let mut stats = Stats::new(Window::Count(4));
+        assert_eq!(stats.summary(), None);
+        for (i, &value) in [99.0, 2.0, 4.0, 4.0, 6.0].iter().enumerate() {
+            let timestamp = at("2018-07-14T12:00:00Z") + chrono::Duration::minutes(i as i64);
+            stats.push(Temperature::Celsius(value), timestamp);
+        }
+
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.count, 4);
+        assert_eq!(summary.min.value(), 2.0);
+        assert_eq!(summary.max.value(), 6.0);
+        assert_eq!(summary.mean.value(), 4.0);
+        assert!((summary.std_dev.degrees() - Float::sqrt(2.0)).abs() < 1e-6);
+        assert_eq!(summary.first, at("2018-07-14T12:01:00Z"));
+        assert_eq!(summary.last, at("2018-07-14T12:04:00Z"));
+    }
+
+    #[test]
+    fn sums_up_a_time_span_in_the_latest_scale() {
+        let mut stats = Stats::new(Window::Span(Duration::from_secs(3600)));
+        stats.push(Temperature::Celsius(50.0), at("2018-07-14T10:00:00Z"));
+        stats.push(Temperature::Celsius(0.0), at("2018-07-14T11:30:00Z"));
+        stats.push(Temperature::Kelvin(283.15), at("2018-07-14T12:00:00Z"));
+
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.count, 2);
+        assert_eq!(summary.mean.scale(), TemperatureScale::Kelvin);
+        assert!((summary.mean.value() - 278.15).abs() < 1e-3);
+        assert_eq!(summary.first, at("2018-07-14T11:30:00Z"));
+
+        stats.clear();
+        assert!(stats.is_empty());
+    }
+}
@@ -0,0 +1,239 @@
+//! Commands over type-erased I2C devices.
+//!
+//! `RunOn::run_on` is generic over the device, so it cannot be called
+//! through a trait object. `Transport` erases the device and its error
+//! type, so that devices of different kinds can be kept together, e.g. in a
+//! `Vec<Box<dyn Transport>>`, and any command can be run on each of them.
+//!
+//! A device wrapped in `Traced` passes every transaction to a hook, which
+//! can log, record, or assert on the traffic.
+use std::thread;
+use std::time::Duration;
+
+use super::protocol::{
+    decode_reply, encode_command, retry_interrupted, transfer_error, Reply, RunOn, WriteCommand,
+    MAX_COMMAND, MAX_REPLY, WRITE_RETRY_DELAY,
+};
+use super::{ErrorKind, EzoError};
+
+use i2cdev::core::I2CDevice;
+
+/// An object-safe I2C device, whose errors are turned into `EzoError`s.
+///
+/// Every `I2CDevice` is a `Transport`, whose interrupted transfers are
+/// retried, as `retry_interrupted()` does.
+pub trait Transport {
+    /// Writes `bytes` to the device.
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), EzoError>;
+
+    /// Fills `buf` with bytes read from the device.
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(), EzoError>;
+}
+
+impl<D> Transport for D
+where
+    D: I2CDevice,
+    D::Error: Send + Sync + 'static,
+{
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), EzoError> {
+        retry_interrupted(|| self.write(bytes))
+            .map_err(|err| transfer_error(err, ErrorKind::UnwritableCommand))?;
+        Ok(())
+    }
+
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(), EzoError> {
+        retry_interrupted(|| self.read(buf))
+            .map_err(|err| transfer_error(err, ErrorKind::I2CRead))?;
+        Ok(())
+    }
+}
+
+impl dyn Transport + '_ {
+    /// Runs the command on the device, as `RunOn::run_dyn` does.
+    pub fn run<C: RunOn>(&mut self, cmd: &C) -> Result<C::Response, EzoError> {
+        cmd.run_dyn(self)
+    }
+}
+
+/// A transaction seen by the hook of a `Traced` device.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Traffic<'a> {
+    /// Bytes written to the device: a command string and its NUL byte.
+    Write(&'a [u8]),
+    /// Raw buffer read from the device, starting with the response code.
+    Read(&'a [u8]),
+}
+
+/// A device that passes every write, and every successful read, to a hook.
+#[derive(Debug)]
+pub struct Traced<T, F> {
+    inner: T,
+    hook: F,
+}
+
+impl<T: Transport, F: FnMut(Traffic<'_>)> Traced<T, F> {
+    /// Wraps `inner`, calling `hook` on each of its transactions.
+    pub fn new(inner: T, hook: F) -> Traced<T, F> {
+        Traced { inner, hook }
+    }
+
+    /// Mutable access to the wrapped device.
+    ///
+    /// Transactions issued directly on it are not traced.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Gives back the wrapped device.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Transport, F: FnMut(Traffic<'_>)> Transport for Traced<T, F> {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), EzoError> {
+        (self.hook)(Traffic::Write(bytes));
+        self.inner.write_bytes(bytes)
+    }
+
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(), EzoError> {
+        self.inner.read_bytes(buf)?;
+        (self.hook)(Traffic::Read(buf));
+        Ok(())
+    }
+}
+
+/// Writes the command string of `cmd` to the device, followed by a NUL
+/// byte.
+///
+/// A failed write is retried once, after `WRITE_RETRY_DELAY` milliseconds.
+pub fn write_command<C: WriteCommand>(dev: &mut dyn Transport, cmd: &C) -> Result<(), EzoError> {
+    let mut cmd_buffer = [0u8; MAX_COMMAND];
+    let len = encode_command(cmd, &mut cmd_buffer)?;
+    if dev.write_bytes(&cmd_buffer[..len]).is_err() {
+        thread::sleep(Duration::from_millis(WRITE_RETRY_DELAY));
+        dev.write_bytes(&cmd_buffer[..len])?;
+    }
+    Ok(())
+}
+
+/// Reads a reply of the given kind from the device, returning its data
+/// string, which is empty unless `reply` is `Reply::Data`.
+pub fn read_reply(dev: &mut dyn Transport, reply: Reply) -> Result<String, EzoError> {
+    if reply == Reply::None {
+        return Ok(String::new());
+    }
+
+    let mut data_buffer = [0u8; MAX_REPLY];
+
+    dev.read_bytes(&mut data_buffer)?;
+
+    decode_reply(&data_buffer, reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{MemoryRecallWithScale, ReadingWithScale, ScaleState, Sleep};
+    use crate::response::{Temperature, TemperatureScale};
+    use i2cdev::mock::MockI2CDevice;
+    use std::collections::VecDeque;
+
+    /// A device that answers each read with the next scripted reply.
+    struct Scripted {
+        written: Vec<Vec<u8>>,
+        replies: VecDeque<&'static [u8]>,
+    }
+
+    impl Transport for Scripted {
+        fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), EzoError> {
+            self.written.push(bytes.to_vec());
+            Ok(())
+        }
+
+        fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(), EzoError> {
+            let reply = self.replies.pop_front().ok_or(ErrorKind::I2CRead)?;
+            buf[..reply.len()].copy_from_slice(reply);
+            Ok(())
+        }
+    }
+
+    /// A device that fails every transaction.
+    struct Unplugged;
+
+    impl Transport for Unplugged {
+        fn write_bytes(&mut self, _bytes: &[u8]) -> Result<(), EzoError> {
+            Err(ErrorKind::UnwritableCommand.into())
+        }
+
+        fn read_bytes(&mut self, _buf: &mut [u8]) -> Result<(), EzoError> {
+            Err(ErrorKind::I2CRead.into())
+        }
+    }
+
+    #[test]
+    fn runs_commands_on_boxed_transports() {
+        // The mock takes the first byte written as a register offset, and
+        // the rest as data; reads continue right after it.
+        let mut dev = MockI2CDevice::new();
+        dev.regmap.write_regs(usize::from(b'S') + 3, b"\x01?S,K\0");
+
+        let mut pool: Vec<Box<dyn Transport>> = vec![Box::new(dev), Box::new(Unplugged)];
+
+        assert_eq!(pool[0].run(&ScaleState).unwrap(), TemperatureScale::Kelvin);
+        let err = pool[1].run(&Sleep).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::UnwritableCommand);
+    }
+
+    #[test]
+    fn runs_reading_with_scale_on_transport() {
+        let mut dev = Scripted {
+            written: Vec::new(),
+            replies: vec![&b"\x01?S,F\0"[..], &b"\x0132.5\0"[..]].into(),
+        };
+
+        {
+            let dev: &mut dyn Transport = &mut dev;
+            assert_eq!(
+                dev.run(&ReadingWithScale).unwrap(),
+                Temperature::Fahrenheit(32.5)
+            );
+        }
+        assert_eq!(dev.written, vec![b"S,?\0".to_vec(), b"R\0".to_vec()]);
+    }
+
+    #[test]
+    fn runs_memory_recall_with_scale_on_transport() {
+        let mut dev = Scripted {
+            written: Vec::new(),
+            replies: vec![&b"\x01?S,K\0"[..], &b"\x014,295.150\0"[..]].into(),
+        };
+
+        {
+            let dev: &mut dyn Transport = &mut dev;
+            assert_eq!(
+                dev.run(&MemoryRecallWithScale).unwrap(),
+                (4, Temperature::Kelvin(295.15))
+            );
+        }
+        assert_eq!(dev.written, vec![b"S,?\0".to_vec(), b"M\0".to_vec()]);
+    }
+
+    #[test]
+    fn traced_device_passes_traffic_to_hook() {
+        let dev = Scripted {
+            written: Vec::new(),
+            replies: vec![&b"\x01?S,C\0"[..]].into(),
+        };
+        let mut traffic = Vec::new();
+        {
+            let mut traced = Traced::new(dev, |t: Traffic<'_>| match t {
+                Traffic::Write(bytes) => traffic.push(bytes.to_vec()),
+                Traffic::Read(buf) => traffic.push(buf[..6].to_vec()),
+            });
+            let dev: &mut dyn Transport = &mut traced;
+            assert_eq!(dev.run(&ScaleState).unwrap(), TemperatureScale::Celsius);
+        }
+        assert_eq!(traffic, vec![b"S,?\0".to_vec(), b"\x01?S,C\0".to_vec()]);
+    }
+}
@@ -0,0 +1,108 @@
+//! Recovery of a sensor from bus hiccups, by reopening its I2C device.
+//!
+//! A `Watchdog` set on an `RtdSensor` counts the commands that fail in a
+//! row. Once they reach its limit, the sensor closes and reopens its I2C
+//! device, so that long-running daemons recover without restarting.
+use super::config::DeviceConfig;
+
+/// Reopens the I2C device of a sensor after too many consecutive failures.
+///
+/// Once reopened, the device can be checked to still be an RTD chip, and
+/// be given back a known configuration, e.g. after the chip lost power.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Watchdog {
+    max_failures: u32,
+    verify_identity: bool,
+    config: Option<DeviceConfig>,
+    failures: u32,
+    reconnects: u32,
+}
+
+impl Watchdog {
+    /// Reopens the device once `max_failures` commands failed in a row,
+    /// and at least once.
+    pub fn new(max_failures: u32) -> Watchdog {
+        Watchdog {
+            max_failures: max_failures.max(1),
+            verify_identity: false,
+            config: None,
+            failures: 0,
+            reconnects: 0,
+        }
+    }
+
+    /// Checks that the reopened device is an RTD chip.
+    pub fn verify_identity(mut self, verify: bool) -> Watchdog {
+        self.verify_identity = verify;
+        self
+    }
+
+    /// Applies `config` to the reopened device.
+    pub fn restore_config(mut self, config: DeviceConfig) -> Watchdog {
+        self.config = Some(config);
+        self
+    }
+
+    /// Returns the number of commands that failed in a row so far.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Returns the number of times the device was reopened successfully.
+    pub fn reconnects(&self) -> u32 {
+        self.reconnects
+    }
+
+    /// Returns `true` if the reopened device is checked to be an RTD chip.
+    pub fn verifies_identity(&self) -> bool {
+        self.verify_identity
+    }
+
+    /// Returns the configuration applied to the reopened device, if any.
+    pub fn config(&self) -> Option<&DeviceConfig> {
+        self.config.as_ref()
+    }
+
+    // Records whether a command succeeded, returning `true` if the device
+    // should be reopened. The count starts over either way, so a device
+    // that could not be reopened is tried again after as many failures.
+    pub(crate) fn record(&mut self, succeeded: bool) -> bool {
+        if succeeded {
+            self.failures = 0;
+            return false;
+        }
+        self.failures += 1;
+        if self.failures < self.max_failures {
+            return false;
+        }
+        self.failures = 0;
+        true
+    }
+
+    // Records that the device was reopened.
+    pub(crate) fn reconnected(&mut self) {
+        self.reconnects += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fires_after_consecutive_failures() {
+        let mut watchdog = Watchdog::new(3);
+        assert!(!watchdog.record(false));
+        assert!(!watchdog.record(false));
+        assert_eq!(watchdog.failures(), 2);
+        assert!(!watchdog.record(true));
+        assert_eq!(watchdog.failures(), 0);
+
+        assert!(!watchdog.record(false));
+        assert!(!watchdog.record(false));
+        assert!(watchdog.record(false));
+        assert_eq!(watchdog.failures(), 0);
+
+        assert!(Watchdog::new(0).record(false));
+    }
+}